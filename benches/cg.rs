@@ -0,0 +1,90 @@
+//! Time per step of the heat equation solver with every conjugate gradient
+//! variant, on the default adapter.
+//!
+//! Run with `cargo bench --bench cg`, optionally followed by `-- N...` to
+//! choose the grid sizes.
+use std::time::Instant;
+
+use heat_wgpu::{
+    conjugate_gradient::CGVariant,
+    heat_equation::{HeatEquation, SolverOptions},
+};
+
+const WARMUP_STEPS: usize = 3;
+const STEPS: usize = 20;
+
+fn main() {
+    let sizes: Vec<usize> = std::env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect();
+    let sizes = match sizes.is_empty() {
+        true => vec![128, 256, 512],
+        false => sizes,
+    };
+
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        ..Default::default()
+    }))
+    .expect("Failed to find an appropriate adapter");
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::default(),
+        },
+        None,
+    ))
+    .expect("Failed to create device");
+    println!("Adapter: {}", adapter.get_info().name);
+    println!(
+        "{:>6} {:>10} {:>12} {:>8}",
+        "n", "variant", "ms/step", "speedup"
+    );
+
+    for n in sizes {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: n as u32,
+                height: n as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+        let u0: Vec<f32> = (0..n * n)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        let mut standard = None;
+        for cg in [CGVariant::Standard, CGVariant::Fused, CGVariant::Pipelined] {
+            let options = SolverOptions {
+                cg,
+                ..SolverOptions::default()
+            };
+            let mut solver =
+                HeatEquation::with_options(&device, 2e-4, n, 0.016, &u0, &texture, options);
+            for _ in 0..WARMUP_STEPS {
+                solver.compute_step(&device, &queue);
+            }
+            device.poll(wgpu::Maintain::Wait);
+            let start = Instant::now();
+            for _ in 0..STEPS {
+                solver.compute_step(&device, &queue);
+            }
+            device.poll(wgpu::Maintain::Wait);
+            let ms = start.elapsed().as_secs_f64() * 1e3 / STEPS as f64;
+            let speedup = *standard.get_or_insert(ms) / ms;
+            println!(
+                "{n:>6} {:>10} {ms:>12.3} {speedup:>7.2}x",
+                format!("{cg:?}")
+            );
+        }
+    }
+}
@@ -1,4 +1,15 @@
-use crate::{heat_equation::HeatEquation, renderer::Renderer};
+use crate::{
+    checkpoint::{Checkpoint, Checkpointer},
+    config::Config,
+    diagnostics::DiagnosticsLog,
+    heat_equation::HeatEquation,
+    iterative_refinement::RefinementLog,
+    output::Outputs,
+    probes::{ProbeLog, ProbeSampler},
+    profiler::{ProfileReport, Profiler},
+    renderer::Renderer,
+};
+use std::{fs::File, io::BufWriter};
 use winit::window::Window;
 
 pub struct App {
@@ -9,11 +20,18 @@ pub struct App {
     size: winit::dpi::PhysicalSize<u32>,
     heat_eqn: HeatEquation,
     renderer: Renderer,
+    outputs: Outputs,
+    checkpointer: Option<Checkpointer>,
+    diagnostics: Option<DiagnosticsLog<BufWriter<File>>>,
+    probes: Option<(ProbeSampler, ProbeLog<BufWriter<File>>)>,
+    refinement: Option<RefinementLog<BufWriter<File>>>,
+    steps_per_frame: usize,
+    profile: Option<ProfileReport>,
 }
 
 impl App {
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: &Window) -> Self {
+    pub async fn new(window: &Window, config: &Config) -> Self {
         let size = window.inner_size();
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
@@ -38,11 +56,22 @@ impl App {
             .unwrap();
         println!("Adapter: {:?}", adapter.get_info());
         println!("Surface: {:?}", surface.get_capabilities(&adapter));
+        let options = config
+            .solver_options(adapter.features())
+            .expect("Failed to load the mask or the materials");
+        println!("Precision: {:?}", options.precision);
+        let profile = config.profile_report();
         //device and queue
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                    features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                        | options.precision.features()
+                        | if profile.is_some() {
+                            Profiler::features(adapter.features())
+                        } else {
+                            wgpu::Features::empty()
+                        },
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web we'll have to disable some.
                     limits: if cfg!(target_arch = "wasm32") {
@@ -56,18 +85,18 @@ impl App {
             )
             .await
             .unwrap();
-        let mut config = surface
+        let mut surface_config = surface
             .get_default_config(&adapter, size.width, size.height)
             .expect("Surface isn't supported by the adapter.");
-        config.present_mode = wgpu::PresentMode::Fifo;
-        config.format = wgpu::TextureFormat::Bgra8Unorm;
-        let surface_view_format = config.format.add_srgb_suffix();
-        config.view_formats.push(surface_view_format);
-        println!("Config: {:?}", config);
-        surface.configure(&device, &config);
+        surface_config.present_mode = wgpu::PresentMode::Fifo;
+        surface_config.format = wgpu::TextureFormat::Bgra8Unorm;
+        let surface_view_format = surface_config.format.add_srgb_suffix();
+        surface_config.view_formats.push(surface_view_format);
+        println!("Config: {:?}", surface_config);
+        surface.configure(&device, &surface_config);
 
         // ------ GPU Compute config ------
-        let n = 512;
+        let n = config.n;
         let width = n;
         let height = n;
         let texture_size = wgpu::Extent3d {
@@ -89,7 +118,14 @@ impl App {
             view_formats: &[],
         });
         // Initialize texture with some data
-        let input_data = generate_input_data(width, height);
+        let mut input_data = config
+            .initial
+            .field(n as usize)
+            .expect("Failed to load the initial condition")
+            .data;
+        if let Some(mask) = &options.mask {
+            mask.apply(&mut input_data);
+        }
         queue.write_texture(
             texture.as_image_copy(),
             bytemuck::cast_slice(input_data.as_slice()),
@@ -102,21 +138,66 @@ impl App {
         );
         let texture_view = &texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        let alpha = 2e-4;
-        let dt = 0.016;
-
-        let compute = HeatEquation::new(&device, alpha, n as usize, dt, &input_data, &texture);
-        let renderer = Renderer::new(&device, &config, texture_view);
+        let mut compute = HeatEquation::with_options(
+            &device,
+            config.alpha,
+            n as usize,
+            config.dt,
+            &input_data,
+            &texture,
+            options,
+        );
+        if let Some(path) = &config.restart {
+            let checkpoint = Checkpoint::load(path).expect("Failed to read the checkpoint");
+            compute
+                .restore(&device, &queue, &checkpoint)
+                .expect("Failed to restore the checkpoint");
+        }
+        compute.profile(&device, &queue, profile.as_ref());
+        let renderer = Renderer::new(
+            &device,
+            &queue,
+            &surface_config,
+            texture_view,
+            compute.mask(),
+        );
+        let mut outputs = config.outputs().expect("Failed to create the output files");
+        outputs
+            .record(&compute, (&device, &queue))
+            .expect("Failed to record the initial condition");
+        let mut diagnostics = config
+            .diagnostics_log()
+            .expect("Failed to create the diagnostics log");
+        if let Some(log) = &mut diagnostics {
+            compute.track_diagnostics(&device, &queue, true);
+            log.write(compute.last_diagnostics().unwrap())
+                .expect("Failed to write the diagnostics");
+        }
+        let probes = config
+            .probe_log()
+            .expect("Failed to create the probe log")
+            .map(|log| (ProbeSampler::new(&device, &compute, &config.probes), log));
 
-        Self {
+        let mut app = Self {
             surface,
             device,
             queue,
-            config,
+            config: surface_config,
             size,
             heat_eqn: compute,
             renderer,
-        }
+            outputs,
+            checkpointer: config.checkpointer(),
+            diagnostics,
+            probes,
+            refinement: config
+                .refinement_log()
+                .expect("Failed to create the refinement log"),
+            steps_per_frame: config.steps_per_frame,
+            profile,
+        };
+        app.sample_probes();
+        app
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -136,39 +217,89 @@ impl App {
         self.resize(new_size);
     }
 
+    /// Advances the solution by the steps of a frame, in as few submissions
+    /// as the outputs, checkpoints and logs allow.
     pub fn update(&mut self) {
-        self.heat_eqn.compute_step(&self.device, &self.queue);
+        // the logs sample every step, the outputs and checkpoints the steps
+        // they are due at
+        let per_step =
+            self.diagnostics.is_some() || self.probes.is_some() || self.refinement.is_some();
+        let mut remaining = self.steps_per_frame;
+        while remaining > 0 {
+            let iteration = self.heat_eqn.iteration();
+            let steps = [
+                Some(if per_step { 1 } else { remaining }),
+                self.outputs.steps_until_due(iteration),
+                self.checkpointer
+                    .as_ref()
+                    .map(|c| c.steps_until_due(iteration)),
+            ]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap();
+            self.heat_eqn
+                .compute_steps(&self.device, &self.queue, steps);
+            remaining -= steps;
+            self.record_step();
+        }
     }
 
-    pub fn render(&self, window: &Window) -> Result<(), wgpu::SurfaceError> {
-        self.renderer
-            .render(window, &self.device, &self.surface, &self.queue)
+    /// Records the current step to the outputs, checkpoint and logs.
+    fn record_step(&mut self) {
+        self.outputs
+            .record(&self.heat_eqn, (&self.device, &self.queue))
+            .expect("Failed to record the solution");
+        if let Some(checkpointer) = &self.checkpointer {
+            checkpointer
+                .update(&self.heat_eqn, &self.device, &self.queue)
+                .expect("Failed to save the checkpoint");
+        }
+        if let Some(log) = &mut self.diagnostics {
+            log.write(self.heat_eqn.last_diagnostics().unwrap())
+                .expect("Failed to write the diagnostics");
+        }
+        self.sample_probes();
+        if let (Some(log), Some(report)) = (&mut self.refinement, self.heat_eqn.refinement_report())
+        {
+            log.write(self.heat_eqn.iteration(), report)
+                .expect("Failed to write the refinement log");
+        }
     }
-}
 
-fn gaussian(x: f32) -> f32 {
-    const SIGMA: f32 = 0.5;
-    const SIGMA2: f32 = SIGMA * SIGMA;
-    const ROOT_2PI: f32 = 2.5066283;
-    (1.0 / (ROOT_2PI * SIGMA)) * (-x * x / (2.0 * SIGMA2)).exp()
-}
+    fn sample_probes(&mut self) {
+        if let Some((sampler, log)) = &mut self.probes {
+            let values = sampler
+                .sample(&self.device, &self.queue, &self.heat_eqn)
+                .expect("Failed to read the probe samples");
+            let step = self.heat_eqn.iteration();
+            log.write(step, step as f64 * self.heat_eqn.dt() as f64, &values)
+                .expect("Failed to write the probe samples");
+        }
+    }
 
-fn generate_input_data(width: u32, height: u32) -> Vec<f32> {
-    use noise::{NoiseFn, Perlin};
-    let mut data = vec![0.0; (width * height) as usize];
-    const WIDTH: f32 = 1.0;
-    const HEIGHT: f32 = 1.0;
-    let perlin = Perlin::new(1);
-    for i in 0..width {
-        for j in 0..height {
-            let x = (i as f32 / width as f32) * WIDTH;
-            let y = (j as f32 / height as f32) * HEIGHT;
-            let x = x - WIDTH / 2.0;
-            let y = y - HEIGHT / 2.0;
-            let r = (x * x + y * y).sqrt();
-            let noise = perlin.get([x as f64 * 10.0, y as f64 * 10.0, 0.0]) as f32;
-            data[(i + j * width) as usize] = gaussian(r) + noise;
+    /// Flushes the recorded outputs and reports the profile. Must be called
+    /// before exiting.
+    pub fn finish(&mut self) {
+        self.outputs
+            .finish()
+            .expect("Failed to finish writing the outputs");
+        if let Some(log) = &mut self.diagnostics {
+            log.flush().expect("Failed to write the diagnostics");
+        }
+        if let Some((_, log)) = &mut self.probes {
+            log.flush().expect("Failed to write the probe samples");
         }
+        if let Some(log) = &mut self.refinement {
+            log.flush().expect("Failed to write the refinement log");
+        }
+        if let (Some(report), Some(profiler)) = (&self.profile, self.heat_eqn.profiler()) {
+            report.write(profiler).expect("Failed to write the profile");
+        }
+    }
+
+    pub fn render(&self, window: &Window) -> Result<(), wgpu::SurfaceError> {
+        self.renderer
+            .render(window, &self.device, &self.surface, &self.queue)
     }
-    data
 }
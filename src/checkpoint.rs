@@ -0,0 +1,543 @@
+//! Saving the state of a run to disk and resuming it later.
+//!
+//! A checkpoint is a little-endian binary file:
+//!
+//! | bytes     | contents                                      |
+//! | --------- | --------------------------------------------- |
+//! | 8         | magic, `HEATCKPT`                             |
+//! | 4         | format version, currently 4                   |
+//! | 4         | time stepping scheme, see [`Scheme`]          |
+//! | 4         | boundary condition, see [`BoundaryCondition`] |
+//! | 4         | solver precision: 0 single, 1 double, 2 double-float |
+//! | 4         | `n`                                           |
+//! | 4, 4      | `alpha`, `dt`                                 |
+//! | 8         | mask fingerprint, see [`fingerprint`], 0 without a mask |
+//! | 8         | material map fingerprint, 0 without materials |
+//! | 8         | iteration                                     |
+//! | 8         | simulation time, as `f64`                     |
+//! | `s n²`    | the field in row-major order, as `f32` (`s = 4`) in single precision and `f64` (`s = 8`) otherwise |
+//! | `s n²`    | the previous field, in the same layout        |
+//!
+//! The fields are saved in the precision of the solver, so that a resumed
+//! double precision run is not rounded to `f32`. Double-float values are
+//! saved as their exact sum in `f64`, which may split into a different pair
+//! of `f32` when restored: such runs resume to within `f64` rounding rather
+//! than bit for bit.
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{heat_equation::HeatEquation, precision::Precision};
+
+const MAGIC: &[u8; 8] = b"HEATCKPT";
+const VERSION: u32 = 4;
+
+/// Time stepping scheme the checkpointed run was using.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    CrankNicolson,
+}
+
+/// Boundary condition on the edges of the square.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryCondition {
+    /// Zero temperature on the points just outside the grid.
+    ZeroDirichlet,
+}
+
+impl Scheme {
+    fn tag(self) -> u32 {
+        match self {
+            Scheme::CrankNicolson => 0,
+        }
+    }
+
+    fn from_tag(tag: u32) -> Option<Self> {
+        match tag {
+            0 => Some(Scheme::CrankNicolson),
+            _ => None,
+        }
+    }
+}
+
+impl BoundaryCondition {
+    fn tag(self) -> u32 {
+        match self {
+            BoundaryCondition::ZeroDirichlet => 0,
+        }
+    }
+
+    fn from_tag(tag: u32) -> Option<Self> {
+        match tag {
+            0 => Some(BoundaryCondition::ZeroDirichlet),
+            _ => None,
+        }
+    }
+}
+
+fn precision_tag(precision: Precision) -> u32 {
+    match precision {
+        Precision::Single => 0,
+        Precision::Double => 1,
+        Precision::DoubleFloat => 2,
+    }
+}
+
+fn precision_from_tag(tag: u32) -> Option<Precision> {
+    match tag {
+        0 => Some(Precision::Single),
+        1 => Some(Precision::Double),
+        2 => Some(Precision::DoubleFloat),
+        _ => None,
+    }
+}
+
+/// FNV-1a hash of `bytes`, which identifies a mask or material map in a
+/// checkpoint without saving it whole.
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// The file is not a checkpoint, or is corrupted.
+    InvalidFormat(String),
+    /// The checkpoint was written by a different version of the format.
+    UnsupportedVersion(u32),
+    /// The checkpoint does not belong to a run with the same grid, scheme or
+    /// parameters as the solver it is restored into.
+    Mismatch(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "{e}"),
+            CheckpointError::InvalidFormat(msg) => write!(f, "invalid checkpoint: {msg}"),
+            CheckpointError::UnsupportedVersion(version) => {
+                write!(f, "unsupported checkpoint version {version}")
+            }
+            CheckpointError::Mismatch(msg) => write!(f, "checkpoint does not match: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e: io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+/// Settings a solver must share with a checkpointed run to resume it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameters {
+    pub scheme: Scheme,
+    pub boundary: BoundaryCondition,
+    /// Precision of the solver, which the fields are saved in.
+    pub precision: Precision,
+    pub n: usize,
+    pub alpha: f32,
+    pub dt: f32,
+    /// [`fingerprint`] of the mask labels, or 0 without a mask.
+    pub mask: u64,
+    /// [`MaterialMap::fingerprint`](crate::material::MaterialMap::fingerprint),
+    /// or 0 without materials.
+    pub materials: u64,
+}
+
+/// Everything needed to resume a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub parameters: Parameters,
+    /// Number of time steps computed so far. Its parity decides which of the
+    /// solver's two buffers holds the solution.
+    pub iteration: usize,
+    pub time: f64,
+    /// Solution, `n * n` values in row-major order.
+    pub field: Vec<f64>,
+    /// Solution of the step before, which the conjugate gradient solver uses
+    /// as its initial guess. Restoring it makes a resumed run identical to an
+    /// uninterrupted one.
+    pub previous: Vec<f64>,
+}
+
+impl Checkpoint {
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let p = &self.parameters;
+        let n = u32::try_from(p.n)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "grid too large"))?;
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&p.scheme.tag().to_le_bytes())?;
+        w.write_all(&p.boundary.tag().to_le_bytes())?;
+        w.write_all(&precision_tag(p.precision).to_le_bytes())?;
+        w.write_all(&n.to_le_bytes())?;
+        w.write_all(&p.alpha.to_le_bytes())?;
+        w.write_all(&p.dt.to_le_bytes())?;
+        w.write_all(&p.mask.to_le_bytes())?;
+        w.write_all(&p.materials.to_le_bytes())?;
+        w.write_all(&(self.iteration as u64).to_le_bytes())?;
+        w.write_all(&self.time.to_le_bytes())?;
+        assert!(
+            self.field.len() == p.n * p.n && self.previous.len() == p.n * p.n,
+            "both fields must have n * n values"
+        );
+        for &value in self.field.iter().chain(self.previous.iter()) {
+            match p.precision {
+                Precision::Single => w.write_all(&(value as f32).to_le_bytes())?,
+                Precision::Double | Precision::DoubleFloat => w.write_all(&value.to_le_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> Result<Self, CheckpointError> {
+        let invalid = |msg: &str| CheckpointError::InvalidFormat(msg.to_string());
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+        let version = read_u32(r)?;
+        if version != VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        let scheme = Scheme::from_tag(read_u32(r)?).ok_or_else(|| invalid("unknown scheme"))?;
+        let boundary = BoundaryCondition::from_tag(read_u32(r)?)
+            .ok_or_else(|| invalid("unknown boundary condition"))?;
+        let precision =
+            precision_from_tag(read_u32(r)?).ok_or_else(|| invalid("unknown precision"))?;
+        let n = read_u32(r)? as usize;
+        let alpha = f32::from_bits(read_u32(r)?);
+        let dt = f32::from_bits(read_u32(r)?);
+        let mask = read_u64(r)?;
+        let materials = read_u64(r)?;
+        let iteration = read_u64(r)? as usize;
+        let time = f64::from_bits(read_u64(r)?);
+        let len = n
+            .checked_mul(n)
+            .and_then(|points| points.checked_mul(precision.size()))
+            .ok_or_else(|| invalid("grid too large"))?;
+        let mut read_field = || {
+            // n comes from the file, so the buffer only grows with the data
+            // actually read rather than being allocated up front
+            let mut bytes = Vec::new();
+            r.take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() != len {
+                return Err(invalid("truncated field data"));
+            }
+            let data = match precision {
+                Precision::Single => bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+                    .collect(),
+                Precision::Double | Precision::DoubleFloat => bytes
+                    .chunks_exact(8)
+                    .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                    .collect(),
+            };
+            Ok::<_, CheckpointError>(data)
+        };
+        let field = read_field()?;
+        let previous = read_field()?;
+        Ok(Self {
+            parameters: Parameters {
+                scheme,
+                boundary,
+                precision,
+                n,
+                alpha,
+                dt,
+                mask,
+                materials,
+            },
+            iteration,
+            time,
+            field,
+            previous,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CheckpointError> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Checks that this checkpoint can be restored into a solver with the
+    /// given parameters.
+    ///
+    /// Native and emulated double precision both save `f64` values, so
+    /// either can resume the other, but single and double precision runs
+    /// cannot resume each other.
+    pub fn check(&self, solver: &Parameters) -> Result<(), CheckpointError> {
+        let saved = &self.parameters;
+        let mismatch = |what: &str, found: String, expected: String| {
+            Err(CheckpointError::Mismatch(format!(
+                "{what} is {found} in the checkpoint but {expected} in the solver"
+            )))
+        };
+        if saved.n != solver.n {
+            return mismatch("grid size", saved.n.to_string(), solver.n.to_string());
+        }
+        if saved.scheme != solver.scheme {
+            return mismatch(
+                "scheme",
+                format!("{:?}", saved.scheme),
+                format!("{:?}", solver.scheme),
+            );
+        }
+        if saved.boundary != solver.boundary {
+            return mismatch(
+                "boundary condition",
+                format!("{:?}", saved.boundary),
+                format!("{:?}", solver.boundary),
+            );
+        }
+        if saved.precision.size() != solver.precision.size() {
+            return mismatch(
+                "precision",
+                format!("{:?}", saved.precision),
+                format!("{:?}", solver.precision),
+            );
+        }
+        if saved.alpha != solver.alpha {
+            return mismatch("alpha", saved.alpha.to_string(), solver.alpha.to_string());
+        }
+        if saved.dt != solver.dt {
+            return mismatch("dt", saved.dt.to_string(), solver.dt.to_string());
+        }
+        let describe = |fingerprint: u64| match fingerprint {
+            0 => "absent".to_string(),
+            fingerprint => format!("{fingerprint:016x}"),
+        };
+        if saved.mask != solver.mask {
+            return mismatch("mask", describe(saved.mask), describe(solver.mask));
+        }
+        if saved.materials != solver.materials {
+            return mismatch(
+                "material map",
+                describe(saved.materials),
+                describe(solver.materials),
+            );
+        }
+        Ok(())
+    }
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Keeps a checkpoint of the run at `path`, updated every `interval` steps.
+///
+/// The file is written next to `path` first and then renamed, so that an
+/// interrupted run always leaves a complete checkpoint behind.
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: usize,
+}
+
+impl Checkpointer {
+    pub fn new(path: impl Into<PathBuf>, interval: usize) -> Self {
+        assert!(
+            interval > 0,
+            "checkpoint interval must be at least one step"
+        );
+        Self {
+            path: path.into(),
+            interval,
+        }
+    }
+
+    /// Number of time steps from `step` until the next checkpoint.
+    pub fn steps_until_due(&self, step: usize) -> usize {
+        self.interval - step % self.interval
+    }
+
+    /// Saves a checkpoint if the current iteration is a multiple of the interval.
+    pub fn update(
+        &self,
+        heat_eqn: &HeatEquation,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> io::Result<()> {
+        if !heat_eqn.iteration().is_multiple_of(self.interval) {
+            return Ok(());
+        }
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        heat_eqn
+            .checkpoint(device, queue)
+            .map_err(io::Error::other)?
+            .save(&partial)?;
+        std::fs::rename(partial, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters() -> Parameters {
+        Parameters {
+            scheme: Scheme::CrankNicolson,
+            boundary: BoundaryCondition::ZeroDirichlet,
+            precision: Precision::Single,
+            n: 2,
+            alpha: 2e-4,
+            dt: 0.016,
+            mask: 0,
+            materials: 0,
+        }
+    }
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            parameters: parameters(),
+            iteration: 7,
+            time: 0.112,
+            field: vec![1.0, -2.0, 3.5, 0.0],
+            previous: vec![1.5, -2.5, 3.0, 0.5],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut bytes = Vec::new();
+        checkpoint().write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 68 + 2 * 4 * 4);
+        assert_eq!(
+            Checkpoint::read(&mut bytes.as_slice()).unwrap(),
+            checkpoint()
+        );
+
+        // double precision fields are not rounded to f32
+        let double = Checkpoint {
+            parameters: Parameters {
+                precision: Precision::DoubleFloat,
+                mask: fingerprint(&[0, 255, 0, 0]),
+                materials: fingerprint(b"copper"),
+                ..parameters()
+            },
+            field: vec![0.1, 1.0 + 1e-12, -3.0, 2.0 / 3.0],
+            ..checkpoint()
+        };
+        let mut bytes = Vec::new();
+        double.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 68 + 2 * 4 * 8);
+        assert_eq!(Checkpoint::read(&mut bytes.as_slice()).unwrap(), double);
+    }
+
+    #[test]
+    fn steps_until_due() {
+        let checkpointer = Checkpointer::new("heat.ckpt", 4);
+        assert_eq!(checkpointer.steps_until_due(0), 4);
+        assert_eq!(checkpointer.steps_until_due(3), 1);
+        assert_eq!(checkpointer.steps_until_due(4), 4);
+        assert_eq!(checkpointer.steps_until_due(6), 2);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let mut bytes = Vec::new();
+        checkpoint().write(&mut bytes).unwrap();
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] = 1;
+        assert!(matches!(
+            Checkpoint::read(&mut wrong_version.as_slice()),
+            Err(CheckpointError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            Checkpoint::read(&mut &bytes[..bytes.len() - 1]),
+            Err(CheckpointError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            Checkpoint::read(&mut &b"not a checkpoint"[..]),
+            Err(CheckpointError::InvalidFormat(_))
+        ));
+        // a huge grid size must not allocate before the data runs out
+        let mut huge = bytes.clone();
+        huge[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Checkpoint::read(&mut huge.as_slice()),
+            Err(CheckpointError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn checks_parameters() {
+        let c = checkpoint();
+        assert!(c.check(&parameters()).is_ok());
+        assert!(matches!(
+            c.check(&Parameters {
+                n: 4,
+                ..parameters()
+            }),
+            Err(CheckpointError::Mismatch(_))
+        ));
+        assert!(c
+            .check(&Parameters {
+                dt: 0.01,
+                ..parameters()
+            })
+            .is_err());
+        let double_float = Parameters {
+            precision: Precision::DoubleFloat,
+            ..parameters()
+        };
+        assert!(c.check(&double_float).is_err());
+        let double = Checkpoint {
+            parameters: Parameters {
+                precision: Precision::Double,
+                ..parameters()
+            },
+            ..checkpoint()
+        };
+        assert!(double.check(&double_float).is_ok());
+
+        let walls = fingerprint(&[0, 255, 0, 0]);
+        assert_ne!(walls, fingerprint(&[0, 0, 255, 0]));
+        let masked = Checkpoint {
+            parameters: Parameters {
+                mask: walls,
+                ..parameters()
+            },
+            ..checkpoint()
+        };
+        assert!(masked
+            .check(&Parameters {
+                mask: walls,
+                ..parameters()
+            })
+            .is_ok());
+        assert!(masked.check(&parameters()).is_err());
+        assert!(c
+            .check(&Parameters {
+                mask: walls,
+                ..parameters()
+            })
+            .is_err());
+    }
+}
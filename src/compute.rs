@@ -130,8 +130,8 @@ fn compute_work_group_count(
     (width, height): (u32, u32),
     (workgroup_width, workgroup_height): (u32, u32),
 ) -> (u32, u32) {
-    let x = (width + workgroup_width - 1) / workgroup_width;
-    let y = (height + workgroup_height - 1) / workgroup_height;
+    let x = width.div_ceil(workgroup_width);
+    let y = height.div_ceil(workgroup_height);
 
     (x, y)
 }
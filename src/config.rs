@@ -0,0 +1,593 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use crate::{
+    checkpoint::Checkpointer,
+    conjugate_gradient::CGVariant,
+    diagnostics::DiagnosticsLog,
+    heat_equation::{HeatEquation, SolverOptions},
+    initial_condition::InitialCondition,
+    iterative_refinement::{RefinementLog, RefinementOptions},
+    kernels::dot::Summation,
+    mask::{Mask, MaskSource},
+    material::{self, MaterialMap},
+    matrix_market,
+    output::{
+        image::{self, ImageRecorder},
+        npy::NpyRecorder,
+        vtk::{self, Geometry, VtkRecorder},
+        Outputs,
+    },
+    precision::Precision,
+    probes::{ProbeLog, Probes},
+    profiler::ProfileReport,
+};
+
+pub const USAGE: &str = "\
+Usage: heat-wgpu [OPTIONS]
+
+Options:
+  --n <N>            grid points along each axis [default: 512]
+  --alpha <ALPHA>    thermal diffusivity [default: 2e-4]
+  --dt <DT>          time step [default: 0.016]
+  --initial <SPEC>   initial condition: a + separated sum of gaussian, perlin,
+                     step, disk, rectangle, eigenmode or uniform, each with
+                     optional parameters as in gaussian:x=0.3,sigma=0.1, or the
+                     path of a grayscale PNG or TIFF image, a .npy array or a
+                     CSV file, resampled to the grid
+  --initial-range <MIN,MAX>
+                     temperatures of black and white pixels [default: 0,1]
+  --headless         run without opening a window
+  --steps <STEPS>    number of time steps to run in headless mode [default: 1000]
+  --steps-per-frame <K>
+                     time steps per rendered frame, or per submission in
+                     headless mode, recorded into a single submission; outputs
+                     and checkpoints still get the steps they are due at, but
+                     diagnostics, probes and the refinement log need a
+                     submission per step [default: 1]
+  --npy <PATH>       record the field as NumPy arrays: numbered .npy files inside
+                     the directory PATH, or a single archive if PATH ends in .npz
+  --vtk <DIR>        record the field as VTK files inside DIR, with a u.pvd
+                     collection to open the time series in ParaView
+  --vtk-format <F>   xml (.vti) or legacy (.vtk) [default: xml]
+  --images <DIR>     record the field as a numbered image sequence inside DIR
+  --image-format <F> png (colormapped), exr or tiff (32-bit float) [default: png]
+  --every <K>        record every K time steps [default: 1]
+  --checkpoint <PATH>
+                     keep a checkpoint of the run at PATH, updated every
+                     --checkpoint-every steps [default: 100]
+  --restart <PATH>   resume the run saved in a checkpoint, which must use the
+                     same --n, --alpha and --dt
+  --diagnostics <PATH>
+                     write the total heat, min, max, mean and L2 norm of the
+                     field after every time step to the CSV file PATH
+  --probe <NAME=X,Y> sample the temperature at the point (X, Y) of the unit
+                     square; can be repeated
+  --line <NAME=X0,Y0,X1,Y1,SAMPLES>
+                     sample the temperature at SAMPLES points from (X0, Y0)
+                     to (X1, Y1); can be repeated
+  --probes <PATH>    write the probe and line samples after every time step to
+                     the CSV file PATH
+  --summation <S>    accumulation of the solver dot products: plain (f32) or
+                     compensated (double-float) [default: plain]
+  --cg <VARIANT>     conjugate gradient iteration: standard, fused (dot
+                     products computed by the SpMV and vector updates) or
+                     pipelined (Chronopoulos-Gear, one reduction per
+                     iteration); only standard supports compensated sums
+                     [default: standard]
+  --precision <P>    solver precision: single, double (native f64 when the
+                     adapter supports it, emulated otherwise) or double-float
+                     (always emulated) [default: single]
+  --mask <SPEC>      solve over part of the grid only: l-shape, hole with
+                     optional parameters as in hole:x=0.3,radius=0.1,insulated=1,
+                     or the path of an image whose white pixels are the domain,
+                     gray pixels insulated walls and black pixels walls held at
+                     zero
+  --materials <TABLE>
+                     solve rho c du/dt = div(k grad u) instead, with the
+                     materials listed in the CSV file TABLE as
+                     name,density,specific_heat,conductivity[,#rrggbb];
+                     --alpha is then unused
+  --material-map <PATH>
+                     material of every grid point: an image whose colors are
+                     those of the table, or a .npy, CSV or TIFF file of
+                     material indices, resampled to the grid
+  --matrix-free      apply the matrices of the solver as 5-point stencils
+                     instead of storing their diagonals
+  --refine <TOL>     solve each step by mixed-precision iterative refinement:
+                     single precision CG corrections until the relative
+                     residual is below TOL; needs --precision double or
+                     double-float
+  --refinement-log <PATH>
+                     write the number of refinement iterations and the
+                     residuals of every time step to the CSV file PATH
+  --export-matrices <DIR>
+                     write the matrices A and B of the Crank-Nicolson system
+                     A u_new = B u_old to DIR/a.mtx and DIR/b.mtx, in Matrix
+                     Market format
+  --profile          time the kernels of every step with GPU timestamp
+                     queries, or whole steps on the host without them, and
+                     print a summary at the end of the run
+  --profile-trace <PATH>
+                     profile the run and write its spans to PATH, in the
+                     Chrome trace event format
+  -h, --help         print this message
+";
+
+/// Run configuration, read from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub n: u32,
+    pub alpha: f32,
+    pub dt: f32,
+    pub initial: InitialCondition,
+    pub headless: bool,
+    pub steps: usize,
+    pub steps_per_frame: usize,
+    pub npy: Option<PathBuf>,
+    pub vtk: Option<PathBuf>,
+    pub vtk_format: vtk::Format,
+    pub images: Option<PathBuf>,
+    pub image_format: image::Format,
+    pub output_interval: usize,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: usize,
+    pub restart: Option<PathBuf>,
+    pub diagnostics: Option<PathBuf>,
+    pub probes: Probes,
+    pub probe_output: Option<PathBuf>,
+    pub summation: Summation,
+    pub cg: CGVariant,
+    pub precision: Precision,
+    pub mask: Option<MaskSource>,
+    pub materials: Option<PathBuf>,
+    pub material_map: Option<PathBuf>,
+    pub matrix_free: bool,
+    pub refinement: Option<RefinementOptions>,
+    pub refinement_log: Option<PathBuf>,
+    pub export_matrices: Option<PathBuf>,
+    pub profile: bool,
+    pub profile_trace: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            n: 512,
+            alpha: 2e-4,
+            dt: 0.016,
+            initial: InitialCondition::default(),
+            headless: false,
+            steps: 1000,
+            steps_per_frame: 1,
+            npy: None,
+            vtk: None,
+            vtk_format: vtk::Format::Xml,
+            images: None,
+            image_format: image::Format::Png,
+            output_interval: 1,
+            checkpoint: None,
+            checkpoint_interval: 100,
+            restart: None,
+            diagnostics: None,
+            probes: Probes::default(),
+            probe_output: None,
+            summation: Summation::Plain,
+            cg: CGVariant::Standard,
+            precision: Precision::Single,
+            mask: None,
+            materials: None,
+            material_map: None,
+            matrix_free: false,
+            refinement: None,
+            refinement_log: None,
+            export_matrices: None,
+            profile: false,
+            profile_trace: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses the command line arguments, without the program name.
+    ///
+    /// Returns `Ok(None)` when the usage message was requested.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        let mut initial_range = [0.0, 1.0];
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--n" => config.n = parse(&arg, value()?)?,
+                "--alpha" => config.alpha = parse(&arg, value()?)?,
+                "--dt" => config.dt = parse(&arg, value()?)?,
+                "--initial" => config.initial = InitialCondition::parse(&value()?, initial_range)?,
+                "--initial-range" => {
+                    let range = value()?;
+                    initial_range = match range.split_once(',') {
+                        Some((min, max)) => [parse(&arg, min.into())?, parse(&arg, max.into())?],
+                        None => return Err(format!("expected MIN,MAX for {arg}, found {range:?}")),
+                    }
+                }
+                "--headless" => config.headless = true,
+                "--steps" => config.steps = parse(&arg, value()?)?,
+                "--steps-per-frame" => config.steps_per_frame = parse(&arg, value()?)?,
+                "--npy" => config.npy = Some(PathBuf::from(value()?)),
+                "--vtk" => config.vtk = Some(PathBuf::from(value()?)),
+                "--vtk-format" => {
+                    config.vtk_format = match value()?.as_str() {
+                        "xml" => vtk::Format::Xml,
+                        "legacy" => vtk::Format::Legacy,
+                        other => return Err(format!("unknown VTK format {other:?}")),
+                    }
+                }
+                "--images" => config.images = Some(PathBuf::from(value()?)),
+                "--image-format" => {
+                    config.image_format = match value()?.as_str() {
+                        "png" => image::Format::Png,
+                        "exr" => image::Format::Exr,
+                        "tiff" => image::Format::Tiff,
+                        other => return Err(format!("unknown image format {other:?}")),
+                    }
+                }
+                "--every" => config.output_interval = parse(&arg, value()?)?,
+                "--checkpoint" => config.checkpoint = Some(PathBuf::from(value()?)),
+                "--checkpoint-every" => config.checkpoint_interval = parse(&arg, value()?)?,
+                "--restart" => config.restart = Some(PathBuf::from(value()?)),
+                "--diagnostics" => config.diagnostics = Some(PathBuf::from(value()?)),
+                "--probe" => config.probes.points.push(value()?.parse()?),
+                "--line" => config.probes.lines.push(value()?.parse()?),
+                "--probes" => config.probe_output = Some(PathBuf::from(value()?)),
+                "--summation" => {
+                    config.summation = match value()?.as_str() {
+                        "plain" => Summation::Plain,
+                        "compensated" => Summation::Compensated,
+                        other => return Err(format!("unknown summation {other:?}")),
+                    }
+                }
+                "--cg" => {
+                    config.cg = match value()?.as_str() {
+                        "standard" => CGVariant::Standard,
+                        "fused" => CGVariant::Fused,
+                        "pipelined" => CGVariant::Pipelined,
+                        other => return Err(format!("unknown CG variant {other:?}")),
+                    }
+                }
+                "--precision" => {
+                    config.precision = match value()?.as_str() {
+                        "single" => Precision::Single,
+                        "double" => Precision::Double,
+                        "double-float" => Precision::DoubleFloat,
+                        other => return Err(format!("unknown precision {other:?}")),
+                    }
+                }
+                "--mask" => config.mask = Some(value()?.parse()?),
+                "--materials" => config.materials = Some(PathBuf::from(value()?)),
+                "--material-map" => config.material_map = Some(PathBuf::from(value()?)),
+                "--matrix-free" => config.matrix_free = true,
+                "--refine" => {
+                    config.refinement = Some(RefinementOptions {
+                        tolerance: parse(&arg, value()?)?,
+                        ..RefinementOptions::default()
+                    })
+                }
+                "--refinement-log" => config.refinement_log = Some(PathBuf::from(value()?)),
+                "--export-matrices" => config.export_matrices = Some(PathBuf::from(value()?)),
+                "--profile" => config.profile = true,
+                "--profile-trace" => config.profile_trace = Some(PathBuf::from(value()?)),
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+        if let InitialCondition::File { range, .. } = &mut config.initial {
+            *range = initial_range;
+        }
+        if config.n == 0 {
+            return Err("--n must be positive".to_string());
+        }
+        if config.steps_per_frame == 0 {
+            return Err("--steps-per-frame must be positive".to_string());
+        }
+        if config.output_interval == 0 {
+            return Err("--every must be positive".to_string());
+        }
+        if config.checkpoint_interval == 0 {
+            return Err("--checkpoint-every must be positive".to_string());
+        }
+        if config.probes.is_empty() != config.probe_output.is_none() {
+            return Err(
+                "--probes needs at least one --probe or --line, and vice versa".to_string(),
+            );
+        }
+        if config.refinement.is_some() && config.precision == Precision::Single {
+            return Err("--refine needs --precision double or double-float".to_string());
+        }
+        if config.refinement_log.is_some() && config.refinement.is_none() {
+            return Err("--refinement-log needs --refine".to_string());
+        }
+        if config.summation == Summation::Compensated && config.cg != CGVariant::Standard {
+            return Err("--summation compensated needs --cg standard".to_string());
+        }
+        if config.mask.is_some() && config.matrix_free {
+            return Err("--mask cannot be combined with --matrix-free".to_string());
+        }
+        if config.materials.is_some() != config.material_map.is_some() {
+            return Err("--materials and --material-map go together".to_string());
+        }
+        if config.materials.is_some() && config.matrix_free {
+            return Err("--materials cannot be combined with --matrix-free".to_string());
+        }
+        Ok(Some(config))
+    }
+
+    /// Creates the recorders requested by this configuration.
+    pub fn outputs(&self) -> io::Result<Outputs> {
+        let mut outputs = Outputs::new();
+        if let Some(path) = &self.npy {
+            let recorder = if path.extension().is_some_and(|ext| ext == "npz") {
+                NpyRecorder::npz(path, self.output_interval)?
+            } else {
+                NpyRecorder::files(path, "u", self.output_interval)?
+            };
+            outputs.push(Box::new(recorder));
+        }
+        if let Some(directory) = &self.vtk {
+            let n = self.n as usize;
+            let h = HeatEquation::grid_spacing(n);
+            let x0 = HeatEquation::grid_coordinate(0, n);
+            let geometry = Geometry {
+                origin: [x0, x0],
+                spacing: [h, h],
+            };
+            outputs.push(Box::new(VtkRecorder::new(
+                directory,
+                "u",
+                self.vtk_format,
+                geometry,
+                self.output_interval,
+            )?));
+        }
+        if let Some(directory) = &self.images {
+            outputs.push(Box::new(ImageRecorder::new(
+                directory,
+                "u",
+                self.image_format,
+                self.output_interval,
+            )?));
+        }
+        Ok(outputs)
+    }
+
+    /// Options of the GPU solver requested by this configuration, on an
+    /// adapter with `features`. Fails if the mask or the materials cannot be
+    /// loaded.
+    pub fn solver_options(&self, features: wgpu::Features) -> io::Result<SolverOptions> {
+        Ok(SolverOptions {
+            summation: self.summation,
+            cg: self.cg,
+            precision: self.precision.supported(features),
+            refinement: self.refinement,
+            matrix_free: self.matrix_free,
+            mask: self.mask()?,
+            materials: self.materials()?,
+        })
+    }
+
+    /// Evaluates the mask requested by this configuration on the grid, if any.
+    pub fn mask(&self) -> io::Result<Option<Mask>> {
+        self.mask
+            .as_ref()
+            .map(|source| source.mask(self.n as usize))
+            .transpose()
+    }
+
+    /// Creates the checkpointer requested by this configuration, if any.
+    pub fn checkpointer(&self) -> Option<Checkpointer> {
+        self.checkpoint
+            .as_ref()
+            .map(|path| Checkpointer::new(path, self.checkpoint_interval))
+    }
+
+    /// Creates the diagnostics log requested by this configuration, if any.
+    pub fn diagnostics_log(&self) -> io::Result<Option<DiagnosticsLog<BufWriter<File>>>> {
+        self.diagnostics
+            .as_ref()
+            .map(DiagnosticsLog::create)
+            .transpose()
+    }
+
+    /// Loads the material map requested by this configuration onto the grid,
+    /// if any.
+    pub fn materials(&self) -> io::Result<Option<MaterialMap>> {
+        let (Some(table), Some(map)) = (&self.materials, &self.material_map) else {
+            return Ok(None);
+        };
+        let materials = material::load_table(table)?;
+        MaterialMap::load(map, materials, self.n as usize).map(Some)
+    }
+
+    /// Writes the matrices of the solver to the directory requested by this
+    /// configuration, if any.
+    pub fn export_matrices(&self) -> io::Result<()> {
+        let Some(directory) = &self.export_matrices else {
+            return Ok(());
+        };
+        fs::create_dir_all(directory)?;
+        let n = self.n as usize;
+        let [a, b] = match (self.materials()?, self.mask()?) {
+            (Some(materials), mask) => {
+                HeatEquation::material_matrices(n, self.dt, &materials, mask.as_ref())
+            }
+            (None, Some(mask)) => HeatEquation::masked_matrices(self.alpha, n, self.dt, &mask),
+            (None, None) => HeatEquation::matrices(self.alpha, n, self.dt),
+        };
+        matrix_market::save(directory.join("a.mtx"), &a)?;
+        matrix_market::save(directory.join("b.mtx"), &b)
+    }
+
+    /// Creates the refinement log requested by this configuration, if any.
+    pub fn refinement_log(&self) -> io::Result<Option<RefinementLog<BufWriter<File>>>> {
+        self.refinement_log
+            .as_ref()
+            .map(RefinementLog::create)
+            .transpose()
+    }
+
+    /// Reports of the profile requested by this configuration, if any.
+    pub fn profile_report(&self) -> Option<ProfileReport> {
+        (self.profile || self.profile_trace.is_some()).then(|| ProfileReport {
+            summary: self.profile,
+            trace: self.profile_trace.clone(),
+        })
+    }
+
+    /// Creates the probe log requested by this configuration, if any.
+    pub fn probe_log(&self) -> io::Result<Option<ProbeLog<BufWriter<File>>>> {
+        self.probe_output
+            .as_ref()
+            .map(|path| ProbeLog::create(path, &self.probes))
+            .transpose()
+    }
+}
+
+fn parse<T: FromStr>(arg: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {value:?} for {arg}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parses_arguments() {
+        let config =
+            Config::from_args(args("--headless --n 64 --steps 10 --npy out.npz --every 5"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                n: 64,
+                headless: true,
+                steps: 10,
+                npy: Some(PathBuf::from("out.npz")),
+                output_interval: 5,
+                ..Config::default()
+            }
+        );
+        assert_eq!(Config::from_args(args("--help")), Ok(None));
+        let config = Config::from_args(args("--steps-per-frame 8"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.steps_per_frame, 8);
+        assert!(Config::from_args(args("--steps-per-frame 0")).is_err());
+        assert!(Config::from_args(args("--n")).is_err());
+        assert!(Config::from_args(args("--dt fast")).is_err());
+        assert!(Config::from_args(args("--every 0")).is_err());
+        assert!(Config::from_args(args("--vtk-format binary")).is_err());
+        assert!(Config::from_args(args("--image-format jpeg")).is_err());
+
+        let config = Config::from_args(args("--initial-range 10,20.5 --initial hot.png"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            config.initial,
+            InitialCondition::File {
+                path: PathBuf::from("hot.png"),
+                range: [10.0, 20.5],
+            }
+        );
+        assert!(Config::from_args(args("--initial-range 10")).is_err());
+        let config = Config::from_args(args("--initial uniform:value=2+eigenmode:k=2"))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(config.initial, InitialCondition::Analytic(terms) if terms.len() == 2));
+        assert!(Config::from_args(args("--initial disk:radius=big")).is_err());
+        assert!(Config::from_args(args("--checkpoint-every 0")).is_err());
+        let config = Config::from_args(args("--diagnostics d.csv"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.diagnostics, Some(PathBuf::from("d.csv")));
+        let config = Config::from_args(args(
+            "--probe tc1=0.5,0.5 --line mid=0,0.5,1,0.5,11 --probes p.csv",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.probes.positions().len(), 12);
+        assert!(Config::from_args(args("--probe tc1=0.5,0.5")).is_err());
+        assert!(Config::from_args(args("--probes p.csv")).is_err());
+        assert!(Config::from_args(args("--probe tc1=2,0.5 --probes p.csv")).is_err());
+        let config = Config::from_args(args("--summation compensated"))
+            .unwrap()
+            .unwrap();
+        let options = config.solver_options(wgpu::Features::empty()).unwrap();
+        assert_eq!(options.summation, Summation::Compensated);
+        assert!(Config::from_args(args("--summation kahan")).is_err());
+        let config = Config::from_args(args("--cg pipelined")).unwrap().unwrap();
+        let options = config.solver_options(wgpu::Features::empty()).unwrap();
+        assert_eq!(options.cg, CGVariant::Pipelined);
+        assert!(Config::from_args(args("--cg fused --summation compensated")).is_err());
+        assert!(Config::from_args(args("--cg gmres")).is_err());
+        let config = Config::from_args(args("--precision double"))
+            .unwrap()
+            .unwrap();
+        let options = config.solver_options(wgpu::Features::empty()).unwrap();
+        assert_eq!(options.precision, Precision::DoubleFloat);
+        let options = config.solver_options(wgpu::Features::SHADER_F64).unwrap();
+        assert_eq!(options.precision, Precision::Double);
+        assert!(Config::from_args(args("--precision half")).is_err());
+        let config = Config::from_args(args(
+            "--precision double-float --refine 1e-12 --refinement-log r.csv",
+        ))
+        .unwrap()
+        .unwrap();
+        let options = config.solver_options(wgpu::Features::empty()).unwrap();
+        assert_eq!(options.refinement.unwrap().tolerance, 1e-12);
+        assert_eq!(config.refinement_log, Some(PathBuf::from("r.csv")));
+        assert!(Config::from_args(args("--refine 1e-12")).is_err());
+        assert!(Config::from_args(args("--precision double --refine tight")).is_err());
+        assert!(Config::from_args(args("--precision double --refinement-log r.csv")).is_err());
+        let config = Config::from_args(args("--export-matrices matrices"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.export_matrices, Some(PathBuf::from("matrices")));
+        let config = Config::from_args(args("--matrix-free")).unwrap().unwrap();
+        let options = config.solver_options(wgpu::Features::empty()).unwrap();
+        assert!(options.matrix_free);
+        let config = Config::from_args(args("--n 8 --mask l-shape"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.mask, Some(MaskSource::LShape));
+        let options = config.solver_options(wgpu::Features::empty()).unwrap();
+        assert_eq!(options.mask.unwrap().active_cells(), 48);
+        assert!(Config::from_args(args("--mask l-shape --matrix-free")).is_err());
+        assert!(Config::from_args(args("--mask hole:radius=wide")).is_err());
+        let config = Config::from_args(args("--mask missing.png"))
+            .unwrap()
+            .unwrap();
+        assert!(config.solver_options(wgpu::Features::empty()).is_err());
+        assert_eq!(Config::default().profile_report(), None);
+        let config = Config::from_args(args("--profile-trace trace.json"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            config.profile_report(),
+            Some(ProfileReport {
+                summary: false,
+                trace: Some(PathBuf::from("trace.json")),
+            })
+        );
+        assert!(Config::from_args(args("--profile-trace")).is_err());
+    }
+}
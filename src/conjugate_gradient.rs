@@ -1,24 +1,51 @@
 use std::rc::Rc;
 
 use crate::{
-    dia_matrix::DIAMatrixDescriptor,
     kernels::{
-        dot::DotKernel,
+        cg_update::{FusedUpdateKernel, PipelinedUpdateKernel},
+        compensated_dot::CompensatedDotKernel,
+        dot::{DotKernel, Summation},
         kernel::Kernel,
+        reduce::{ReduceKernel, ReduceOp},
         saxpy_update::SAXPYUpdateKernel,
         saxpy_update_div::{Operation, SAXPYUpdateDivKernel},
-        spmv::SpMVKernel,
+        spmv_dots,
     },
+    precision::Precision,
+    profiler::{encode_kernels, Profiler},
+    sparse_matrix::SparseMatrix,
 };
 
+/// Formulation of the conjugate gradient iteration, which changes how many
+/// dispatches, and so global synchronizations, each iteration takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CGVariant {
+    /// Separate kernels for every product and update: 7 stages per
+    /// iteration, 3 of which are dot products over the whole vectors.
+    #[default]
+    Standard,
+    /// The SpMV computes the products of `p . q` and the updates of `x` and
+    /// `r` those of `r . r`, which leaves a reduction for each dot product:
+    /// 5 stages per iteration.
+    Fused,
+    /// Chronopoulos-Gear CG, which computes both dot products of an iteration
+    /// along with its only SpMV: 3 stages per iteration, at the cost of two
+    /// more vectors and a slightly different rounding.
+    Pipelined,
+}
+
 /// Specialized data structure for the conjugate gradient method
 /// specific for GPU compute.
 ///
-/// This uses the CG method to solve the system of linear equations Ax = b where A is a sparse matrix.
+/// This uses the CG method to solve the system of linear equations Ax = b where A is a sparse matrix
+/// in any [`SparseMatrix`] format.
+/// The vectors hold values of the precision of A.
 pub struct CG {
     buffers: Rc<CGBuffers>,
     init_stages: Vec<Box<dyn Kernel>>,
-    stages: Vec<Box<dyn Kernel>>,
+    /// Stages of the iterations, which alternate between the sets of stages
+    /// when there are several.
+    stages: Vec<Vec<Box<dyn Kernel>>>,
     max_steps: usize,
 }
 
@@ -26,14 +53,28 @@ impl CG {
     pub fn new(
         device: &wgpu::Device,
         buffers: Rc<CGBuffers>,
-        a: &DIAMatrixDescriptor, // Sparse matrix A
-        b: &wgpu::Buffer,        // Vector b
-        x: &wgpu::Buffer,        // Vector x initialized with initial guess x_0
+        a: &dyn SparseMatrix, // Sparse matrix A, in any format
+        b: &wgpu::Buffer,     // Vector b
+        x: &wgpu::Buffer,     // Vector x initialized with initial guess x_0
+        summation: Summation, // Accumulation of the dot products, in single precision
     ) -> Self {
+        let stages = match buffers.variant {
+            VariantBuffers::Standard => vec![Self::stages(device, &buffers, a, x, summation)],
+            _ => {
+                assert_eq!(
+                    summation,
+                    Summation::Plain,
+                    "only the standard CG supports compensated sums"
+                );
+                (0..2)
+                    .map(|parity| Self::variant_stages(device, &buffers, a, x, parity))
+                    .collect()
+            }
+        };
         Self {
             buffers: buffers.clone(),
             init_stages: Self::init_stages(device, buffers.as_ref(), a, b, x),
-            stages: Self::stages(device, buffers.as_ref(), a, x),
+            stages,
             max_steps: 10,
         }
     }
@@ -41,23 +82,151 @@ impl CG {
     fn init_stages(
         device: &wgpu::Device,
         buffers: &CGBuffers,
-        a: &DIAMatrixDescriptor,
+        a: &dyn SparseMatrix,
         b: &wgpu::Buffer,
         x: &wgpu::Buffer,
     ) -> Vec<Box<dyn Kernel>> {
-        let CGBuffers { r, .. } = buffers;
+        let CGBuffers {
+            r,
+            sigma,
+            tmp,
+            variant,
+            ..
+        } = buffers;
+        let precision = a.precision();
         // Initialize r = b - A * x
-        let r_init0 = SpMVKernel::new(device, a, x, r);
-        let r_init1 = SAXPYUpdateKernel::new(device, b, r);
-        vec![Box::new(r_init0), Box::new(r_init1)]
+        let r_init0 = a.spmv(device, x, r);
+        let r_init1 = SAXPYUpdateKernel::new(device, precision, b, r);
+        let mut stages = vec![r_init0, Box::new(r_init1) as Box<dyn Kernel>];
+        match variant {
+            VariantBuffers::Standard => {}
+            // sigma = dot(r, r), which the first iteration reads
+            VariantBuffers::Fused { .. } => {
+                stages.push(Box::new(DotKernel::new(
+                    device, precision, r, r, tmp, sigma,
+                )));
+            }
+            // w = A * r with dot(r, w) and dot(r, r) in the first slot
+            VariantBuffers::Pipelined {
+                products,
+                w,
+                scalars,
+                ..
+            } => {
+                stages.push(a.spmv_dots(device, r, w, products));
+                stages.push(Box::new(Self::reduce_dots(
+                    device, precision, products, 2, scalars, 0,
+                )));
+            }
+        }
+        stages
+    }
+
+    /// Sums the products of the first `count` dot products stored in
+    /// `products` into `output`, from `output_index`.
+    fn reduce_dots(
+        device: &wgpu::Device,
+        precision: Precision,
+        products: &wgpu::Buffer,
+        count: u32,
+        output: &wgpu::Buffer,
+        output_index: u32,
+    ) -> ReduceKernel {
+        let len = (products.size() / precision.size() as u64 / 2) as u32;
+        ReduceKernel::segments(
+            device,
+            precision,
+            ReduceOp::Sum,
+            products,
+            len,
+            count,
+            output,
+            output_index,
+        )
+    }
+
+    /// Define the stages of the iterations of parity `parity` for the fused
+    /// and pipelined variants, which alternate between two sets of scalars.
+    fn variant_stages(
+        device: &wgpu::Device,
+        buffers: &CGBuffers,
+        a: &dyn SparseMatrix,
+        x: &wgpu::Buffer,
+        parity: usize,
+    ) -> Vec<Box<dyn Kernel>> {
+        let CGBuffers {
+            r,
+            p,
+            q,
+            sigma,
+            sigma_prime,
+            variant,
+            ..
+        } = buffers;
+        let precision = a.precision();
+        match variant {
+            VariantBuffers::Standard => unreachable!("the standard CG has a single set of stages"),
+            VariantBuffers::Fused { products, pq } => {
+                // rho = dot(r, r) of the current residual, and of the next one
+                let (rho, rho_next) = match parity {
+                    0 => (sigma, sigma_prime),
+                    _ => (sigma_prime, sigma),
+                };
+                vec![
+                    // q = A * p and pq = dot(p, q)
+                    a.spmv_dots(device, p, q, products),
+                    Box::new(Self::reduce_dots(device, precision, products, 1, pq, 0)),
+                    // x = x + (rho / pq) * p, r = r - (rho / pq) * q and
+                    // rho_next = dot(r, r)
+                    Box::new(FusedUpdateKernel::new(
+                        device, precision, x, r, p, q, rho, pq, products,
+                    )),
+                    Box::new(Self::reduce_dots(
+                        device, precision, products, 1, rho_next, 0,
+                    )),
+                    // p = r + (rho_next / rho) * p
+                    Box::new(SAXPYUpdateDivKernel::new(
+                        device,
+                        precision,
+                        rho_next,
+                        rho,
+                        r,
+                        p,
+                        Operation::Aypx,
+                    )),
+                ]
+            }
+            VariantBuffers::Pipelined {
+                products,
+                s,
+                w,
+                scalars,
+            } => vec![
+                // p, s, x and r from the scalars of slot `parity`
+                Box::new(PipelinedUpdateKernel::new(
+                    device, precision, x, r, p, s, w, scalars, parity,
+                )),
+                // w = A * r with dot(r, w) and dot(r, r) in the other slot
+                a.spmv_dots(device, r, w, products),
+                Box::new(Self::reduce_dots(
+                    device,
+                    precision,
+                    products,
+                    2,
+                    scalars,
+                    ((parity ^ 1) * PipelinedUpdateKernel::SLOT_LEN) as u32,
+                )),
+            ],
+        }
     }
 
     /// Define the stages for a single iteration of the CG algorithm on a `wgpu::ComputePass`
     fn stages(
         device: &wgpu::Device,
         buffers: &CGBuffers,
-        a: &DIAMatrixDescriptor,
+        a: &dyn SparseMatrix,
         x: &wgpu::Buffer,
+        summation: Summation,
     ) -> Vec<Box<dyn Kernel>> {
         let CGBuffers {
             r,
@@ -65,70 +234,80 @@ impl CG {
             q,
             sigma,
             sigma_prime,
-            tmp0,
-            tmp1,
+            tmp,
+            ..
         } = buffers;
+        let precision = a.precision();
+        let dot = |x, y, output| -> Box<dyn Kernel> {
+            match (summation, precision) {
+                (Summation::Compensated, Precision::Single) => {
+                    Box::new(CompensatedDotKernel::new(device, x, y, output))
+                }
+                _ => Box::new(DotKernel::new(device, precision, x, y, tmp, output)),
+            }
+        };
         // Iteration stages
         // First stage of iteration: sigma = dot(r, r)
-        let sigma_stage = DotKernel::new(device, r, r, tmp0, tmp1, sigma);
+        let sigma_stage = dot(r, r, sigma);
 
         // Second stage of iteration: q = A * p (Sparse matrix-vector multiplication)
-        let q_stage = SpMVKernel::new(device, a, p, q);
+        let q_stage = a.spmv(device, p, q);
 
         // Third stage of iteration: sigma_prime = dot(p, q)
-        let sigma_prime_stage = DotKernel::new(device, p, q, tmp0, tmp1, sigma_prime);
+        let sigma_prime_stage = dot(p, q, sigma_prime);
 
         // Fourth stage of iteration: x = x + (sigma / sigma_prime) * p
-        let x_stage = SAXPYUpdateDivKernel::new(device, sigma, sigma_prime, p, x, Operation::Add);
+        let x_stage =
+            SAXPYUpdateDivKernel::new(device, precision, sigma, sigma_prime, p, x, Operation::Add);
 
         // Fifth stage of iteration: r = r - (sigma / sigma_prime) * q
-        let r_stage = SAXPYUpdateDivKernel::new(device, sigma, sigma_prime, q, r, Operation::Sub);
+        let r_stage =
+            SAXPYUpdateDivKernel::new(device, precision, sigma, sigma_prime, q, r, Operation::Sub);
 
         // Sixth stage of iteration: sigma_prime = dot(r, r)
-        let sigma_prime_stage2 = DotKernel::new(device, r, r, tmp0, tmp1, sigma_prime);
+        let sigma_prime_stage2 = dot(r, r, sigma_prime);
 
         // Seventh stage of iteration: p = r + (sigma_prime / sigma) * p
-        let p_stage = SAXPYUpdateDivKernel::new(device, sigma_prime, sigma, r, p, Operation::Add);
+        let p_stage =
+            SAXPYUpdateDivKernel::new(device, precision, sigma_prime, sigma, r, p, Operation::Aypx);
 
         // create Vec<Box<dyn Kernel>> to iterate over
         vec![
-            Box::new(sigma_stage),
-            Box::new(q_stage),
-            Box::new(sigma_prime_stage),
+            sigma_stage,
+            q_stage,
+            sigma_prime_stage,
             Box::new(x_stage),
             Box::new(r_stage),
-            Box::new(sigma_prime_stage2),
+            sigma_prime_stage2,
             Box::new(p_stage),
         ]
     }
 
     pub fn run(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        let CGBuffers { r, p, .. } = self.buffers.as_ref();
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Conjugate Gradient"),
         });
-        // Initialize r = b - A * x
-        let cdescriptor = wgpu::ComputePassDescriptor {
-            label: None,
-            timestamp_writes: None,
-        };
-        let mut compute_pass = encoder.begin_compute_pass(&cdescriptor);
-        for stage in self.init_stages.iter() {
-            stage.add_to_pass(&mut compute_pass);
+        self.encode(&mut encoder, None);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Records the whole solve into `encoder`, so that it can be submitted
+    /// along with other work, with its kernels timed by `profiler`.
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder, profiler: Option<&Profiler>) {
+        let CGBuffers { r, p, variant, .. } = self.buffers.as_ref();
+        if let VariantBuffers::Pipelined { scalars, .. } = variant {
+            // zero scalars of a previous iteration make the first one restart
+            encoder.clear_buffer(scalars, 0, None);
         }
-        drop(compute_pass);
+        // Initialize r = b - A * x
+        let init_stages = self.init_stages.iter().map(|s| s.as_ref());
+        encode_kernels(encoder, None, init_stages, profiler);
         encoder.copy_buffer_to_buffer(r, 0, p, 0, r.size());
         // describes all the stages in a single iteration of the CG algorithm
-        for _ in 0..self.max_steps {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
-            for s in self.stages.iter() {
-                s.add_to_pass(&mut compute_pass);
-            }
+        for i in 0..self.max_steps {
+            let stages = self.stages[i % self.stages.len()].iter();
+            encode_kernels(encoder, None, stages.map(|s| s.as_ref()), profiler);
         }
-        queue.submit(Some(encoder.finish()));
     }
 }
 
@@ -142,15 +321,35 @@ pub struct CGBuffers {
     q: wgpu::Buffer,           // A * p
     sigma: wgpu::Buffer,       // scalar
     sigma_prime: wgpu::Buffer, // scalar
-    tmp0: wgpu::Buffer,        // scratch vector
-    tmp1: wgpu::Buffer,        // scratch vector
+    tmp: wgpu::Buffer,         // scratch vector
+    variant: VariantBuffers,
+}
+
+/// Buffers of the [`CGVariant`] on top of those of the standard CG.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // a single one per solver
+enum VariantBuffers {
+    Standard,
+    Fused {
+        products: wgpu::Buffer, // products of the dot products
+        pq: wgpu::Buffer,       // scalar
+    },
+    Pipelined {
+        products: wgpu::Buffer, // products of the dot products
+        s: wgpu::Buffer,        // A * p
+        w: wgpu::Buffer,        // A * r
+        scalars: wgpu::Buffer,  // two slots of dot products and step length
+    },
 }
 
 impl CGBuffers {
     pub fn new(
         device: &wgpu::Device,
-        size: wgpu::BufferAddress, // size of the vectors, in bytes
+        precision: Precision,
+        len: usize, // number of values in the vectors
+        variant: CGVariant,
     ) -> Self {
+        let size = (len * precision.size()) as wgpu::BufferAddress;
         // before iterating, must set up the r(residual) and p(direction) vectors as GPU buffers
         let r = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("r"),
@@ -173,40 +372,60 @@ impl CGBuffers {
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let f32_size = std::mem::size_of::<f32>() as wgpu::BufferAddress;
+        let scalar_size = precision.size() as wgpu::BufferAddress;
         let sigma = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("sigma"),
-            size: f32_size,
+            size: scalar_size,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
         let sigma_prime = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("sigma_prime"),
-            size: f32_size,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        let tmp0 = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("tmp0"),
-            size,
+            size: scalar_size,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let tmp1 = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("tmp1"),
+        let tmp = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("tmp"),
             size,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
 
+        let buffer = |label, size| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let products_size = (spmv_dots::products_len(len) * precision.size()) as u64;
+        let variant = match variant {
+            CGVariant::Standard => VariantBuffers::Standard,
+            CGVariant::Fused => VariantBuffers::Fused {
+                products: buffer("products", products_size),
+                pq: buffer("pq", scalar_size),
+            },
+            CGVariant::Pipelined => VariantBuffers::Pipelined {
+                products: buffer("products", products_size),
+                s: buffer("s", size),
+                w: buffer("w", size),
+                scalars: buffer(
+                    "scalars",
+                    2 * PipelinedUpdateKernel::SLOT_LEN as u64 * scalar_size,
+                ),
+            },
+        };
+
         Self {
             r,
             p,
             q,
             sigma,
             sigma_prime,
-            tmp0,
-            tmp1,
+            tmp,
+            variant,
         }
     }
 }
@@ -0,0 +1,126 @@
+use crate::{csr_matrix::CSRMatrix, dia_matrix::DIAMatrix};
+
+/// Host-side sparse matrix in coordinate format, for assembling matrices
+/// entry by entry.
+///
+/// Entries can be pushed in any order, and the values of repeated entries
+/// add up, as in finite element assembly. Convert to [`CSRMatrix`] or
+/// [`DIAMatrix`] with `From` to multiply or upload the matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct COOMatrix {
+    pub num_cols: usize,
+    pub num_rows: usize,
+    /// `(row, col, value)` of every entry, in the order they were pushed.
+    pub entries: Vec<(usize, usize, f64)>,
+}
+
+impl COOMatrix {
+    /// Creates a matrix without entries.
+    pub fn new(num_cols: usize, num_rows: usize) -> Self {
+        Self {
+            num_cols,
+            num_rows,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds `value` to the entry in `row` and `col`.
+    pub fn push(&mut self, row: usize, col: usize, value: f64) {
+        assert!(
+            row < self.num_rows && col < self.num_cols,
+            "entry ({row}, {col}) lies outside the {} x {} matrix",
+            self.num_rows,
+            self.num_cols
+        );
+        self.entries.push((row, col, value));
+    }
+
+    /// Value of the entry in `row` and `col`, zero if there is none.
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.entries
+            .iter()
+            .filter(|&&(i, j, _)| (i, j) == (row, col))
+            .map(|&(_, _, value)| value)
+            .sum()
+    }
+
+    /// Entries sorted by row and column, with the repeated ones added up.
+    pub fn compressed(&self) -> Vec<(usize, usize, f64)> {
+        let mut entries = self.entries.clone();
+        entries.sort_by_key(|&(row, col, _)| (row, col));
+        let mut compressed: Vec<(usize, usize, f64)> = Vec::with_capacity(entries.len());
+        for (row, col, value) in entries {
+            match compressed.last_mut() {
+                Some(last) if (last.0, last.1) == (row, col) => last.2 += value,
+                _ => compressed.push((row, col, value)),
+            }
+        }
+        compressed
+    }
+}
+
+/// Adds up repeated entries and drops the zeros.
+impl From<&COOMatrix> for CSRMatrix {
+    fn from(a: &COOMatrix) -> Self {
+        let entries = a.compressed();
+        let rows = (0..a.num_rows).map(|row| {
+            let start = entries.partition_point(|&(i, _, _)| i < row);
+            let end = entries.partition_point(|&(i, _, _)| i <= row);
+            entries[start..end]
+                .iter()
+                .map(|&(_, col, value)| (col, value))
+        });
+        CSRMatrix::from_rows(a.num_cols, a.num_rows, rows)
+    }
+}
+
+impl From<&COOMatrix> for DIAMatrix {
+    fn from(a: &COOMatrix) -> Self {
+        DIAMatrix::from(&CSRMatrix::from(a))
+    }
+}
+
+impl From<&CSRMatrix> for COOMatrix {
+    fn from(a: &CSRMatrix) -> Self {
+        let mut coo = COOMatrix::new(a.num_cols, a.num_rows);
+        for row in 0..a.num_rows {
+            for (col, value) in a.row(row) {
+                coo.push(row, col, value);
+            }
+        }
+        coo
+    }
+}
+
+/// Keeps the nonzero entries of the diagonals that lie inside the matrix.
+impl From<&DIAMatrix> for COOMatrix {
+    fn from(a: &DIAMatrix) -> Self {
+        COOMatrix::from(&CSRMatrix::from(a))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_repeated_entries() {
+        let mut a = COOMatrix::new(3, 2);
+        a.push(1, 2, 1.5);
+        a.push(0, 0, 2.0);
+        a.push(1, 2, 0.5);
+        a.push(0, 1, 1.0);
+        a.push(0, 1, -1.0);
+        assert_eq!(a.get(1, 2), 2.0);
+        assert_eq!(a.get(1, 0), 0.0);
+        assert_eq!(a.compressed(), vec![(0, 0, 2.0), (0, 1, 0.0), (1, 2, 2.0)]);
+        // the cancelled entry is dropped
+        let csr = CSRMatrix::from(&a);
+        assert_eq!(csr.row_offsets, vec![0, 1, 2]);
+        assert_eq!(csr.col_indices, vec![0, 2]);
+        assert_eq!(csr.data, vec![2.0, 2.0]);
+        let dia = DIAMatrix::from(&a);
+        assert_eq!(dia.offsets, vec![0, 1]);
+        assert_eq!(dia.data, vec![2.0, 0.0, 0.0, 2.0]);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{dia_matrix::DIAMatrix, kernels::saxpy_update_div::Operation};
+
+use super::kernels::{dot, saxpy_update, saxpy_update_div, spmv};
+
+/// CPU counterpart of `conjugate_gradient::CG`.
+///
+/// Runs the same sequence of stages as the GPU version, including the fixed
+/// number of iterations, so that both produce comparable results.
+pub struct CG {
+    r: Vec<f64>, // residual vector
+    p: Vec<f64>, // direction vector
+    q: Vec<f64>, // A * p
+    max_steps: usize,
+}
+
+impl CG {
+    /// Creates a solver for systems with `size` unknowns.
+    pub fn new(size: usize) -> Self {
+        Self {
+            r: vec![0.0; size],
+            p: vec![0.0; size],
+            q: vec![0.0; size],
+            max_steps: 10,
+        }
+    }
+
+    /// Solves `A x = b`, using the contents of `x` as the initial guess.
+    pub fn run(&mut self, a: &DIAMatrix, b: &[f64], x: &mut [f64]) {
+        let Self { r, p, q, .. } = self;
+        // Initialize r = b - A * x
+        spmv(a, x, r);
+        saxpy_update(b, r);
+        p.copy_from_slice(r);
+        for _ in 0..self.max_steps {
+            let sigma = dot(r, r);
+            // the GPU version would produce NaNs from here on,
+            // since the residual is exactly zero
+            if sigma == 0.0 {
+                break;
+            }
+            spmv(a, p, q);
+            let sigma_prime = dot(p, q);
+            saxpy_update_div(sigma, sigma_prime, p, x, Operation::Add);
+            saxpy_update_div(sigma, sigma_prime, q, r, Operation::Sub);
+            let sigma_prime = dot(r, r);
+            saxpy_update_div(sigma_prime, sigma, r, p, Operation::Aypx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solves_spd_system() {
+        // 1D Poisson-like tridiagonal matrix, which is symmetric positive definite
+        const M: usize = 8;
+        let mut data = vec![-1.0; M];
+        data[0] = 0.0;
+        data.extend(vec![4.0; M]);
+        data.extend(vec![-1.0; M]);
+        data[3 * M - 1] = 0.0;
+        let a = DIAMatrix::new(M, M, vec![-1, 0, 1], data);
+        let expected: Vec<f64> = (0..M).map(|i| i as f64).collect();
+        let mut b = vec![0.0; M];
+        spmv(&a, &expected, &mut b);
+
+        let mut x = vec![0.0; M];
+        CG::new(M).run(&a, &b, &mut x);
+        for (xi, ei) in x.iter().zip(expected.iter()) {
+            assert!((xi - ei).abs() < 1e-10, "{xi} != {ei}");
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::io;
+
+use crate::{dia_matrix::DIAMatrix, field::Field, heat_equation, solver::Solver};
+
+use super::{conjugate_gradient::CG, kernels::spmv};
+
+/// CPU counterpart of `heat_equation::HeatEquation`.
+///
+/// Uses the same matrices and the same ping-pong between `u` and `u_`, so the
+/// conjugate gradient solver starts from the same initial guess as on the GPU.
+pub struct HeatEquation {
+    a: DIAMatrix,
+    b: DIAMatrix,
+    cg: CG,
+    u: Vec<f64>,
+    u_: Vec<f64>,
+    tmp: Vec<f64>,
+    iteration: usize,
+    n: usize,
+    dt: f32,
+}
+
+impl HeatEquation {
+    pub fn new(alpha: f32, n: usize, dt: f32, u0: &[f32]) -> Self {
+        let m = n * n;
+        assert_eq!(u0.len(), m, "initial condition must have n * n entries");
+        Self {
+            a: heat_equation::HeatEquation::a_matrix(alpha, n, dt),
+            b: heat_equation::HeatEquation::b_matrix(alpha, n, dt),
+            cg: CG::new(m),
+            u: u0.iter().map(|&v| v as f64).collect(),
+            u_: vec![0.0; m],
+            tmp: vec![0.0; m],
+            iteration: 0,
+            n,
+            dt,
+        }
+    }
+
+    pub fn compute_step(&mut self) {
+        let (u_old, u_new) = if self.iteration.is_multiple_of(2) {
+            (&self.u, &mut self.u_)
+        } else {
+            (&self.u_, &mut self.u)
+        };
+        // tmp = B * u_old
+        spmv(&self.b, u_old, &mut self.tmp);
+        // A u_new = tmp
+        self.cg.run(&self.a, &self.tmp, u_new);
+        self.iteration += 1;
+    }
+
+    /// Current solution, in row-major order.
+    pub fn field(&self) -> &[f64] {
+        if self.iteration.is_multiple_of(2) {
+            &self.u
+        } else {
+            &self.u_
+        }
+    }
+
+    fn field_mut(&mut self) -> &mut [f64] {
+        if self.iteration.is_multiple_of(2) {
+            &mut self.u
+        } else {
+            &mut self.u_
+        }
+    }
+
+    /// Replaces the current solution with `data`, in row-major order.
+    pub fn set_field(&mut self, data: &[f32]) {
+        assert_eq!(data.len(), self.n * self.n, "field must have n * n entries");
+        for (u, &v) in self.field_mut().iter_mut().zip(data) {
+            *u = v as f64;
+        }
+    }
+
+    /// Replaces a rectangular region of the current solution, see
+    /// `heat_equation::HeatEquation::set_region`.
+    pub fn set_region(&mut self, origin: (usize, usize), width: usize, data: &[f32]) {
+        let (x0, y0) = origin;
+        let n = self.n;
+        assert!(
+            width > 0 && data.len().is_multiple_of(width),
+            "region data must be a whole number of rows"
+        );
+        assert!(
+            x0 + width <= n && y0 + data.len() / width <= n,
+            "region must lie inside the grid"
+        );
+        let field = self.field_mut();
+        for (row, values) in data.chunks_exact(width).enumerate() {
+            let start = (y0 + row) * n + x0;
+            for (u, &v) in field[start..start + width].iter_mut().zip(values) {
+                *u = v as f64;
+            }
+        }
+    }
+}
+
+impl Solver for HeatEquation {
+    type Context<'a> = ();
+
+    fn compute_step(&mut self, _: ()) {
+        HeatEquation::compute_step(self);
+    }
+
+    fn iteration(&self) -> usize {
+        self.iteration
+    }
+
+    fn time(&self) -> f32 {
+        self.iteration as f32 * self.dt
+    }
+
+    fn n(&self) -> usize {
+        self.n
+    }
+
+    fn read_field(&self, _: ()) -> io::Result<Field> {
+        let data = self.field().iter().map(|&v| v as f32).collect();
+        Ok(Field::new(self.n, self.n, data))
+    }
+
+    fn set_field(&mut self, _: (), data: &[f32]) {
+        HeatEquation::set_field(self, data);
+    }
+
+    fn set_region(&mut self, _: (), origin: (usize, usize), width: usize, data: &[f32]) {
+        HeatEquation::set_region(self, origin, width, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heat_diffuses() {
+        const N: usize = 16;
+        let mut u0 = vec![0.0; N * N];
+        u0[N * N / 2 + N / 2] = 1.0;
+        let mut heat_eqn = HeatEquation::new(2e-4, N, 0.016, &u0);
+        let mut peak = 1.0;
+        for _ in 0..5 {
+            heat_eqn.compute_step();
+            let field = heat_eqn.field();
+            let max = field.iter().copied().fold(f64::MIN, f64::max);
+            let total: f64 = field.iter().sum();
+            assert!(max < peak);
+            // no heat has reached the boundary yet, so it must be conserved
+            assert!((total - 1.0).abs() < 1e-6, "total heat {total}");
+            peak = max;
+        }
+        assert_eq!(heat_eqn.iteration(), 5);
+    }
+
+    #[test]
+    fn set_region_writes_rows() {
+        const N: usize = 4;
+        let mut heat_eqn = HeatEquation::new(1e-3, N, 0.01, &[0.0; N * N]);
+        heat_eqn.compute_step();
+        heat_eqn.set_region((1, 2), 2, &[1.0, 2.0, 3.0, 4.0]);
+        let field = heat_eqn.read_field(()).unwrap();
+        assert_eq!(field.get(1, 2), 1.0);
+        assert_eq!(field.get(2, 2), 2.0);
+        assert_eq!(field.get(1, 3), 3.0);
+        assert_eq!(field.get(2, 3), 4.0);
+        assert_eq!(field.data.iter().sum::<f32>(), 10.0);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{dia_matrix::DIAMatrix, kernels::saxpy_update_div::Operation};
+
+/// y = A * x, same as `SpMVKernel`.
+pub fn spmv(a: &DIAMatrix, x: &[f64], y: &mut [f64]) {
+    for (row, out) in y.iter_mut().enumerate().take(a.num_rows) {
+        let mut dot = 0.0;
+        for (n, offset) in a.offsets.iter().enumerate() {
+            let col = row as i64 + *offset as i64;
+            if col >= 0 && col < a.num_cols as i64 {
+                dot += a.data[a.num_rows * n + row] * x[col as usize];
+            }
+        }
+        *out = dot;
+    }
+}
+
+/// Returns x . y, same as `DotKernel`.
+pub fn dot(x: &[f64], y: &[f64]) -> f64 {
+    x.iter().zip(y).map(|(a, b)| a * b).sum()
+}
+
+/// y = x - y, same as `SAXPYUpdateKernel`.
+pub fn saxpy_update(x: &[f64], y: &mut [f64]) {
+    for (yi, xi) in y.iter_mut().zip(x) {
+        *yi = xi - *yi;
+    }
+}
+
+/// y = y OP (a1/a2) * x, same as `SAXPYUpdateDivKernel`.
+pub fn saxpy_update_div(a1: f64, a2: f64, x: &[f64], y: &mut [f64], op: Operation) {
+    let alpha = a1 / a2;
+    for (yi, xi) in y.iter_mut().zip(x) {
+        match op {
+            Operation::Add => *yi += alpha * xi,
+            Operation::Sub => *yi -= alpha * xi,
+            Operation::Aypx => *yi = xi + alpha * *yi,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spmv_tridiagonal() {
+        const M: usize = 16;
+        let mut data = vec![2.0; M];
+        data[0] = 0.0;
+        data.extend(vec![1.0; M]);
+        data.extend(vec![2.0; M]);
+        data[3 * M - 1] = 0.0;
+        let a = DIAMatrix::new(M, M, vec![-1, 0, 1], data);
+        let x = vec![2.0; M];
+        let mut y = vec![0.0; M];
+        spmv(&a, &x, &mut y);
+        let mut expected = vec![10.0; M];
+        expected[0] = 6.0;
+        expected[M - 1] = 6.0;
+        assert_eq!(y, expected);
+    }
+
+    #[test]
+    fn saxpy_updates() {
+        let x = vec![1.0, 2.0, 3.0];
+        let mut y = vec![3.0, 2.0, 1.0];
+        saxpy_update(&x, &mut y);
+        assert_eq!(y, vec![-2.0, 0.0, 2.0]);
+        saxpy_update_div(4.0, 2.0, &x, &mut y, Operation::Add);
+        assert_eq!(y, vec![0.0, 4.0, 8.0]);
+        saxpy_update_div(1.0, 1.0, &x, &mut y, Operation::Sub);
+        assert_eq!(y, vec![-1.0, 2.0, 5.0]);
+        saxpy_update_div(1.0, 2.0, &x, &mut y, Operation::Aypx);
+        assert_eq!(y, vec![0.5, 3.0, 5.5]);
+        assert_eq!(dot(&x, &y), 23.0);
+    }
+}
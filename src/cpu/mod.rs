@@ -0,0 +1,8 @@
+//! Pure-Rust reference implementation of the GPU kernels and solvers.
+//!
+//! Everything here mirrors its counterpart in `kernels`, `conjugate_gradient`
+//! and `heat_equation` step by step, but runs on the host in `f64`. It is used
+//! where no adapter is available and as a numerical oracle for the GPU output.
+pub mod conjugate_gradient;
+pub mod heat_equation;
+pub mod kernels;
@@ -0,0 +1,215 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    dia_matrix::DIAMatrix,
+    ell_matrix::ELLMatrix,
+    kernels::{kernel::Kernel, spmv_csr::CSRSpMVKernel},
+    precision::Precision,
+    sparse_matrix::{storage_buffer, SparseMatrix},
+};
+
+/// Host-side sparse matrix in compressed sparse row format.
+///
+/// The entries of row `i` are `data[row_offsets[i]..row_offsets[i + 1]]`, in
+/// the columns `col_indices` at the same positions, in increasing order. This
+/// is the layout used by `spmv_csr.wgsl`, which reads the row offsets and
+/// column indices from a single buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CSRMatrix {
+    pub num_cols: usize,
+    pub num_rows: usize,
+    pub row_offsets: Vec<u32>,
+    pub col_indices: Vec<u32>,
+    pub data: Vec<f64>,
+}
+
+impl CSRMatrix {
+    pub fn new(
+        num_cols: usize,
+        num_rows: usize,
+        row_offsets: Vec<u32>,
+        col_indices: Vec<u32>,
+        data: Vec<f64>,
+    ) -> Self {
+        assert_eq!(
+            row_offsets.len(),
+            num_rows + 1,
+            "CSR row offsets must hold num_rows + 1 entries"
+        );
+        assert_eq!(
+            col_indices.len(),
+            data.len(),
+            "CSR column indices and data must have the same length"
+        );
+        assert_eq!(
+            *row_offsets.last().unwrap() as usize,
+            data.len(),
+            "the last CSR row offset must be the number of entries"
+        );
+        Self {
+            num_cols,
+            num_rows,
+            row_offsets,
+            col_indices,
+            data,
+        }
+    }
+
+    /// Number of stored entries.
+    pub fn nnz(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Columns and values of the entries of `row`.
+    pub fn row(&self, row: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let range = self.row_offsets[row] as usize..self.row_offsets[row + 1] as usize;
+        self.col_indices[range.clone()]
+            .iter()
+            .map(|&col| col as usize)
+            .zip(self.data[range].iter().copied())
+    }
+
+    /// Builds a matrix from the nonzero entries of each row, in row order.
+    pub(crate) fn from_rows(
+        num_cols: usize,
+        num_rows: usize,
+        rows: impl Iterator<Item = impl Iterator<Item = (usize, f64)>>,
+    ) -> Self {
+        let mut row_offsets = vec![0];
+        let mut col_indices = Vec::new();
+        let mut data = Vec::new();
+        for row in rows {
+            let mut entries: Vec<_> = row.filter(|&(_, value)| value != 0.0).collect();
+            entries.sort_by_key(|&(col, _)| col);
+            for (col, value) in entries {
+                col_indices.push(col as u32);
+                data.push(value);
+            }
+            row_offsets.push(data.len() as u32);
+        }
+        Self::new(num_cols, num_rows, row_offsets, col_indices, data)
+    }
+}
+
+/// Keeps the nonzero entries of the diagonals that lie inside the matrix.
+impl From<&DIAMatrix> for CSRMatrix {
+    fn from(a: &DIAMatrix) -> Self {
+        let rows = (0..a.num_rows).map(|row| {
+            a.offsets.iter().enumerate().filter_map(move |(n, offset)| {
+                let col = row as i64 + *offset as i64;
+                (col >= 0 && col < a.num_cols as i64)
+                    .then(|| (col as usize, a.data[a.num_rows * n + row]))
+            })
+        });
+        Self::from_rows(a.num_cols, a.num_rows, rows)
+    }
+}
+
+/// Drops the padding of the rows.
+impl From<&ELLMatrix> for CSRMatrix {
+    fn from(a: &ELLMatrix) -> Self {
+        let rows = (0..a.num_rows).map(|row| a.row(row));
+        Self::from_rows(a.num_cols, a.num_rows, rows)
+    }
+}
+
+/// Represents a sparse matrix in compressed sparse row format.
+pub struct CSRMatrixDescriptor {
+    pub num_cols: u32,
+    pub num_rows: u32,
+    pub precision: Precision,
+    pub params: wgpu::Buffer,  // num_cols, num_rows
+    pub indices: wgpu::Buffer, // row offsets followed by column indices
+    pub data: wgpu::Buffer,
+}
+
+impl CSRMatrixDescriptor {
+    /// Uploads a host-side matrix to the GPU, rounded to `precision`.
+    pub fn from_matrix(device: &wgpu::Device, a: &CSRMatrix, precision: Precision) -> Self {
+        let (num_cols, num_rows) = (a.num_cols as u32, a.num_rows as u32);
+        Self {
+            num_cols,
+            num_rows,
+            precision,
+            params: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("CSR Matrix Params Buffer"),
+                contents: bytemuck::cast_slice(&[num_cols, num_rows]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            }),
+            // a single buffer, since adapters may only bind four storage buffers
+            indices: storage_buffer(
+                device,
+                "CSR Matrix Indices Buffer",
+                bytemuck::cast_slice(&[&a.row_offsets[..], &a.col_indices].concat()),
+            ),
+            data: storage_buffer(
+                device,
+                "CSR Matrix Data Buffer",
+                &precision.encode(a.data.iter().copied()),
+            ),
+        }
+    }
+}
+
+impl SparseMatrix for CSRMatrixDescriptor {
+    fn num_cols(&self) -> u32 {
+        self.num_cols
+    }
+
+    fn num_rows(&self) -> u32 {
+        self.num_rows
+    }
+
+    fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    fn spmv(&self, device: &wgpu::Device, x: &wgpu::Buffer, y: &wgpu::Buffer) -> Box<dyn Kernel> {
+        Box::new(CSRSpMVKernel::new(device, self, x, y))
+    }
+
+    fn spmv_dots(
+        &self,
+        device: &wgpu::Device,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+        products: &wgpu::Buffer,
+    ) -> Box<dyn Kernel> {
+        Box::new(CSRSpMVKernel::with_dots(device, self, x, y, Some(products)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 4 x 4 tridiagonal matrix with a zero on its superdiagonal.
+    fn tridiagonal() -> DIAMatrix {
+        #[rustfmt::skip]
+        let data = vec![
+            0.0, -1.0, -2.0, -3.0,
+            4.0, 5.0, 6.0, 7.0,
+            1.0, 0.0, 3.0, 0.0,
+        ];
+        DIAMatrix::new(4, 4, vec![-1, 0, 1], data)
+    }
+
+    #[test]
+    fn from_dia() {
+        let a = CSRMatrix::from(&tridiagonal());
+        assert_eq!(a.row_offsets, vec![0, 2, 4, 7, 9]);
+        assert_eq!(a.col_indices, vec![0, 1, 0, 1, 1, 2, 3, 2, 3]);
+        assert_eq!(a.data, vec![4.0, 1.0, -1.0, 5.0, -2.0, 6.0, 3.0, -3.0, 7.0]);
+        assert_eq!(
+            a.row(2).collect::<Vec<_>>(),
+            [(1, -2.0), (2, 6.0), (3, 3.0)]
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let a = CSRMatrix::from(&tridiagonal());
+        assert_eq!(CSRMatrix::from(&ELLMatrix::from(&a)), a);
+        assert_eq!(DIAMatrix::from(&a), tridiagonal());
+    }
+}
@@ -1,15 +1,79 @@
 use wgpu::util::DeviceExt;
+
+use crate::{
+    csr_matrix::CSRMatrix,
+    kernels::{kernel::Kernel, spmv::SpMVKernel},
+    precision::Precision,
+    sparse_matrix::SparseMatrix,
+};
+
+/// Host-side sparse matrix in diagonal format.
+///
+/// `data` stores the `num_diags` diagonals one after the other, each one with
+/// `num_rows` entries, where entry `i` of diagonal `d` multiplies column
+/// `i + offsets[d]`. This is the same layout used by `spmv.wgsl`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DIAMatrix {
+    pub num_cols: usize,
+    pub num_rows: usize,
+    pub offsets: Vec<i32>,
+    pub data: Vec<f64>,
+}
+
+impl DIAMatrix {
+    pub fn new(num_cols: usize, num_rows: usize, offsets: Vec<i32>, data: Vec<f64>) -> Self {
+        assert_eq!(
+            data.len(),
+            offsets.len() * num_rows,
+            "DIA data must hold num_diags * num_rows entries"
+        );
+        Self {
+            num_cols,
+            num_rows,
+            offsets,
+            data,
+        }
+    }
+
+    pub fn num_diags(&self) -> usize {
+        self.offsets.len()
+    }
+}
+
+/// Stores every diagonal that holds an entry, with zeros where the entries
+/// are missing.
+impl From<&CSRMatrix> for DIAMatrix {
+    fn from(a: &CSRMatrix) -> Self {
+        let diagonal = |row: usize, col: usize| col as i32 - row as i32;
+        let mut offsets: Vec<i32> = (0..a.num_rows)
+            .flat_map(|row| a.row(row).map(move |(col, _)| diagonal(row, col)))
+            .collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        let mut data = vec![0.0; offsets.len() * a.num_rows];
+        for row in 0..a.num_rows {
+            for (col, value) in a.row(row) {
+                let n = offsets.binary_search(&diagonal(row, col)).unwrap();
+                data[a.num_rows * n + row] = value;
+            }
+        }
+        Self::new(a.num_cols, a.num_rows, offsets, data)
+    }
+}
+
 /// Represents a sparse matrix in diagonal format.
 pub struct DIAMatrixDescriptor {
     pub num_cols: u32,
     pub num_rows: u32,
     pub num_diags: u32,
+    pub precision: Precision,
     pub params: wgpu::Buffer, //num_cols, num_rows, num_diags
     pub data: wgpu::Buffer,
     pub offsets: wgpu::Buffer,
 }
 
 impl DIAMatrixDescriptor {
+    /// Uploads a single precision matrix.
     pub fn new(
         device: &wgpu::Device,
         num_cols: u32,
@@ -17,11 +81,28 @@ impl DIAMatrixDescriptor {
         num_diags: u32,
         data: &[f32],
         offsets: &[i32],
+    ) -> Self {
+        Self::with_data(
+            device,
+            [num_cols, num_rows, num_diags],
+            Precision::Single,
+            bytemuck::cast_slice(data),
+            offsets,
+        )
+    }
+
+    fn with_data(
+        device: &wgpu::Device,
+        [num_cols, num_rows, num_diags]: [u32; 3],
+        precision: Precision,
+        data: &[u8],
+        offsets: &[i32],
     ) -> Self {
         Self {
             num_cols,
             num_rows,
             num_diags,
+            precision,
             params: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Matrix Params Buffer"),
                 contents: bytemuck::cast_slice(&[num_cols, num_rows, num_diags]),
@@ -29,7 +110,7 @@ impl DIAMatrixDescriptor {
             }),
             data: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Matrix Data Buffer"),
-                contents: bytemuck::cast_slice(data),
+                contents: data,
                 usage: wgpu::BufferUsages::STORAGE,
             }),
             offsets: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -39,4 +120,43 @@ impl DIAMatrixDescriptor {
             }),
         }
     }
+
+    /// Uploads a host-side matrix to the GPU, rounded to `precision`.
+    pub fn from_matrix(device: &wgpu::Device, a: &DIAMatrix, precision: Precision) -> Self {
+        Self::with_data(
+            device,
+            [a.num_cols as u32, a.num_rows as u32, a.num_diags() as u32],
+            precision,
+            &precision.encode(a.data.iter().copied()),
+            &a.offsets,
+        )
+    }
+}
+
+impl SparseMatrix for DIAMatrixDescriptor {
+    fn num_cols(&self) -> u32 {
+        self.num_cols
+    }
+
+    fn num_rows(&self) -> u32 {
+        self.num_rows
+    }
+
+    fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    fn spmv(&self, device: &wgpu::Device, x: &wgpu::Buffer, y: &wgpu::Buffer) -> Box<dyn Kernel> {
+        Box::new(SpMVKernel::new(device, self, x, y))
+    }
+
+    fn spmv_dots(
+        &self,
+        device: &wgpu::Device,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+        products: &wgpu::Buffer,
+    ) -> Box<dyn Kernel> {
+        Box::new(SpMVKernel::with_dots(device, self, x, y, Some(products)))
+    }
 }
@@ -0,0 +1,153 @@
+//! Integral quantities of the solution, tracked over time.
+//!
+//! The total heat `∫ u` is what a run of uniform material with insulated
+//! edges conserves. With the zero Dirichlet boundary of the solvers heat flows
+//! out through the edges, so the total heat and the L² norm can only decrease;
+//! an increase means the solver is misbehaving.
+//!
+//! With materials the conserved energy is `∫ ρ c u` instead. Neither the total
+//! heat nor the L² norm is weighted by the heat capacity `ρ c`, so either can
+//! grow when heat flows into a material of lower heat capacity.
+//!
+//! On a masked domain the walls are held at zero and add nothing to the sums,
+//! while the minimum, maximum and mean are taken over the active points only.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    field::Field,
+    heat_equation::HeatEquation,
+    mask::{Cell, Mask},
+};
+
+/// Summary of the solution after a time step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diagnostics {
+    pub iteration: usize,
+    pub time: f64,
+    /// `h² Σ u`, which approximates the integral of `u` over the unit square.
+    pub total_heat: f64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// `sqrt(h² Σ u²)`, which approximates the L² norm over the unit square.
+    pub l2: f64,
+}
+
+impl Diagnostics {
+    /// Builds the diagnostics of an `n x n` field from its sum, minimum, maximum
+    /// and sum of squares over its `active` points.
+    pub fn from_reductions(
+        iteration: usize,
+        time: f64,
+        n: usize,
+        active: usize,
+        reductions: [f64; 4],
+    ) -> Self {
+        let [sum, min, max, sum_of_squares] = reductions;
+        let h = HeatEquation::grid_spacing(n);
+        Self {
+            iteration,
+            time,
+            total_heat: h * h * sum,
+            min,
+            max,
+            mean: sum / active as f64,
+            l2: (h * h * sum_of_squares).sqrt(),
+        }
+    }
+
+    /// Computes the diagnostics of a field on the host, in double precision,
+    /// over the domain of `mask` if there is one.
+    pub fn from_field(iteration: usize, time: f64, field: &Field, mask: Option<&Mask>) -> Self {
+        assert_eq!(field.width, field.height, "the grid must be square");
+        let mut reductions = [0.0, f64::INFINITY, f64::NEG_INFINITY, 0.0];
+        let mut active = 0;
+        for (i, &u) in field.data.iter().enumerate() {
+            if mask.is_some_and(|mask| mask.cells[i] != Cell::Active) {
+                continue;
+            }
+            active += 1;
+            let u = u as f64;
+            reductions[0] += u;
+            reductions[1] = reductions[1].min(u);
+            reductions[2] = reductions[2].max(u);
+            reductions[3] += u * u;
+        }
+        Self::from_reductions(iteration, time, field.width, active, reductions)
+    }
+}
+
+/// Writes diagnostics to a CSV file, one row per time step.
+pub struct DiagnosticsLog<W: Write> {
+    writer: W,
+}
+
+impl DiagnosticsLog<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> DiagnosticsLog<W> {
+    pub const HEADER: &'static str = "step,time,total_heat,min,max,mean,l2";
+
+    /// Writes the header line to `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "{}", Self::HEADER)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, d: &Diagnostics) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{}",
+            d.iteration, d.time, d.total_heat, d.min, d.max, d.mean, d.l2
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_field() {
+        // h = 1/3
+        let field = Field::new(2, 2, vec![1.0, -2.0, 3.0, 2.0]);
+        let d = Diagnostics::from_field(4, 0.5, &field, None);
+        assert_eq!((d.iteration, d.time), (4, 0.5));
+        assert!((d.total_heat - 4.0 / 9.0).abs() < 1e-12);
+        assert_eq!((d.min, d.max, d.mean), (-2.0, 3.0, 1.0));
+        assert!((d.l2 - (18.0f64 / 9.0).sqrt()).abs() < 1e-12);
+
+        // walls count neither in the extremes nor in the mean
+        let field = Field::new(2, 2, vec![1.0, 0.0, 3.0, 2.0]);
+        let cells = vec![Cell::Active, Cell::Dirichlet, Cell::Active, Cell::Active];
+        let d = Diagnostics::from_field(4, 0.5, &field, Some(&Mask::new(2, 2, cells)));
+        assert!((d.total_heat - 6.0 / 9.0).abs() < 1e-12);
+        assert_eq!((d.min, d.max, d.mean), (1.0, 3.0, 2.0));
+    }
+
+    #[test]
+    fn csv_rows() {
+        let mut log = DiagnosticsLog::new(Vec::new()).unwrap();
+        let field = Field::new(1, 1, vec![2.0]);
+        log.write(&Diagnostics::from_field(0, 0.0, &field, None))
+            .unwrap();
+        log.write(&Diagnostics::from_field(1, 0.25, &field, None))
+            .unwrap();
+        let csv = String::from_utf8(log.writer).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "step,time,total_heat,min,max,mean,l2");
+        assert_eq!(lines[2], "1,0.25,0.5,2,2,2,1");
+        assert_eq!(lines.len(), 3);
+    }
+}
@@ -0,0 +1,169 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    csr_matrix::CSRMatrix,
+    kernels::{kernel::Kernel, spmv_ell::ELLSpMVKernel},
+    precision::Precision,
+    sparse_matrix::{storage_buffer, SparseMatrix},
+};
+
+/// Host-side sparse matrix in ELLPACK format.
+///
+/// Every row holds `width` entries, the longest row of the matrix, with the
+/// shorter rows padded by entries in column `-1`. Entry `k` of row `i` is
+/// stored at `k * num_rows + i` of `col_indices` and `data`, so that
+/// neighbouring rows read neighbouring values, like the diagonals of
+/// [`DIAMatrix`](crate::dia_matrix::DIAMatrix). This is the same layout used by
+/// `spmv_ell.wgsl`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ELLMatrix {
+    pub num_cols: usize,
+    pub num_rows: usize,
+    pub width: usize,
+    pub col_indices: Vec<i32>,
+    pub data: Vec<f64>,
+}
+
+impl ELLMatrix {
+    /// Column of the padding entries.
+    pub const PADDING: i32 = -1;
+
+    pub fn new(
+        num_cols: usize,
+        num_rows: usize,
+        width: usize,
+        col_indices: Vec<i32>,
+        data: Vec<f64>,
+    ) -> Self {
+        assert_eq!(
+            data.len(),
+            width * num_rows,
+            "ELL data must hold width * num_rows entries"
+        );
+        assert_eq!(
+            col_indices.len(),
+            data.len(),
+            "ELL column indices and data must have the same length"
+        );
+        Self {
+            num_cols,
+            num_rows,
+            width,
+            col_indices,
+            data,
+        }
+    }
+
+    /// Columns and values of the entries of `row`, without the padding.
+    pub fn row(&self, row: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        (0..self.width).filter_map(move |k| {
+            let index = k * self.num_rows + row;
+            let col = self.col_indices[index];
+            (col != Self::PADDING).then(|| (col as usize, self.data[index]))
+        })
+    }
+}
+
+/// Pads every row to the longest one.
+impl From<&CSRMatrix> for ELLMatrix {
+    fn from(a: &CSRMatrix) -> Self {
+        let width = a
+            .row_offsets
+            .windows(2)
+            .map(|w| (w[1] - w[0]) as usize)
+            .max()
+            .unwrap_or(0);
+        let mut col_indices = vec![Self::PADDING; width * a.num_rows];
+        let mut data = vec![0.0; width * a.num_rows];
+        for row in 0..a.num_rows {
+            for (k, (col, value)) in a.row(row).enumerate() {
+                col_indices[k * a.num_rows + row] = col as i32;
+                data[k * a.num_rows + row] = value;
+            }
+        }
+        Self::new(a.num_cols, a.num_rows, width, col_indices, data)
+    }
+}
+
+/// Represents a sparse matrix in ELLPACK format.
+pub struct ELLMatrixDescriptor {
+    pub num_cols: u32,
+    pub num_rows: u32,
+    pub width: u32,
+    pub precision: Precision,
+    pub params: wgpu::Buffer, // num_cols, num_rows, width
+    pub col_indices: wgpu::Buffer,
+    pub data: wgpu::Buffer,
+}
+
+impl ELLMatrixDescriptor {
+    /// Uploads a host-side matrix to the GPU, rounded to `precision`.
+    pub fn from_matrix(device: &wgpu::Device, a: &ELLMatrix, precision: Precision) -> Self {
+        let [num_cols, num_rows, width] = [a.num_cols, a.num_rows, a.width].map(|v| v as u32);
+        Self {
+            num_cols,
+            num_rows,
+            width,
+            precision,
+            params: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("ELL Matrix Params Buffer"),
+                contents: bytemuck::cast_slice(&[num_cols, num_rows, width]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            }),
+            col_indices: storage_buffer(
+                device,
+                "ELL Matrix Column Indices Buffer",
+                bytemuck::cast_slice(&a.col_indices),
+            ),
+            data: storage_buffer(
+                device,
+                "ELL Matrix Data Buffer",
+                &precision.encode(a.data.iter().copied()),
+            ),
+        }
+    }
+}
+
+impl SparseMatrix for ELLMatrixDescriptor {
+    fn num_cols(&self) -> u32 {
+        self.num_cols
+    }
+
+    fn num_rows(&self) -> u32 {
+        self.num_rows
+    }
+
+    fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    fn spmv(&self, device: &wgpu::Device, x: &wgpu::Buffer, y: &wgpu::Buffer) -> Box<dyn Kernel> {
+        Box::new(ELLSpMVKernel::new(device, self, x, y))
+    }
+
+    fn spmv_dots(
+        &self,
+        device: &wgpu::Device,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+        products: &wgpu::Buffer,
+    ) -> Box<dyn Kernel> {
+        Box::new(ELLSpMVKernel::with_dots(device, self, x, y, Some(products)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pads_rows() {
+        // rows with 1, 0 and 2 entries
+        let a = CSRMatrix::new(3, 3, vec![0, 1, 1, 3], vec![2, 0, 1], vec![1.0, 2.0, 3.0]);
+        let ell = ELLMatrix::from(&a);
+        assert_eq!(ell.width, 2);
+        assert_eq!(ell.col_indices, vec![2, -1, 0, -1, -1, 1]);
+        assert_eq!(ell.data, vec![1.0, 0.0, 2.0, 0.0, 0.0, 3.0]);
+        assert_eq!(ell.row(1).count(), 0);
+    }
+}
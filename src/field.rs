@@ -0,0 +1,29 @@
+/// Snapshot of the temperature field on the host.
+///
+/// Values are stored in row-major order, i.e. the value at grid point `(x, y)`
+/// is `data[y * width + x]`, the same layout used by the GPU buffers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<f32>,
+}
+
+impl Field {
+    pub fn new(width: usize, height: usize, data: Vec<f32>) -> Self {
+        assert_eq!(
+            data.len(),
+            width * height,
+            "field data must have width * height entries"
+        );
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width + x]
+    }
+}
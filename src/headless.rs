@@ -0,0 +1,195 @@
+use std::io;
+
+use crate::{
+    checkpoint::Checkpoint,
+    config::Config,
+    heat_equation::HeatEquation,
+    iterative_refinement::RefinementLog,
+    probes::{ProbeLog, ProbeSampler},
+    profiler::Profiler,
+};
+
+/// Runs the simulation without a window, for batch jobs and machines without a display.
+pub struct Headless {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    heat_eqn: HeatEquation,
+    // the heat equation solver always writes its output to a texture
+    _texture: wgpu::Texture,
+}
+
+impl Headless {
+    pub async fn new(config: &Config) -> Self {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await
+            .expect("Failed to find an appropriate adapter");
+        println!("Adapter: {:?}", adapter.get_info());
+        let options = config
+            .solver_options(adapter.features())
+            .expect("Failed to load the mask or the materials");
+        println!("Precision: {:?}", options.precision);
+        let profile = config.profile_report();
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features: options.precision.features()
+                        | if profile.is_some() {
+                            Profiler::features(adapter.features())
+                        } else {
+                            wgpu::Features::empty()
+                        },
+                    limits: wgpu::Limits::default(),
+                    label: None,
+                },
+                None, // Trace path
+            )
+            .await
+            .unwrap();
+
+        let n = config.n;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Heat Equation Texture"),
+            size: wgpu::Extent3d {
+                width: n,
+                height: n,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        });
+        let input_data = config
+            .initial
+            .field(n as usize)
+            .expect("Failed to load the initial condition")
+            .data;
+        let mut heat_eqn = HeatEquation::with_options(
+            &device,
+            config.alpha,
+            n as usize,
+            config.dt,
+            &input_data,
+            &texture,
+            options,
+        );
+        if let Some(path) = &config.restart {
+            let checkpoint = Checkpoint::load(path).expect("Failed to read the checkpoint");
+            heat_eqn
+                .restore(&device, &queue, &checkpoint)
+                .expect("Failed to restore the checkpoint");
+        }
+        heat_eqn.profile(&device, &queue, profile.as_ref());
+
+        Self {
+            device,
+            queue,
+            heat_eqn,
+            _texture: texture,
+        }
+    }
+
+    /// Computes `config.steps` time steps, recording the initial condition and
+    /// every step in between to the outputs requested by `config`, and keeping
+    /// the checkpoint, diagnostics, probe and refinement logs up to date. The
+    /// profile, if any, is reported at the end.
+    pub fn run(&mut self, config: &Config) -> io::Result<()> {
+        let mut outputs = config.outputs()?;
+        let checkpointer = config.checkpointer();
+        let mut diagnostics = config.diagnostics_log()?;
+        let mut probes = config.probe_log()?.map(|log| {
+            (
+                ProbeSampler::new(&self.device, &self.heat_eqn, &config.probes),
+                log,
+            )
+        });
+        let mut refinement = config.refinement_log()?;
+        let ctx = (&self.device, &self.queue);
+        outputs.record(&self.heat_eqn, ctx)?;
+        if let Some(log) = &mut diagnostics {
+            self.heat_eqn
+                .track_diagnostics(&self.device, &self.queue, true);
+            log.write(self.heat_eqn.last_diagnostics().unwrap())?;
+        }
+        if let Some((sampler, log)) = &mut probes {
+            self.sample_probes(sampler, log)?;
+        }
+        // the logs sample every step, the outputs and checkpoints the steps
+        // they are due at
+        let per_step = diagnostics.is_some() || probes.is_some() || refinement.is_some();
+        let mut remaining = config.steps;
+        while remaining > 0 {
+            let iteration = self.heat_eqn.iteration();
+            let steps = [
+                Some(if per_step { 1 } else { config.steps_per_frame }),
+                Some(remaining),
+                outputs.steps_until_due(iteration),
+                checkpointer.as_ref().map(|c| c.steps_until_due(iteration)),
+            ]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap();
+            self.heat_eqn
+                .compute_steps(&self.device, &self.queue, steps);
+            remaining -= steps;
+            outputs.record(&self.heat_eqn, ctx)?;
+            if let Some(checkpointer) = &checkpointer {
+                checkpointer.update(&self.heat_eqn, &self.device, &self.queue)?;
+            }
+            if let Some(log) = &mut diagnostics {
+                log.write(self.heat_eqn.last_diagnostics().unwrap())?;
+            }
+            if let Some((sampler, log)) = &mut probes {
+                self.sample_probes(sampler, log)?;
+            }
+            if let Some(log) = &mut refinement {
+                self.write_refinement(log)?;
+            }
+        }
+        if let Some(log) = &mut diagnostics {
+            log.flush()?;
+        }
+        if let Some((_, log)) = &mut probes {
+            log.flush()?;
+        }
+        if let Some(log) = &mut refinement {
+            log.flush()?;
+        }
+        if let (Some(report), Some(profiler)) = (config.profile_report(), self.heat_eqn.profiler())
+        {
+            report.write(profiler)?;
+        }
+        outputs.finish()
+    }
+
+    fn write_refinement<W: io::Write>(&self, log: &mut RefinementLog<W>) -> io::Result<()> {
+        match self.heat_eqn.refinement_report() {
+            Some(report) => log.write(self.heat_eqn.iteration(), report),
+            None => Ok(()),
+        }
+    }
+
+    fn sample_probes<W: io::Write>(
+        &self,
+        sampler: &ProbeSampler,
+        log: &mut ProbeLog<W>,
+    ) -> io::Result<()> {
+        let values = sampler
+            .sample(&self.device, &self.queue, &self.heat_eqn)
+            .map_err(io::Error::other)?;
+        let step = self.heat_eqn.iteration();
+        log.write(step, step as f64 * self.heat_eqn.dt() as f64, &values)
+    }
+}
@@ -1,21 +1,129 @@
-use std::rc::Rc;
+use std::{future::Future, io, rc::Rc};
 
 use wgpu::util::DeviceExt;
 
 use crate::{
-    conjugate_gradient::{CGBuffers, CG},
-    dia_matrix::DIAMatrixDescriptor,
-    kernels::{kernel::Kernel, spmv::SpMVKernel, write_to_texture::WriteToTextureKernel},
+    checkpoint::{self, BoundaryCondition, Checkpoint, CheckpointError, Parameters, Scheme},
+    conjugate_gradient::{CGBuffers, CGVariant, CG},
+    coo_matrix::COOMatrix,
+    dia_matrix::{DIAMatrix, DIAMatrixDescriptor},
+    diagnostics::Diagnostics,
+    field::Field,
+    iterative_refinement::{
+        IterativeRefinement, RefinementBuffers, RefinementOptions, RefinementReport,
+    },
+    kernels::{
+        convert::ConvertKernel, diagnostics::DiagnosticsKernel, dot::Summation, kernel::Kernel,
+        write_to_texture::WriteToTextureKernel,
+    },
+    mask::{Cell, Mask},
+    material::{interface_conductivity, MaterialMap},
+    precision::Precision,
+    profiler::{encode_kernels, ProfileReport, Profiler},
+    solver::Solver,
+    sparse_matrix::SparseMatrix,
+    stencil_operator::{Stencil, StencilOperator},
 };
 
+/// Options of the GPU solver that do not change the discretization.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolverOptions {
+    /// Accumulation of the dot products of the conjugate gradient solver,
+    /// when it runs in single precision.
+    pub summation: Summation,
+    /// Formulation of the conjugate gradient iteration. Only
+    /// [`CGVariant::Standard`] supports compensated sums.
+    pub cg: CGVariant,
+    /// Precision of the solver vectors and matrices. The device must have
+    /// been created with the features it needs.
+    pub precision: Precision,
+    /// Solve each step by mixed-precision iterative refinement: single
+    /// precision CG corrections of a solution kept in `precision`, which
+    /// must then be double or double-float.
+    pub refinement: Option<RefinementOptions>,
+    /// Apply the matrices as stencils on the fly, with a
+    /// [`StencilOperator`], rather than reading them from memory.
+    pub matrix_free: bool,
+    /// Solve only over the active cells of an `n * n` mask, which cannot be
+    /// combined with `matrix_free`.
+    pub mask: Option<Mask>,
+    /// Materials of the `n * n` grid points, which replace the diffusivity
+    /// `alpha`. Cannot be combined with `matrix_free` either.
+    pub materials: Option<MaterialMap>,
+}
+
+/// Solver of the Crank-Nicolson system for the new solution.
+enum LinearSolver {
+    CG(CG),
+    Refinement(Box<IterativeRefinement>),
+}
+
+impl LinearSolver {
+    /// Records the solve into `encoder`. Iterative refinement, which reads
+    /// its residuals back after every correction, submits the work recorded
+    /// so far and solves right away instead, returning its report.
+    fn solve(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        profiler: Option<&Profiler>,
+    ) -> Option<RefinementReport> {
+        match self {
+            LinearSolver::CG(cg) => {
+                cg.encode(encoder, profiler);
+                None
+            }
+            LinearSolver::Refinement(refinement) => {
+                let recorded = std::mem::replace(encoder, time_step_encoder(device));
+                queue.submit(Some(recorded.finish()));
+                Some(refinement.run(device, queue, profiler))
+            }
+        }
+    }
+}
+
+fn time_step_encoder(device: &wgpu::Device) -> wgpu::CommandEncoder {
+    device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Time Step Encoder"),
+    })
+}
+
+/// Solution in the solver precision, when it is not single precision.
+///
+/// The solver works on `x` and `x_`, which are rounded to `u` and `u_` after
+/// every step for the rest of the program.
+struct PreciseSolution {
+    x: wgpu::Buffer,                 // solution at even iterations
+    x_: wgpu::Buffer,                // solution at odd iterations
+    convert_forward: ConvertKernel,  // x_ -> u_
+    convert_backward: ConvertKernel, // x -> u
+}
+
 pub struct HeatEquation {
-    cg_forward: CG,                    // CG method for forward mode (tmp -> u_)
-    cg_backward: CG,                   // CG method for backward mode (tmp -> u)
-    initial_spmv_forward: SpMVKernel,  // Initial SpMV kernel for forward mode
-    initial_spmv_backward: SpMVKernel, // Initial SpMV kernel for backward mode
+    solver_forward: LinearSolver, // linear solver for forward mode (tmp -> u_)
+    solver_backward: LinearSolver, // linear solver for backward mode (tmp -> u)
+    last_refinement: Option<RefinementReport>, // report of the last refined step
+    initial_spmv_forward: Box<dyn Kernel>, // Initial SpMV kernel for forward mode
+    initial_spmv_backward: Box<dyn Kernel>, // Initial SpMV kernel for backward mode
     write_to_texture_forward: WriteToTextureKernel, // Write to texture kernel for forward mode
     write_to_texture_backward: WriteToTextureKernel, // Write to texture kernel for backward mode
-    iteration: usize,                  // current iteration
+    diagnostics_forward: DiagnosticsKernel, // Diagnostics of u_
+    diagnostics_backward: DiagnosticsKernel, // Diagnostics of u
+    diagnostics_output: wgpu::Buffer, // sum, min, max and sum of squares
+    _domain: Option<wgpu::Buffer>, // mask of the diagnostics, when masked
+    last_diagnostics: Option<Diagnostics>, // of the last step, when tracked
+    profiler: Option<Profiler>,   // kernel timings, when profiled
+    precise: Option<PreciseSolution>, // solver copy of the solution, unless f32
+    precision: Precision,         // precision of the solver
+    mask: Option<Mask>,           // domain of the solver, when not the whole grid
+    materials: u64,               // fingerprint of the material map, 0 without one
+    u: wgpu::Buffer,              // solution at even iterations
+    u_: wgpu::Buffer,             // solution at odd iterations
+    iteration: usize,             // current iteration
+    n: usize,                     // grid size (n x n)
+    alpha: f32,                   // thermal diffusivity
+    dt: f32,                      // time step
 }
 
 impl HeatEquation {
@@ -27,8 +135,56 @@ impl HeatEquation {
         u0: &[f32],
         texture: &wgpu::Texture,
     ) -> Self {
-        let a = Self::a_matrix(device, alpha, n, dt);
-        let b = Self::b_matrix(device, alpha, n, dt);
+        Self::with_options(device, alpha, n, dt, u0, texture, SolverOptions::default())
+    }
+
+    pub fn with_options(
+        device: &wgpu::Device,
+        alpha: f32,
+        n: usize,
+        dt: f32,
+        u0: &[f32],
+        texture: &wgpu::Texture,
+        options: SolverOptions,
+    ) -> Self {
+        let precision = options.precision;
+        assert!(
+            !(options.matrix_free && (options.mask.is_some() || options.materials.is_some())),
+            "masks and materials need stored matrices"
+        );
+        let stencils = Self::stencils(alpha, n, dt);
+        let matrices = match (&options.materials, &options.mask) {
+            (Some(materials), mask) => {
+                Some(Self::material_matrices(n, dt, materials, mask.as_ref()))
+            }
+            (None, Some(mask)) => Some(Self::masked_matrices(alpha, n, dt, mask)),
+            (None, None) => None,
+        };
+        // 0 for A and 1 for B
+        let upload = |index: usize, precision| -> Box<dyn SparseMatrix> {
+            if options.matrix_free {
+                Box::new(StencilOperator::new(
+                    device,
+                    stencils[index],
+                    n as u32,
+                    n as u32,
+                    precision,
+                ))
+            } else {
+                let a = match &matrices {
+                    Some(matrices) => DIAMatrix::from(&matrices[index]),
+                    None => DIAMatrix::from(&stencils[index].to_matrix(n, n)),
+                };
+                Box::new(DIAMatrixDescriptor::from_matrix(device, &a, precision))
+            }
+        };
+        let a = upload(0, precision);
+        let b = upload(1, precision);
+        let mut u0 = u0.to_vec();
+        if let Some(mask) = &options.mask {
+            mask.apply(&mut u0);
+        }
+        let u0 = u0.as_slice();
         let u = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("U Vector"),
             contents: bytemuck::cast_slice(u0),
@@ -45,163 +201,913 @@ impl HeatEquation {
                 | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+        let precise = (precision != Precision::Single).then(|| {
+            let x = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("X Vector"),
+                contents: &precision.encode(u0.iter().map(|&v| v as f64)),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            });
+            let x_ = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("X_ Vector"),
+                size: (n * n * precision.size()) as u64,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            PreciseSolution {
+                convert_forward: ConvertKernel::new(device, precision, &x_, &u_),
+                convert_backward: ConvertKernel::new(device, precision, &x, &u),
+                x,
+                x_,
+            }
+        });
+        // the solver vectors, in the solver precision
+        let (x, x_) = match &precise {
+            Some(precise) => (&precise.x, &precise.x_),
+            None => (&u, &u_),
+        };
         let tmp = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("tmp Vector"),
-            size: size_in_bytes,
+            size: (n * n * precision.size()) as u64,
             usage: wgpu::BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
 
-        let cg_buffers = Rc::new(CGBuffers::new(device, size_in_bytes));
-        let cg_forward = CG::new(device, cg_buffers.clone(), &a, &tmp, &u_);
-        let cg_backward = CG::new(device, cg_buffers, &a, &tmp, &u);
-        let initial_spmv_forward = SpMVKernel::new(device, &b, &u, &tmp);
-        let initial_spmv_backward = SpMVKernel::new(device, &b, &u_, &tmp);
+        let (solver_forward, solver_backward) = match options.refinement {
+            Some(refinement) => {
+                assert!(
+                    precision != Precision::Single,
+                    "iterative refinement needs a double precision solution"
+                );
+                let a32 = upload(0, Precision::Single);
+                let buffers = Rc::new(RefinementBuffers::new(device, precision, n * n, options.cg));
+                let solver = |x| {
+                    LinearSolver::Refinement(Box::new(IterativeRefinement::new(
+                        device,
+                        buffers.clone(),
+                        a.as_ref(),
+                        a32.as_ref(),
+                        &tmp,
+                        x,
+                        options.summation,
+                        refinement,
+                    )))
+                };
+                (solver(x_), solver(x))
+            }
+            None => {
+                let cg_buffers = Rc::new(CGBuffers::new(device, precision, n * n, options.cg));
+                let solver = |x| {
+                    LinearSolver::CG(CG::new(
+                        device,
+                        cg_buffers.clone(),
+                        a.as_ref(),
+                        &tmp,
+                        x,
+                        options.summation,
+                    ))
+                };
+                (solver(x_), solver(x))
+            }
+        };
+        let initial_spmv_forward = b.spmv(device, x, &tmp);
+        let initial_spmv_backward = b.spmv(device, x_, &tmp);
         let write_to_texture_forward = WriteToTextureKernel::new(device, &u_, texture);
         let write_to_texture_backward = WriteToTextureKernel::new(device, &u, texture);
+        let diagnostics_output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Diagnostics output"),
+            size: 4 * std::mem::size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        // 1 for the points of the domain, so that the diagnostics skip the walls
+        let domain = options.mask.as_ref().map(|mask| {
+            let active: Vec<u32> = mask
+                .cells
+                .iter()
+                .map(|&c| (c == Cell::Active) as u32)
+                .collect();
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Domain mask"),
+                contents: bytemuck::cast_slice(&active),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        });
+        let diagnostics_forward =
+            DiagnosticsKernel::new(device, &u_, domain.as_ref(), &diagnostics_output);
+        let diagnostics_backward =
+            DiagnosticsKernel::new(device, &u, domain.as_ref(), &diagnostics_output);
 
         Self {
-            cg_forward,
-            cg_backward,
+            solver_forward,
+            solver_backward,
+            last_refinement: None,
             initial_spmv_forward,
             initial_spmv_backward,
             write_to_texture_forward,
             write_to_texture_backward,
+            diagnostics_forward,
+            diagnostics_backward,
+            diagnostics_output,
+            _domain: domain,
+            last_diagnostics: None,
+            profiler: None,
+            precise,
+            precision,
+            mask: options.mask,
+            materials: options
+                .materials
+                .as_ref()
+                .map_or(0, MaterialMap::fingerprint),
+            u,
+            u_,
             iteration: 0,
+            n,
+            alpha,
+            dt,
         }
     }
 
-    fn a_matrix(device: &wgpu::Device, alpha: f32, n: usize, dt: f32) -> DIAMatrixDescriptor {
-        let m = n * n;
-        let num_cols = m;
-        let num_rows = m;
-        let num_diags = 5;
-        let h = 1.0 / (n as f32);
-        let gamma: f32 = alpha * dt / (2.0 * h * h);
-        // describes discretization matrix A
-        // obtained from discretizing U(x,y) in row-major order
-        let f = |i: usize, j: usize| -> f32 {
-            if i == j {
-                1.0 + 4.0 * gamma
-            } else if (i == j + 1 && i % n != 0)
-                || (j == i + 1 && j % n != 0)
-                || (i == j + n || j == i + n)
-            {
-                -gamma
-            } else {
-                0.0
-            }
-        };
-        let n = n as i32;
-        let offsets = vec![-n, -1, 0, 1, n];
-        let mut data: Vec<f32> = Vec::with_capacity(num_diags * m);
-        for offset in offsets.iter() {
-            for i in 0..m {
-                let j = i as i32 + offset;
-                if j >= 0 && j < m as i32 {
-                    data.push(f(i, j as usize));
-                } else {
-                    data.push(0.0);
-                }
-            }
-        }
-        DIAMatrixDescriptor::new(
-            device,
-            num_cols as u32,
-            num_rows as u32,
-            num_diags as u32,
-            &data,
-            &offsets,
-        )
+    /// Number of time steps computed so far.
+    pub fn iteration(&self) -> usize {
+        self.iteration
     }
 
-    /// Same as `a_matrix`, but gamma has a negative sign
-    fn b_matrix(device: &wgpu::Device, alpha: f32, n: usize, dt: f32) -> DIAMatrixDescriptor {
-        let m = n * n;
-        let num_cols = m;
-        let num_rows = m;
-        let num_diags = 5;
-        let h = 1.0 / (n as f32);
-        let gamma: f32 = -alpha * dt / (2.0 * h * h);
-        // describes discretization matrix A
-        // obtained from discretizing U(x,y) in row-major order
-        let f = |i: usize, j: usize| -> f32 {
-            if i == j {
-                1.0 + 4.0 * gamma
-            } else if (i == j + 1 && i % n != 0)
-                || (j == i + 1 && j % n != 0)
-                || (i == j + n || j == i + n)
-            {
-                -gamma
-            } else {
-                0.0
-            }
-        };
-        let n = n as i32;
-        let offsets = vec![-n, -1, 0, 1, n];
-        let mut data: Vec<f32> = Vec::with_capacity(num_diags * m);
-        for offset in offsets.iter() {
-            for i in 0..m {
-                let j = i as i32 + offset;
-                if j >= 0 && j < m as i32 {
-                    data.push(f(i, j as usize));
-                } else {
-                    data.push(0.0);
-                }
-            }
+    /// Time step.
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    /// Simulation time of the current solution.
+    pub fn time(&self) -> f32 {
+        self.iteration as f32 * self.dt
+    }
+
+    /// Number of grid points along each axis.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// Precision of the solver.
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Mask of the domain, when the solver does not cover the whole grid.
+    pub fn mask(&self) -> Option<&Mask> {
+        self.mask.as_ref()
+    }
+
+    /// Report of the iterative refinement of the last time step, when the
+    /// solver refines its solutions.
+    pub fn refinement_report(&self) -> Option<&RefinementReport> {
+        self.last_refinement.as_ref()
+    }
+
+    /// Buffer holding the current solution.
+    fn current(&self) -> &wgpu::Buffer {
+        if self.iteration.is_multiple_of(2) {
+            &self.u
+        } else {
+            &self.u_
         }
-        DIAMatrixDescriptor::new(
-            device,
-            num_cols as u32,
-            num_rows as u32,
-            num_diags as u32,
-            &data,
-            &offsets,
-        )
     }
 
-    pub fn compute_step(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        // First step: tmp = B * u_old
+    /// Both solution buffers, indexed by the parity of the iteration whose
+    /// solution they hold.
+    pub(crate) fn solution_buffers(&self) -> [&wgpu::Buffer; 2] {
+        [&self.u, &self.u_]
+    }
+
+    /// Reduces the current solution on the GPU and copies the four results
+    /// back to the host, blocking until they are available.
+    pub fn diagnostics(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Diagnostics {
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Initial SpMV Encoder (tmp = B*U)"),
+            label: Some("Diagnostics Encoder"),
         });
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Initial SpMV Compute Pass (tmp = B*U)"),
+            label: Some("Diagnostics Compute Pass"),
             timestamp_writes: None,
         });
-        if self.iteration % 2 == 0 {
-            self.initial_spmv_forward.add_to_pass(&mut compute_pass);
+        if self.iteration.is_multiple_of(2) {
+            self.diagnostics_backward.add_to_pass(&mut compute_pass);
         } else {
-            self.initial_spmv_backward.add_to_pass(&mut compute_pass);
-        };
-
+            self.diagnostics_forward.add_to_pass(&mut compute_pass);
+        }
         drop(compute_pass);
         queue.submit(Some(encoder.finish()));
-        // Now we can treat the vector tmp as the "b" in A u_new = b
-        // for our Conjugate Gradient solver
-        if self.iteration % 2 == 0 {
-            self.cg_forward.run(device, queue);
+        let values = read_buffer(device, queue, &self.diagnostics_output)
+            .expect("failed to map the diagnostics buffer");
+        let reductions = [0, 1, 2, 3].map(|i| values[i] as f64);
+        let time = self.iteration as f64 * self.dt as f64;
+        let active = self
+            .mask
+            .as_ref()
+            .map_or(self.n * self.n, Mask::active_cells);
+        Diagnostics::from_reductions(self.iteration, time, self.n, active, reductions)
+    }
+
+    /// Starts or stops computing the diagnostics after every time step.
+    ///
+    /// Starting computes those of the current solution right away. Each
+    /// tracked step waits for the GPU to finish, so tracking slows the solver
+    /// down a little. Only the last diagnostics are kept: callers that need
+    /// the time series, like `DiagnosticsLog`, read them after every step.
+    pub fn track_diagnostics(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, track: bool) {
+        self.last_diagnostics = if track {
+            Some(self.diagnostics(device, queue))
         } else {
-            self.cg_backward.run(device, queue);
+            None
+        };
+    }
+
+    /// Diagnostics of the last time step, when tracked.
+    pub fn last_diagnostics(&self) -> Option<&Diagnostics> {
+        self.last_diagnostics.as_ref()
+    }
+
+    /// Starts timing the kernels of every time step for `report`, or stops
+    /// with `None`. The spans of the kernels are only kept when the report
+    /// includes a trace.
+    ///
+    /// Profiled steps are submitted one at a time, and each waits for the
+    /// GPU to finish, so profiling slows the solver down.
+    pub fn profile(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        report: Option<&ProfileReport>,
+    ) {
+        self.profiler = report.map(|report| Profiler::new(device, queue, report.trace.is_some()));
+    }
+
+    /// Timings of the steps computed since `profile` was called.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Replaces the current solution with `data`, in row-major order.
+    ///
+    /// Only the contents of the solution buffer change, so all kernels, bind groups
+    /// and CG buffers are kept. The texture is refreshed right away. Points
+    /// outside the domain of the mask stay at zero.
+    pub fn set_field(&self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[f32]) {
+        assert_eq!(data.len(), self.n * self.n, "field must have n * n entries");
+        let values: Vec<f64> = data.iter().map(|&v| v as f64).collect();
+        self.write_solution(queue, self.iteration % 2, 0, &values);
+        self.refresh_texture(device, queue);
+    }
+
+    /// Replaces a rectangular region of the current solution.
+    ///
+    /// `data` holds the region in row-major order, `width` values per row, and
+    /// `origin` is the grid point `(x, y)` of its first value. Points outside
+    /// the domain of the mask stay at zero.
+    pub fn set_region(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        origin: (usize, usize),
+        width: usize,
+        data: &[f32],
+    ) {
+        let (x0, y0) = origin;
+        assert!(
+            width > 0 && data.len().is_multiple_of(width),
+            "region data must be a whole number of rows"
+        );
+        let height = data.len() / width;
+        assert!(
+            x0 + width <= self.n && y0 + height <= self.n,
+            "region must lie inside the grid"
+        );
+        for (row, values) in data.chunks_exact(width).enumerate() {
+            let offset = (y0 + row) * self.n + x0;
+            let values: Vec<f64> = values.iter().map(|&v| v as f64).collect();
+            self.write_solution(queue, self.iteration % 2, offset, &values);
+        }
+        self.refresh_texture(device, queue);
+    }
+
+    /// Writes `values` from index `offset` of the solution held at iterations
+    /// of the given `parity`, in single and in the solver precision. Values
+    /// outside the domain of the mask are written as zero.
+    fn write_solution(&self, queue: &wgpu::Queue, parity: usize, offset: usize, values: &[f64]) {
+        let mut values = values.to_vec();
+        if let Some(mask) = &self.mask {
+            mask.apply_at(offset, &mut values);
         }
+        let f32_size = std::mem::size_of::<f32>();
+        queue.write_buffer(
+            self.solution_buffers()[parity],
+            (offset * f32_size) as wgpu::BufferAddress,
+            &Precision::Single.encode(values.iter().copied()),
+        );
+        if let Some(precise) = &self.precise {
+            queue.write_buffer(
+                [&precise.x, &precise.x_][parity],
+                (offset * self.precision.size()) as wgpu::BufferAddress,
+                &self.precision.encode(values.iter().copied()),
+            );
+        }
+    }
+
+    /// Copies the solution held at iterations of the given `parity` back to
+    /// the host in the solver precision, blocking until it is available.
+    fn read_solution(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        parity: usize,
+    ) -> Result<Vec<f64>, wgpu::BufferAsyncError> {
+        let (buffer, precision) = match &self.precise {
+            Some(precise) => ([&precise.x, &precise.x_][parity], self.precision),
+            None => (self.solution_buffers()[parity], Precision::Single),
+        };
+        let bytes = read_bytes_async(device, queue, buffer);
+        device.poll(wgpu::Maintain::Wait);
+        Ok(precision.decode(&pollster::block_on(bytes)?))
+    }
 
-        // now we need to write u_new to the storage texture
+    /// Writes the current solution to the storage texture.
+    fn refresh_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Write to Texture Encoder"),
+            label: Some("Refresh Texture Encoder"),
         });
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("Write to Texture Compute Pass"),
+            label: Some("Refresh Texture Compute Pass"),
             timestamp_writes: None,
         });
-        if self.iteration % 2 == 0 {
-            self.write_to_texture_forward.add_to_pass(&mut compute_pass);
-        } else {
+        if self.iteration.is_multiple_of(2) {
             self.write_to_texture_backward
                 .add_to_pass(&mut compute_pass);
-        };
-
+        } else {
+            self.write_to_texture_forward.add_to_pass(&mut compute_pass);
+        }
         drop(compute_pass);
         queue.submit(Some(encoder.finish()));
+    }
+
+    /// Copies the current solution into a staging buffer and maps it for reading.
+    ///
+    /// The returned future only resolves once the device has been polled, either
+    /// by the caller's event loop or through `wgpu::Device::poll`. See `read_field`
+    /// for a blocking version.
+    pub fn read_field_async(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> impl Future<Output = Result<Field, wgpu::BufferAsyncError>> {
+        self.read_buffer_async(device, queue, self.current())
+    }
+
+    fn read_buffer_async(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+    ) -> impl Future<Output = Result<Field, wgpu::BufferAsyncError>> {
+        let values = read_buffer_async(device, queue, buffer);
+        let n = self.n;
+        async move { Ok(Field::new(n, n, values.await?)) }
+    }
+
+    /// Copies the current solution back to the host, blocking until it is available.
+    ///
+    /// Fails if the staging buffer the solution is copied into cannot be mapped.
+    pub fn read_field(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Field, wgpu::BufferAsyncError> {
+        let field = self.read_field_async(device, queue);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(field)
+    }
+
+    /// Copies the current solution back to the host in the solver precision,
+    /// blocking until it is available. Unlike `read_field`, the values are not
+    /// rounded to `f32` when the solver runs in double precision.
+    pub fn read_values(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<f64>, wgpu::BufferAsyncError> {
+        self.read_solution(device, queue, self.iteration % 2)
+    }
+
+    /// Copies the current state back to the host so that the run can be resumed later.
+    ///
+    /// Both solutions are saved in the solver precision. The previous one is
+    /// the initial guess of the next step, and is read from the solver's own
+    /// buffer, since its `f32` copy is only refreshed when it is rendered.
+    pub fn checkpoint(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Checkpoint, wgpu::BufferAsyncError> {
+        Ok(Checkpoint {
+            parameters: self.checkpoint_parameters(),
+            iteration: self.iteration,
+            time: self.iteration as f64 * self.dt as f64,
+            field: self.read_solution(device, queue, self.iteration % 2)?,
+            previous: self.read_solution(device, queue, (self.iteration + 1) % 2)?,
+        })
+    }
+
+    /// Resumes the run saved in `checkpoint`, which must come from a solver with
+    /// the same grid, scheme, boundary condition, mask, materials and
+    /// parameters.
+    pub fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        checkpoint: &Checkpoint,
+    ) -> Result<(), CheckpointError> {
+        checkpoint.check(&self.checkpoint_parameters())?;
+        // the parity of the iteration decides which buffer is current
+        self.iteration = checkpoint.iteration;
+        self.write_solution(queue, (self.iteration + 1) % 2, 0, &checkpoint.previous);
+        self.write_solution(queue, self.iteration % 2, 0, &checkpoint.field);
+        self.refresh_texture(device, queue);
+        Ok(())
+    }
+
+    fn checkpoint_parameters(&self) -> Parameters {
+        Parameters {
+            scheme: Scheme::CrankNicolson,
+            boundary: BoundaryCondition::ZeroDirichlet,
+            precision: self.precision,
+            n: self.n,
+            alpha: self.alpha,
+            dt: self.dt,
+            mask: self
+                .mask
+                .as_ref()
+                .map_or(0, |mask| checkpoint::fingerprint(&mask.labels())),
+            materials: self.materials,
+        }
+    }
+
+    /// Distance between neighbouring grid points.
+    ///
+    /// The `n x n` unknowns are the interior points of the unit square, whose
+    /// edges are held at zero: point `i` sits at `(i + 1) * h`, and the zero
+    /// boundary values at `0` and `(n + 1) * h = 1`.
+    pub fn grid_spacing(n: usize) -> f64 {
+        1.0 / (n as f64 + 1.0)
+    }
+
+    /// Position of grid point `i` along either axis of the unit square.
+    pub fn grid_coordinate(i: usize, n: usize) -> f64 {
+        (i as f64 + 1.0) * Self::grid_spacing(n)
+    }
+
+    /// Left-hand side matrix of the Crank-Nicolson scheme, `A` in `A u_new = B u_old`.
+    pub(crate) fn a_matrix(alpha: f32, n: usize, dt: f32) -> DIAMatrix {
+        let [a, _] = Self::matrices(alpha, n, dt);
+        DIAMatrix::from(&a)
+    }
+
+    /// Same as `a_matrix`, but gamma has a negative sign
+    pub(crate) fn b_matrix(alpha: f32, n: usize, dt: f32) -> DIAMatrix {
+        let [_, b] = Self::matrices(alpha, n, dt);
+        DIAMatrix::from(&b)
+    }
+
+    /// Stencils of both matrices of the Crank-Nicolson scheme, `I + gamma L`
+    /// and `I - gamma L`, where `L` is the negative 5-point Laplacian scaled
+    /// by `h²` and the boundary values are zero.
+    pub fn stencils(alpha: f32, n: usize, dt: f32) -> [Stencil; 2] {
+        let h = Self::grid_spacing(n);
+        let gamma = alpha as f64 * dt as f64 / (2.0 * h * h);
+        [gamma, -gamma].map(|gamma| Stencil::five_point(1.0 + 4.0 * gamma, -gamma))
+    }
+
+    /// Both matrices of the Crank-Nicolson scheme, `A` and `B` in
+    /// `A u_new = B u_old`, to inspect them or save them with
+    /// [`matrix_market`](crate::matrix_market).
+    pub fn matrices(alpha: f32, n: usize, dt: f32) -> [COOMatrix; 2] {
+        Self::stencils(alpha, n, dt).map(|stencil| stencil.to_matrix(n, n))
+    }
+
+    /// Both matrices of the Crank-Nicolson scheme restricted to the active
+    /// cells of `mask`.
+    ///
+    /// The rows of the inactive cells read `u_new = 0`. An active cell is
+    /// coupled to its active neighbours only, and each face towards the edge
+    /// of the grid or a [`Cell::Dirichlet`] neighbour adds a zero boundary
+    /// value, while faces towards [`Cell::Insulated`] neighbours carry no
+    /// flux. Both matrices stay symmetric, and `A` positive definite.
+    pub fn masked_matrices(alpha: f32, n: usize, dt: f32, mask: &Mask) -> [COOMatrix; 2] {
+        let h = Self::grid_spacing(n);
+        let gamma = alpha as f64 * dt as f64 / (2.0 * h * h);
+        Self::assemble(n, gamma, Some(mask), |_| 1.0, |_, _| 1.0)
+    }
+
+    /// Both matrices of the Crank-Nicolson scheme for `ρ c ∂u/∂t = ∇·(k ∇u)`
+    /// with the materials of `materials`, `A = C + dt/2 K` and
+    /// `B = C - dt/2 K`, where `C` holds the heat capacities and `K` the
+    /// conductivities across the faces, see
+    /// [`interface_conductivity`]. The domain may be restricted by `mask` as
+    /// in [`masked_matrices`](Self::masked_matrices).
+    pub fn material_matrices(
+        n: usize,
+        dt: f32,
+        materials: &MaterialMap,
+        mask: Option<&Mask>,
+    ) -> [COOMatrix; 2] {
+        assert_eq!(
+            (materials.width, materials.height),
+            (n, n),
+            "material map must cover the n * n grid"
+        );
+        let h = Self::grid_spacing(n);
+        let gamma = dt as f64 / (2.0 * h * h);
+        Self::assemble(
+            n,
+            gamma,
+            mask,
+            |i| materials.material(i).heat_capacity(),
+            |i, j| {
+                interface_conductivity(
+                    materials.material(i).conductivity,
+                    materials.material(j).conductivity,
+                )
+            },
+        )
+    }
+
+    /// Assembles `A = C + gamma K` and `B = C - gamma K` over the active
+    /// cells of `mask`, or the whole grid, where `C` is the diagonal of the
+    /// capacities of the points and `K` the 5-point Laplacian weighted by
+    /// the conductance of every face. Faces towards zero boundary values
+    /// take the conductance of the point with itself.
+    fn assemble(
+        n: usize,
+        gamma: f64,
+        mask: Option<&Mask>,
+        capacity: impl Fn(usize) -> f64,
+        conductance: impl Fn(usize, usize) -> f64,
+    ) -> [COOMatrix; 2] {
+        if let Some(mask) = mask {
+            assert_eq!(
+                (mask.width, mask.height),
+                (n, n),
+                "mask must cover the n * n grid"
+            );
+        }
+        let cell = |x, y| mask.map_or(Cell::Active, |mask| mask.get(x, y));
+        let mut a = COOMatrix::new(n * n, n * n);
+        let mut b = COOMatrix::new(n * n, n * n);
+        for y in 0..n {
+            for x in 0..n {
+                let row = y * n + x;
+                if cell(x, y) != Cell::Active {
+                    a.push(row, row, 1.0);
+                    continue;
+                }
+                let mut weight = 0.0;
+                for (dx, dy) in [(0, -1), (-1, 0), (1, 0), (0, 1)] {
+                    let (nx, ny) = (x as isize + dx, y as isize + dy);
+                    let inside = (0..n as isize).contains(&nx) && (0..n as isize).contains(&ny);
+                    let neighbour = if inside {
+                        cell(nx as usize, ny as usize)
+                    } else {
+                        Cell::Dirichlet
+                    };
+                    match neighbour {
+                        Cell::Active => {
+                            let col = ny as usize * n + nx as usize;
+                            let face = conductance(row, col);
+                            a.push(row, col, -gamma * face);
+                            b.push(row, col, gamma * face);
+                            weight += face;
+                        }
+                        Cell::Dirichlet => weight += conductance(row, row),
+                        Cell::Insulated => {}
+                    }
+                }
+                a.push(row, row, capacity(row) + weight * gamma);
+                b.push(row, row, capacity(row) - weight * gamma);
+            }
+        }
+        [a, b]
+    }
+
+    pub fn compute_step(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.compute_steps(device, queue, 1);
+    }
+
+    /// Advances the solution by `steps` time steps, recorded into a single
+    /// submission, and writes the last one to the texture.
+    ///
+    /// wgpu cannot replay command buffers, but every kernel of a step is
+    /// built once, so recording the steps only costs their dispatch calls.
+    /// Iterative refinement still submits once per correction, and the
+    /// tracked diagnostics and the profiler read every step back.
+    pub fn compute_steps(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, steps: usize) {
+        if steps > 1 && (self.last_diagnostics.is_some() || self.profiler.is_some()) {
+            for _ in 0..steps {
+                self.compute_steps(device, queue, 1);
+            }
+            return;
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_step();
+        }
+        let mut encoder = time_step_encoder(device);
+        for step in 0..steps {
+            self.encode_step(device, queue, &mut encoder, step + 1 == steps);
+        }
+        queue.submit(Some(encoder.finish()));
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_step(device, queue, self.iteration);
+        }
+
+        if steps > 0 && self.last_diagnostics.is_some() {
+            self.last_diagnostics = Some(self.diagnostics(device, queue));
+        }
+    }
+
+    /// Records a time step into `encoder`, and the new solution's copy to the
+    /// texture if `output`.
+    fn encode_step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: bool,
+    ) {
+        let forward = self.iteration.is_multiple_of(2);
+        let profiler = self.profiler.as_ref();
+        // First step: tmp = B * u_old
+        let initial_spmv = if forward {
+            &self.initial_spmv_forward
+        } else {
+            &self.initial_spmv_backward
+        };
+        encode_kernels(
+            encoder,
+            Some("Initial SpMV Compute Pass (tmp = B*U)"),
+            [initial_spmv.as_ref()],
+            profiler,
+        );
+
+        // Now we can treat the vector tmp as the "b" in A u_new = b
+        // for our linear solver
+        let solver = if forward {
+            &self.solver_forward
+        } else {
+            &self.solver_backward
+        };
+        self.last_refinement = solver.solve(device, queue, encoder, profiler);
+
+        // now we need to write u_new to the storage texture, after rounding
+        // it to single precision if the solver works in another precision
+        if output {
+            let (convert, write_to_texture) = if forward {
+                (
+                    self.precise.as_ref().map(|p| &p.convert_forward),
+                    &self.write_to_texture_forward,
+                )
+            } else {
+                (
+                    self.precise.as_ref().map(|p| &p.convert_backward),
+                    &self.write_to_texture_backward,
+                )
+            };
+            let kernels = convert
+                .map(|k| k as &dyn Kernel)
+                .into_iter()
+                .chain([write_to_texture as &dyn Kernel]);
+            encode_kernels(
+                encoder,
+                Some("Write to Texture Compute Pass"),
+                kernels,
+                profiler,
+            );
+        }
         self.iteration += 1;
     }
 }
+
+/// Copies a buffer of `f32` values into a staging buffer and maps it for reading.
+///
+/// The returned future only resolves once the device has been polled.
+pub(crate) fn read_buffer_async(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> impl Future<Output = Result<Vec<f32>, wgpu::BufferAsyncError>> {
+    let bytes = read_bytes_async(device, queue, buffer);
+    async move { Ok(bytemuck::cast_slice(&bytes.await?).to_vec()) }
+}
+
+/// Copies a buffer into a staging buffer and maps it for reading.
+///
+/// The returned future only resolves once the device has been polled, and
+/// fails if the staging buffer could not be mapped.
+pub(crate) fn read_bytes_async(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> impl Future<Output = Result<Vec<u8>, wgpu::BufferAsyncError>> {
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback staging buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+    staging_buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |v| {
+            // the receiver is only gone if the future was dropped
+            let _ = sender.send(v);
+        });
+    async move {
+        // a callback dropped without being called never mapped the buffer
+        receiver
+            .receive()
+            .await
+            .unwrap_or(Err(wgpu::BufferAsyncError))?;
+        let data = staging_buffer.slice(..).get_mapped_range();
+        let bytes = data.to_vec();
+        drop(data);
+        staging_buffer.unmap();
+        Ok(bytes)
+    }
+}
+
+/// Copies a buffer of `f32` values back to the host, blocking until it is available.
+pub(crate) fn read_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> Result<Vec<f32>, wgpu::BufferAsyncError> {
+    let values = read_buffer_async(device, queue, buffer);
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(values)
+}
+
+impl Solver for HeatEquation {
+    type Context<'a> = (&'a wgpu::Device, &'a wgpu::Queue);
+
+    fn compute_step(&mut self, (device, queue): Self::Context<'_>) {
+        HeatEquation::compute_step(self, device, queue);
+    }
+
+    fn compute_steps(&mut self, (device, queue): Self::Context<'_>, steps: usize) {
+        HeatEquation::compute_steps(self, device, queue, steps);
+    }
+
+    fn iteration(&self) -> usize {
+        self.iteration
+    }
+
+    fn time(&self) -> f32 {
+        HeatEquation::time(self)
+    }
+
+    fn n(&self) -> usize {
+        self.n
+    }
+
+    fn read_field(&self, (device, queue): Self::Context<'_>) -> io::Result<Field> {
+        HeatEquation::read_field(self, device, queue).map_err(io::Error::other)
+    }
+
+    fn set_field(&mut self, (device, queue): Self::Context<'_>, data: &[f32]) {
+        HeatEquation::set_field(self, device, queue, data);
+    }
+
+    fn set_region(
+        &mut self,
+        (device, queue): Self::Context<'_>,
+        origin: (usize, usize),
+        width: usize,
+        data: &[f32],
+    ) {
+        HeatEquation::set_region(self, device, queue, origin, width, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csr_matrix::CSRMatrix;
+
+    #[test]
+    fn crank_nicolson_matrices() {
+        const N: usize = 4;
+        let a = HeatEquation::a_matrix(0.5, N, 0.1);
+        let n = N as i32;
+        assert_eq!(a.offsets, vec![-n, -1, 0, 1, n]);
+        let [a_entries, b_entries] = HeatEquation::matrices(0.5, N, 0.1);
+        assert_eq!(DIAMatrix::from(&a_entries), a);
+        // A + B = 2 I
+        let mut sum = a_entries.clone();
+        sum.entries.extend(b_entries.entries);
+        let expected: Vec<_> = (0..N * N).map(|i| (i, i, 2.0)).collect();
+        assert_eq!(
+            sum.compressed()
+                .into_iter()
+                .filter(|&(_, _, value)| value != 0.0)
+                .collect::<Vec<_>>(),
+            expected
+        );
+        // the corners have two neighbours, the edges three and the interior four
+        assert_eq!(a_entries.get(N, N - 1), 0.0);
+        assert_eq!(CSRMatrix::from(&a_entries).row(0).count(), 3);
+        assert_eq!(CSRMatrix::from(&a_entries).row(1).count(), 4);
+        assert_eq!(CSRMatrix::from(&a_entries).row(N + 1).count(), 5);
+    }
+
+    #[test]
+    fn masked_matrices() {
+        const N: usize = 4;
+        let full = Mask::new(N, N, vec![Cell::Active; N * N]);
+        let [a, b] = HeatEquation::masked_matrices(0.5, N, 0.1, &full);
+        let [a_full, b_full] = HeatEquation::matrices(0.5, N, 0.1);
+        assert_eq!(a.compressed(), a_full.compressed());
+        assert_eq!(b.compressed(), b_full.compressed());
+
+        // a wall held at zero at (1, 1) and an insulated one at (2, 1)
+        let mut cells = vec![Cell::Active; N * N];
+        cells[N + 1] = Cell::Dirichlet;
+        cells[N + 2] = Cell::Insulated;
+        let mask = Mask::new(N, N, cells);
+        let [a, b] = HeatEquation::masked_matrices(0.5, N, 0.1, &mask);
+        let a_csr = CSRMatrix::from(&a);
+        let b_csr = CSRMatrix::from(&b);
+        for wall in [N + 1, N + 2] {
+            assert_eq!(a_csr.row(wall).collect::<Vec<_>>(), vec![(wall, 1.0)]);
+            assert_eq!(b_csr.row(wall).count(), 0);
+            assert_eq!(a.get(wall - N, wall), 0.0);
+        }
+        // (2, 0) keeps the grid edge above it, loses the insulated face below
+        let gamma = -a_full.get(1, 0);
+        assert_eq!(a.get(2, 2), 1.0 + 3.0 * gamma);
+        // (1, 0) keeps the face towards the wall held at zero
+        assert_eq!(a.get(1, 1), 1.0 + 4.0 * gamma);
+        let symmetric = |m: &COOMatrix| {
+            m.compressed()
+                .iter()
+                .all(|&(row, col, value)| m.get(col, row) == value)
+        };
+        assert!(symmetric(&a) && symmetric(&b));
+    }
+
+    #[test]
+    fn material_matrices() {
+        use crate::material::Material;
+
+        const N: usize = 4;
+        let material = |density, conductivity| Material {
+            name: String::new(),
+            density,
+            specific_heat: 1.0,
+            conductivity,
+            color: None,
+        };
+        // a uniform material with unit heat capacity is the diffusivity
+        let uniform = MaterialMap::uniform(N, N, material(1.0, 0.5));
+        let [a, _] = HeatEquation::material_matrices(N, 0.1, &uniform, None);
+        let [a_full, _] = HeatEquation::matrices(0.5, N, 0.1);
+        for ((i, j, value), (k, l, expected)) in a.compressed().into_iter().zip(a_full.compressed())
+        {
+            assert_eq!((i, j), (k, l));
+            assert!((value - expected).abs() < 1e-12, "{value} != {expected}");
+        }
+
+        // the right half is twice as dense and conducts three times as well
+        let indices = (0..N * N).map(|i| usize::from(i % N >= N / 2)).collect();
+        let map = MaterialMap::new(N, N, vec![material(1.0, 1.0), material(2.0, 3.0)], indices);
+        let [a, b] = HeatEquation::material_matrices(N, 0.1, &map, None);
+        let gamma = 0.1f32 as f64 / (2.0 * HeatEquation::grid_spacing(N).powi(2));
+        assert_eq!(a.get(1, 2), -1.5 * gamma);
+        assert_eq!(a.get(2, 3), -3.0 * gamma);
+        assert_eq!(a.get(0, 0), 1.0 + 4.0 * gamma);
+        // the edge above conducts like the point itself
+        assert_eq!(a.get(2, 2), 2.0 + (3.0 + 1.5 + 3.0 + 3.0) * gamma);
+        // A + B = 2 C
+        assert_eq!(a.get(5, 5) + b.get(5, 5), 2.0);
+        assert_eq!(a.get(6, 6) + b.get(6, 6), 4.0);
+        let symmetric = |m: &COOMatrix| {
+            m.compressed()
+                .iter()
+                .all(|&(row, col, value)| m.get(col, row) == value)
+        };
+        assert!(symmetric(&a) && symmetric(&b));
+    }
+}
@@ -0,0 +1,274 @@
+//! Initial conditions given by formulas, selectable by name from the command line.
+//!
+//! Grid point `(i, j)` of an `n * n` grid sits at `((i + 1) h, (j + 1) h)` in
+//! the unit square, see `HeatEquation::grid_spacing`, with `j` growing
+//! downwards on screen.
+use std::{f64::consts::PI, str::FromStr};
+
+use noise::{NoiseFn, Perlin};
+
+use crate::heat_equation::HeatEquation;
+
+/// One term of an analytic initial condition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Analytic {
+    /// `amplitude * exp(-|p - center|² / (2 sigma²))`.
+    Gaussian {
+        center: [f64; 2],
+        sigma: f64,
+        amplitude: f64,
+    },
+    /// Perlin noise in `[-amplitude, amplitude]`, centered on the domain.
+    /// Every extra octave doubles the frequency and halves the amplitude.
+    Perlin {
+        seed: u32,
+        frequency: f64,
+        octaves: u32,
+        amplitude: f64,
+    },
+    /// `value` left of `x`, zero on the right.
+    Step { x: f64, value: f64 },
+    /// `value` inside the disk, zero outside.
+    Disk {
+        center: [f64; 2],
+        radius: f64,
+        value: f64,
+    },
+    /// `value` inside the rectangle `[min, max]`, zero outside.
+    Rectangle {
+        min: [f64; 2],
+        max: [f64; 2],
+        value: f64,
+    },
+    /// `amplitude * sin(k π x) sin(l π y)`, which vanishes on the edges and is an
+    /// eigenvector of the discrete Laplacian as well as of the continuous one.
+    Eigenmode { k: u32, l: u32, amplitude: f64 },
+    /// The same `value` everywhere.
+    Uniform { value: f64 },
+}
+
+impl Analytic {
+    /// Names accepted by [`Analytic::from_str`].
+    pub const NAMES: [&'static str; 7] = [
+        "gaussian",
+        "perlin",
+        "step",
+        "disk",
+        "rectangle",
+        "eigenmode",
+        "uniform",
+    ];
+
+    /// Evaluates the term at every point of an `n * n` grid, adding it to `data`.
+    pub fn add_to(&self, n: usize, data: &mut [f32]) {
+        assert_eq!(data.len(), n * n, "data must have n * n entries");
+        let perlin_octaves: Vec<Perlin> = match self {
+            Analytic::Perlin { seed, octaves, .. } => (0..*octaves)
+                .map(|o| Perlin::new(seed.wrapping_add(o)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for j in 0..n {
+            for i in 0..n {
+                let (x, y) = (
+                    HeatEquation::grid_coordinate(i, n),
+                    HeatEquation::grid_coordinate(j, n),
+                );
+                let value = match self {
+                    Analytic::Gaussian {
+                        center,
+                        sigma,
+                        amplitude,
+                    } => {
+                        let r2 = (x - center[0]).powi(2) + (y - center[1]).powi(2);
+                        amplitude * (-r2 / (2.0 * sigma * sigma)).exp()
+                    }
+                    Analytic::Perlin {
+                        frequency,
+                        amplitude,
+                        ..
+                    } => {
+                        let (mut sum, mut weight, mut total_weight) = (0.0, 1.0, 0.0);
+                        let mut scale = *frequency;
+                        for perlin in perlin_octaves.iter() {
+                            let point = [(x - 0.5) * scale, (y - 0.5) * scale, 0.0];
+                            sum += weight * perlin.get(point);
+                            total_weight += weight;
+                            weight *= 0.5;
+                            scale *= 2.0;
+                        }
+                        amplitude * sum / total_weight
+                    }
+                    Analytic::Step { x: edge, value } => {
+                        if x < *edge {
+                            *value
+                        } else {
+                            0.0
+                        }
+                    }
+                    Analytic::Disk {
+                        center,
+                        radius,
+                        value,
+                    } => {
+                        let r2 = (x - center[0]).powi(2) + (y - center[1]).powi(2);
+                        if r2 <= radius * radius {
+                            *value
+                        } else {
+                            0.0
+                        }
+                    }
+                    Analytic::Rectangle { min, max, value } => {
+                        if (min[0]..=max[0]).contains(&x) && (min[1]..=max[1]).contains(&y) {
+                            *value
+                        } else {
+                            0.0
+                        }
+                    }
+                    Analytic::Eigenmode { k, l, amplitude } => {
+                        amplitude * (*k as f64 * PI * x).sin() * (*l as f64 * PI * y).sin()
+                    }
+                    Analytic::Uniform { value } => *value,
+                };
+                data[j * n + i] += value as f32;
+            }
+        }
+    }
+}
+
+/// Parses `name` or `name:key=value,key=value,...`. Parameters that are left
+/// out take their default value; points are given as `x` and `y` (`x0`, `y0`,
+/// `x1`, `y1` for rectangles).
+impl FromStr for Analytic {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+        let mut params = params
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (key, value) = p
+                    .split_once('=')
+                    .ok_or(format!("expected key=value in {name}, found {p:?}"))?;
+                let value = value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid value {value:?} for {key} in {name}"))?;
+                Ok((key.trim(), value, false))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut get = |key: &str, default: f64| {
+            params
+                .iter_mut()
+                .find(|(k, _, _)| *k == key)
+                .map_or(default, |(_, value, used)| {
+                    *used = true;
+                    *value
+                })
+        };
+        let analytic = match name {
+            "gaussian" => Analytic::Gaussian {
+                center: [get("x", 0.5), get("y", 0.5)],
+                sigma: get("sigma", 0.1),
+                amplitude: get("amplitude", 1.0),
+            },
+            "perlin" => Analytic::Perlin {
+                seed: get("seed", 1.0) as u32,
+                frequency: get("frequency", 10.0),
+                octaves: get("octaves", 1.0) as u32,
+                amplitude: get("amplitude", 1.0),
+            },
+            "step" => Analytic::Step {
+                x: get("x", 0.5),
+                value: get("value", 1.0),
+            },
+            "disk" => Analytic::Disk {
+                center: [get("x", 0.5), get("y", 0.5)],
+                radius: get("radius", 0.25),
+                value: get("value", 1.0),
+            },
+            "rectangle" => Analytic::Rectangle {
+                min: [get("x0", 0.25), get("y0", 0.25)],
+                max: [get("x1", 0.75), get("y1", 0.75)],
+                value: get("value", 1.0),
+            },
+            "eigenmode" => Analytic::Eigenmode {
+                k: get("k", 1.0) as u32,
+                l: get("l", 1.0) as u32,
+                amplitude: get("amplitude", 1.0),
+            },
+            "uniform" => Analytic::Uniform {
+                value: get("value", 1.0),
+            },
+            _ => return Err(format!("unknown initial condition {name:?}")),
+        };
+        if let Some((key, _, _)) = params.iter().find(|(_, _, used)| !used) {
+            return Err(format!("unknown parameter {key} for {name}"));
+        }
+        if let Analytic::Perlin { octaves: 0, .. } = analytic {
+            return Err("perlin needs at least one octave".to_string());
+        }
+        Ok(analytic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(analytic: &Analytic, n: usize) -> Vec<f32> {
+        let mut data = vec![0.0; n * n];
+        analytic.add_to(n, &mut data);
+        data
+    }
+
+    #[test]
+    fn parses_parameters() {
+        assert_eq!(
+            "gaussian:sigma=0.2, y=0.25".parse(),
+            Ok(Analytic::Gaussian {
+                center: [0.5, 0.25],
+                sigma: 0.2,
+                amplitude: 1.0,
+            })
+        );
+        assert_eq!("uniform".parse(), Ok(Analytic::Uniform { value: 1.0 }));
+        assert!("gaussian:radius=1".parse::<Analytic>().is_err());
+        assert!("gaussian:sigma".parse::<Analytic>().is_err());
+        assert!("square".parse::<Analytic>().is_err());
+        assert!("perlin:octaves=0".parse::<Analytic>().is_err());
+        assert!("perlin:octaves=-2".parse::<Analytic>().is_err());
+        let noise = evaluate(&"perlin:seed=4294967295,octaves=2".parse().unwrap(), 8);
+        assert!(noise.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn shapes() {
+        let disk = evaluate(&"disk:radius=0.2".parse().unwrap(), 10);
+        assert_eq!(disk[5 * 10 + 5], 1.0);
+        assert_eq!(disk[0], 0.0);
+
+        let step = evaluate(&"step:x=0.5,value=2".parse().unwrap(), 4);
+        assert_eq!(&step[4..8], &[2.0, 2.0, 0.0, 0.0]);
+
+        // the middle point of an odd grid is the center of the square
+        let gaussian = evaluate(&"gaussian:amplitude=3".parse().unwrap(), 9);
+        assert_eq!(gaussian[4 * 9 + 4], 3.0);
+        assert!(gaussian[0] < 0.01);
+    }
+
+    #[test]
+    fn eigenmode_is_symmetric() {
+        let n = 9;
+        let mode = evaluate(&"eigenmode".parse().unwrap(), n);
+        let max = mode.iter().cloned().fold(f32::MIN, f32::max);
+        assert_eq!(mode[4 * n + 4], max);
+        for j in 0..n {
+            for i in 0..n {
+                let mirrored = mode[(n - 1 - j) * n + (n - 1 - i)];
+                assert!((mode[j * n + i] - mirrored).abs() < 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Loading initial conditions from images and data files.
+//!
+//! Grayscale images are rescaled from black..white to a temperature range,
+//! while `.npy`, CSV and floating point TIFF files are taken as temperatures.
+//! Rows are read top to bottom, so that an image looks the same in the window.
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{field::Field, heat_equation::HeatEquation, output::npy::read_npy};
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Loads a field from `path` at its native resolution, choosing the format
+/// from the extension.
+///
+/// Integer images are mapped linearly so that black is `range[0]` and white
+/// is `range[1]`.
+pub fn load(path: impl AsRef<Path>, range: [f32; 2]) -> io::Result<Field> {
+    let path = path.as_ref();
+    match extension(path).as_deref() {
+        Some("npy" | "csv" | "txt") => load_samples(path),
+        Some("tif" | "tiff") => read_tiff(BufReader::new(File::open(path)?), Some(range)),
+        _ => load_image(path, range),
+    }
+}
+
+/// Loads the values held by a `.npy`, CSV or TIFF file as they are, without
+/// rescaling integer TIFF samples, for files holding labels or indices.
+pub fn load_samples(path: impl AsRef<Path>) -> io::Result<Field> {
+    let path = path.as_ref();
+    match extension(path).as_deref() {
+        Some("npy") => {
+            let (shape, data) = read_npy(&mut BufReader::new(File::open(path)?))?;
+            match shape[..] {
+                [height, width] => Ok(Field::new(width, height, data)),
+                _ => Err(invalid(format!(
+                    "expected a 2-dimensional array, found shape {shape:?}"
+                ))),
+            }
+        }
+        Some("csv" | "txt") => read_csv(BufReader::new(File::open(path)?)),
+        Some("tif" | "tiff") => read_tiff(BufReader::new(File::open(path)?), None),
+        _ => Err(invalid(format!(
+            "expected a .npy, CSV or TIFF file, found {}",
+            path.display()
+        ))),
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+/// Reads rows of comma separated values, one row of the grid per line.
+pub fn read_csv<R: BufRead>(r: R) -> io::Result<Field> {
+    let mut width = None;
+    let mut data = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let row = line
+            .split(',')
+            .map(|v| {
+                v.trim()
+                    .parse::<f32>()
+                    .map_err(|_| invalid(format!("line {}: invalid number {v:?}", i + 1)))
+            })
+            .collect::<io::Result<Vec<f32>>>()?;
+        match width {
+            None => width = Some(row.len()),
+            Some(width) if width != row.len() => {
+                return Err(invalid(format!(
+                    "line {}: expected {width} values, found {}",
+                    i + 1,
+                    row.len()
+                )))
+            }
+            Some(_) => {}
+        }
+        data.extend(row);
+    }
+    let width = width.ok_or_else(|| invalid("empty CSV file"))?;
+    Ok(Field::new(width, data.len() / width, data))
+}
+
+fn rescale(value: f32, range: [f32; 2]) -> f32 {
+    range[0] + value * (range[1] - range[0])
+}
+
+/// Reads a single channel TIFF. 8 and 16-bit samples are rescaled to `range`,
+/// or kept as integers without one, and floating point samples are kept as
+/// they are.
+pub fn read_tiff<R: io::Read + io::Seek>(r: R, range: Option<[f32; 2]>) -> io::Result<Field> {
+    use tiff::{
+        decoder::{Decoder, DecodingResult},
+        ColorType, TiffError,
+    };
+
+    let to_io = |e| match e {
+        TiffError::IoError(e) => e,
+        e => io::Error::other(e),
+    };
+    let mut decoder = Decoder::new(r).map_err(to_io)?;
+    if !matches!(decoder.colortype().map_err(to_io)?, ColorType::Gray(_)) {
+        return Err(invalid("only grayscale TIFF images are supported"));
+    }
+    let (width, height) = decoder.dimensions().map_err(to_io)?;
+    let integers = |data: Vec<f32>, max: f32| match range {
+        Some(range) => data.into_iter().map(|v| rescale(v / max, range)).collect(),
+        None => data,
+    };
+    let data = match decoder.read_image().map_err(to_io)? {
+        DecodingResult::U8(data) => {
+            integers(data.into_iter().map(f32::from).collect(), u8::MAX as f32)
+        }
+        DecodingResult::U16(data) => {
+            integers(data.into_iter().map(f32::from).collect(), u16::MAX as f32)
+        }
+        DecodingResult::F32(data) => data,
+        DecodingResult::F64(data) => data.into_iter().map(|v| v as f32).collect(),
+        _ => return Err(invalid("unsupported TIFF sample format")),
+    };
+    Ok(Field::new(width as usize, height as usize, data))
+}
+
+/// Loads any image supported by the `image` crate, converted to grayscale
+/// with 16 bits of precision and rescaled to `range`.
+pub fn load_image(path: impl AsRef<Path>, range: [f32; 2]) -> io::Result<Field> {
+    let img = image::open(path)
+        .map_err(|e| match e {
+            image::ImageError::IoError(e) => e,
+            e => invalid(e.to_string()),
+        })?
+        .into_luma16();
+    let data = img
+        .pixels()
+        .map(|p| rescale(p.0[0] as f32 / u16::MAX as f32, range))
+        .collect();
+    Ok(Field::new(
+        img.width() as usize,
+        img.height() as usize,
+        data,
+    ))
+}
+
+/// Bilinearly interpolates `field` onto a `width * height` grid covering the
+/// same domain. Both are laid out like the solver's grid, point `i` of `n`
+/// at [`HeatEquation::grid_coordinate`], and the target points beyond the
+/// outer source points take their values.
+pub fn resample(field: &Field, width: usize, height: usize) -> Field {
+    if (field.width, field.height) == (width, height) {
+        return field.clone();
+    }
+    // source coordinate of a target point, with the index of its lower
+    // neighbour and the interpolation weight of the upper one
+    let coordinates = |n: usize, source_n: usize| {
+        (0..n)
+            .map(|i| {
+                let x = HeatEquation::grid_coordinate(i, n) / HeatEquation::grid_spacing(source_n)
+                    - 1.0;
+                let x = x.clamp(0.0, (source_n - 1) as f64);
+                let i0 = (x.floor() as usize).min(source_n.saturating_sub(2));
+                (i0, (x - i0 as f64) as f32)
+            })
+            .collect::<Vec<_>>()
+    };
+    let xs = coordinates(width, field.width);
+    let ys = coordinates(height, field.height);
+    let at = |x: usize, y: usize| field.get(x.min(field.width - 1), y.min(field.height - 1));
+    let mut data = Vec::with_capacity(width * height);
+    for &(y0, ty) in ys.iter() {
+        for &(x0, tx) in xs.iter() {
+            let bottom = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+            let top = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+            data.push(bottom * (1.0 - ty) + top * ty);
+        }
+    }
+    Field::new(width, height, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows() {
+        let field = read_csv("# comment\n1, 2, 3\n\n4,5,6\n".as_bytes()).unwrap();
+        assert_eq!(field, Field::new(3, 2, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        assert!(read_csv("1,2\n3\n".as_bytes()).is_err());
+        assert!(read_csv("1,x\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn tiff_16_bit_is_rescaled() {
+        use tiff::encoder::{colortype::Gray16, TiffEncoder};
+
+        let mut bytes = io::Cursor::new(Vec::new());
+        TiffEncoder::new(&mut bytes)
+            .unwrap()
+            .write_image::<Gray16>(2, 1, &[0, u16::MAX])
+            .unwrap();
+        bytes.set_position(0);
+        let field = read_tiff(bytes.clone(), Some([10.0, 20.0])).unwrap();
+        assert_eq!(field, Field::new(2, 1, vec![10.0, 20.0]));
+        bytes.set_position(0);
+        let field = read_tiff(bytes, None).unwrap();
+        assert_eq!(field, Field::new(2, 1, vec![0.0, u16::MAX as f32]));
+    }
+
+    #[test]
+    fn resampling() {
+        // a linear ramp over points 1/4, 1/2 and 3/4 stays linear when
+        // refined to points 1/8, ..., 7/8, and constant past its ends
+        let coarse = Field::new(3, 1, vec![0.0, 1.0, 2.0]);
+        let fine = resample(&coarse, 7, 2);
+        assert_eq!(&fine.data[..7], [0.0, 0.0, 0.5, 1.0, 1.5, 2.0, 2.0]);
+        assert_eq!(fine.data[..7], fine.data[7..]);
+        // and is sampled exactly at points 1/3 and 2/3 when coarsened
+        let ramp = Field::new(5, 1, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(resample(&ramp, 2, 1).data, [1.0, 3.0]);
+        assert_eq!(resample(&ramp, 5, 1), ramp);
+    }
+}
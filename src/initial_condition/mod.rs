@@ -0,0 +1,74 @@
+//! Initial temperature fields, either generated or loaded from files.
+pub mod analytic;
+pub mod file;
+
+use std::{io, path::PathBuf};
+
+use crate::field::Field;
+
+use self::analytic::Analytic;
+
+/// Where the temperature at time zero comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitialCondition {
+    /// The sum of one or more analytic terms.
+    Analytic(Vec<Analytic>),
+    /// Loaded with [`file::load`] and resampled to the grid. Grayscale images
+    /// are mapped to `range`, from black to white.
+    File { path: PathBuf, range: [f32; 2] },
+}
+
+impl Default for InitialCondition {
+    /// A normalized gaussian bump with Perlin noise on top.
+    fn default() -> Self {
+        const SIGMA: f64 = 0.5;
+        InitialCondition::Analytic(vec![
+            Analytic::Gaussian {
+                center: [0.5, 0.5],
+                sigma: SIGMA,
+                amplitude: 1.0 / ((2.0 * std::f64::consts::PI).sqrt() * SIGMA),
+            },
+            Analytic::Perlin {
+                seed: 1,
+                frequency: 10.0,
+                octaves: 1,
+                amplitude: 1.0,
+            },
+        ])
+    }
+}
+
+impl InitialCondition {
+    /// Parses a `+` separated sum of analytic terms, such as
+    /// `gaussian:sigma=0.1+perlin:amplitude=0.1`, or else takes `spec` as the
+    /// path of a file.
+    pub fn parse(spec: &str, range: [f32; 2]) -> Result<Self, String> {
+        let name = spec.split([':', '+']).next().unwrap_or_default();
+        if Analytic::NAMES.contains(&name) {
+            let terms = spec.split('+').map(str::parse).collect::<Result<_, _>>()?;
+            Ok(InitialCondition::Analytic(terms))
+        } else {
+            Ok(InitialCondition::File {
+                path: PathBuf::from(spec),
+                range,
+            })
+        }
+    }
+
+    /// Evaluates the initial condition on an `n * n` grid.
+    pub fn field(&self, n: usize) -> io::Result<Field> {
+        match self {
+            InitialCondition::Analytic(terms) => {
+                let mut data = vec![0.0; n * n];
+                for term in terms {
+                    term.add_to(n, &mut data);
+                }
+                Ok(Field::new(n, n, data))
+            }
+            InitialCondition::File { path, range } => {
+                let field = file::load(path, *range)?;
+                Ok(file::resample(&field, n, n))
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Mixed-precision iterative refinement.
+//!
+//! Solving `A x = b` with the conjugate gradient method in double precision
+//! costs several times as much as in single precision, and most of the
+//! iterations are spent on digits that single precision would get right
+//! anyway. Iterative refinement only computes the residual `r = b - A x` and
+//! applies the correction `x = x + d` in the precision of `x`, while the
+//! correction is found by solving `A d = r` with the single precision
+//! [`CG`]. Each refinement gains about as many digits as the inner solve
+//! does, until the residual is as small as the precision of `x` allows.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{
+    conjugate_gradient::{CGBuffers, CGVariant, CG},
+    heat_equation::read_bytes_async,
+    kernels::{
+        convert::ConvertKernel,
+        correction::CorrectionKernel,
+        dot::{DotKernel, Summation},
+        kernel::Kernel,
+        saxpy_update::SAXPYUpdateKernel,
+    },
+    precision::Precision,
+    profiler::{encode_kernels, Profiler},
+    sparse_matrix::SparseMatrix,
+};
+
+/// When to stop refining.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RefinementOptions {
+    /// Relative residual `|b - A x| / |b|` below which the solution is
+    /// accepted.
+    pub tolerance: f64,
+    /// Largest number of corrections per solve.
+    pub max_iterations: usize,
+}
+
+impl Default for RefinementOptions {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-10,
+            max_iterations: 10,
+        }
+    }
+}
+
+/// Relative residuals of one refined solve.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RefinementReport {
+    /// `|b - A x| / |b|` of the initial guess, then after every correction.
+    pub residuals: Vec<f64>,
+}
+
+impl RefinementReport {
+    /// Number of corrections applied.
+    pub fn iterations(&self) -> usize {
+        self.residuals.len().saturating_sub(1)
+    }
+
+    /// Relative residual of the accepted solution.
+    pub fn residual(&self) -> f64 {
+        self.residuals.last().copied().unwrap_or_default()
+    }
+}
+
+/// Describes the intermediate buffers used by iterative refinement.
+///
+/// The single precision CG buffers are shared by all the solvers built over
+/// them, like [`CGBuffers`] themselves.
+#[derive(Debug)]
+pub struct RefinementBuffers {
+    cg: Rc<CGBuffers>,           // buffers of the inner solve
+    r: wgpu::Buffer,             // residual, in the precision of x
+    r32: wgpu::Buffer,           // residual rounded to f32
+    d: wgpu::Buffer,             // correction, in f32
+    tmp: wgpu::Buffer,           // scratch vector for the norms
+    residual_norm: wgpu::Buffer, // scalar
+    rhs_norm: wgpu::Buffer,      // scalar
+}
+
+impl RefinementBuffers {
+    pub fn new(
+        device: &wgpu::Device,
+        precision: Precision,
+        len: usize,    // number of values in the vectors
+        cg: CGVariant, // variant of the inner solve
+    ) -> Self {
+        let vector = |label, precision: Precision, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (len * precision.size()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+        let scalar = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: precision.size() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            cg: Rc::new(CGBuffers::new(device, Precision::Single, len, cg)),
+            r: vector("refinement r", precision, wgpu::BufferUsages::empty()),
+            r32: vector(
+                "refinement r32",
+                Precision::Single,
+                wgpu::BufferUsages::empty(),
+            ),
+            d: vector(
+                "refinement d",
+                Precision::Single,
+                wgpu::BufferUsages::COPY_DST,
+            ),
+            tmp: vector("refinement tmp", precision, wgpu::BufferUsages::empty()),
+            residual_norm: scalar("refinement residual norm"),
+            rhs_norm: scalar("refinement rhs norm"),
+        }
+    }
+}
+
+/// Solves `A x = b` for `x` in double or double-float precision, with the
+/// corrections computed by the single precision [`CG`] over a copy of `A`
+/// rounded to `f32`.
+pub struct IterativeRefinement {
+    buffers: Rc<RefinementBuffers>,
+    precision: Precision,
+    rhs_norm: DotKernel,            // |b|^2
+    residual: Vec<Box<dyn Kernel>>, // r = b - A x and |r|^2
+    round: ConvertKernel,           // r32 = r
+    inner: CG,                      // A32 d = r32
+    correct: CorrectionKernel,      // x = x + d
+    options: RefinementOptions,
+}
+
+impl IterativeRefinement {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        buffers: Rc<RefinementBuffers>,
+        a: &dyn SparseMatrix,   // Sparse matrix A, in the precision of x
+        a32: &dyn SparseMatrix, // A rounded to single precision
+        b: &wgpu::Buffer,       // Vector b
+        x: &wgpu::Buffer,       // Vector x initialized with initial guess x_0
+        summation: Summation,   // Accumulation of the dot products of the inner solve
+        options: RefinementOptions,
+    ) -> Self {
+        assert_eq!(
+            a32.precision(),
+            Precision::Single,
+            "inner matrix must be f32"
+        );
+        let precision = a.precision();
+        let RefinementBuffers {
+            cg,
+            r,
+            r32,
+            d,
+            tmp,
+            residual_norm,
+            rhs_norm,
+        } = buffers.as_ref();
+        let residual: Vec<Box<dyn Kernel>> = vec![
+            a.spmv(device, x, r),
+            Box::new(SAXPYUpdateKernel::new(device, precision, b, r)),
+            Box::new(DotKernel::new(device, precision, r, r, tmp, residual_norm)),
+        ];
+        Self {
+            rhs_norm: DotKernel::new(device, precision, b, b, tmp, rhs_norm),
+            residual,
+            round: ConvertKernel::new(device, precision, r, r32),
+            inner: CG::new(device, cg.clone(), a32, r32, d, summation),
+            correct: CorrectionKernel::new(device, precision, d, x),
+            buffers,
+            precision,
+            options,
+        }
+    }
+
+    /// Refines `x` until its relative residual is below the tolerance, or
+    /// the maximum number of corrections has been applied. Waits for the GPU
+    /// after every correction, to read the residual back.
+    ///
+    /// The kernels are timed by `profiler`, if any.
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        profiler: Option<&Profiler>,
+    ) -> RefinementReport {
+        let RefinementBuffers {
+            d,
+            residual_norm,
+            rhs_norm,
+            ..
+        } = self.buffers.as_ref();
+        self.submit(device, queue, [&self.rhs_norm as &dyn Kernel], profiler);
+        // an exact solution of A x = 0 is x = 0, so the residual is absolute
+        let rhs_norm = match self.read_scalar(device, queue, rhs_norm).sqrt() {
+            norm if norm > 0.0 => norm,
+            _ => 1.0,
+        };
+        let mut report = RefinementReport::default();
+        loop {
+            let residual = self.residual.iter().map(|k| k.as_ref());
+            self.submit(device, queue, residual, profiler);
+            let residual = self.read_scalar(device, queue, residual_norm).sqrt() / rhs_norm;
+            report.residuals.push(residual);
+            if residual <= self.options.tolerance
+                || report.iterations() >= self.options.max_iterations
+            {
+                return report;
+            }
+            // d = 0 is the initial guess of the inner solve
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Refinement Round Encoder"),
+            });
+            encoder.clear_buffer(d, 0, None);
+            encode_kernels(&mut encoder, None, [&self.round as &dyn Kernel], profiler);
+            self.inner.encode(&mut encoder, profiler);
+            queue.submit(Some(encoder.finish()));
+            self.submit(device, queue, [&self.correct as &dyn Kernel], profiler);
+        }
+    }
+
+    fn submit<'a>(
+        &'a self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        kernels: impl IntoIterator<Item = &'a dyn Kernel>,
+        profiler: Option<&Profiler>,
+    ) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Iterative Refinement"),
+        });
+        encode_kernels(&mut encoder, None, kernels, profiler);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn read_scalar(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+    ) -> f64 {
+        let bytes = read_bytes_async(device, queue, buffer);
+        device.poll(wgpu::Maintain::Wait);
+        let bytes = pollster::block_on(bytes).expect("failed to map the refinement buffer");
+        self.precision.decode(&bytes)[0]
+    }
+}
+
+/// Writes the refinement reports to a CSV file, one row per time step.
+pub struct RefinementLog<W: Write> {
+    writer: W,
+}
+
+impl RefinementLog<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> RefinementLog<W> {
+    pub const HEADER: &'static str = "step,iterations,initial_residual,residual";
+
+    /// Writes the header line to `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "{}", Self::HEADER)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, step: usize, report: &RefinementReport) -> io::Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{},{}",
+            step,
+            report.iterations(),
+            report.residuals.first().copied().unwrap_or_default(),
+            report.residual()
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows() {
+        let mut log = RefinementLog::new(Vec::new()).unwrap();
+        let report = RefinementReport {
+            residuals: vec![0.5, 0.25, 0.125],
+        };
+        log.write(3, &report).unwrap();
+        log.write(4, &RefinementReport::default()).unwrap();
+        let csv = String::from_utf8(log.writer).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "step,iterations,initial_residual,residual");
+        assert_eq!(lines[1], "3,2,0.5,0.125");
+        assert_eq!(lines[2], "4,0,0,0");
+        assert_eq!(lines.len(), 3);
+    }
+}
@@ -0,0 +1,146 @@
+use regex::Regex;
+
+use super::{kernel::Kernel, ExecutionStep};
+use crate::precision::Precision;
+
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Compute step of a single shader over vectors of `len` values, whose bind
+/// group holds `buffers` in the order of their bindings.
+fn step(
+    device: &wgpu::Device,
+    label: &str,
+    shader_string: String,
+    buffers: &[&wgpu::Buffer],
+    len: u32,
+) -> ExecutionStep {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: None,
+        module: &shader,
+        entry_point: "main",
+    });
+    let entries: Vec<_> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &entries,
+    });
+    ExecutionStep::new(bind_group, pipeline, (len.div_ceil(WORKGROUP_SIZE), 1, 1))
+}
+
+/// Updates of the fused conjugate gradient iteration: x = x + alpha * p and
+/// r = r - alpha * q with alpha = rho / pq, along with the products `r .* r`
+/// in the first half of `products`, as for [`spmv_dots`](super::spmv_dots).
+pub struct FusedUpdateKernel {
+    step: ExecutionStep,
+}
+
+impl FusedUpdateKernel {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        precision: Precision,
+        x: &wgpu::Buffer,
+        r: &wgpu::Buffer,
+        p: &wgpu::Buffer,
+        q: &wgpu::Buffer,
+        rho: &wgpu::Buffer,
+        pq: &wgpu::Buffer,
+        products: &wgpu::Buffer,
+    ) -> Self {
+        let len = (x.size() / precision.size() as u64) as u32;
+        Self {
+            step: step(
+                device,
+                "Fused CG update",
+                precision.shader(include_str!("../shaders/cg_update.wgsl")),
+                &[x, r, p, q, rho, pq, products],
+                len,
+            ),
+        }
+    }
+}
+
+impl Kernel for FusedUpdateKernel {
+    fn name(&self) -> &'static str {
+        "cg update"
+    }
+
+    fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        self.step.add_to_pass(pass);
+    }
+}
+
+/// Updates of an iteration of the pipelined conjugate gradient method, see
+/// `cg_pipelined.wgsl`.
+///
+/// `scalars` holds two slots of 3 values, and the iteration reads the dot
+/// products of its residual from slot `parity`.
+pub struct PipelinedUpdateKernel {
+    step: ExecutionStep,
+}
+
+impl PipelinedUpdateKernel {
+    /// Values of each slot of the scalars: `r . w`, `r . r` and the step
+    /// length.
+    pub const SLOT_LEN: usize = 3;
+
+    /// Shader of the iteration reading slot `parity`, for the `real` type of
+    /// any [`Precision`].
+    pub(crate) fn source(parity: usize) -> String {
+        let current = Regex::new(r"\{CURRENT\}").unwrap();
+        let previous = Regex::new(r"\{PREVIOUS\}").unwrap();
+        let source = include_str!("../shaders/cg_pipelined.wgsl");
+        let source = current.replace_all(source, (parity * Self::SLOT_LEN).to_string());
+        previous
+            .replace_all(&source, ((parity ^ 1) * Self::SLOT_LEN).to_string())
+            .to_string()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        precision: Precision,
+        x: &wgpu::Buffer,
+        r: &wgpu::Buffer,
+        p: &wgpu::Buffer,
+        s: &wgpu::Buffer,
+        w: &wgpu::Buffer,
+        scalars: &wgpu::Buffer,
+        parity: usize,
+    ) -> Self {
+        assert!(parity < 2, "parity must be 0 or 1");
+        let len = (x.size() / precision.size() as u64) as u32;
+        Self {
+            step: step(
+                device,
+                "Pipelined CG update",
+                precision.shader(&Self::source(parity)),
+                &[x, r, p, s, w, scalars],
+                len,
+            ),
+        }
+    }
+}
+
+impl Kernel for PipelinedUpdateKernel {
+    fn name(&self) -> &'static str {
+        "cg update"
+    }
+
+    fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        self.step.add_to_pass(pass);
+    }
+}
@@ -0,0 +1,148 @@
+use regex::Regex;
+
+use super::{kernel::Kernel, ExecutionStep};
+
+/// Computes output = x . y with compensated accumulation.
+///
+/// Every product and partial sum is kept as a double-float number, an
+/// unevaluated sum of two `f32`, using error-free transformations (two-sum, and
+/// Dekker's splitting for the products). The result is then accurate to
+/// about one `f32` rounding no matter the length of the vectors or the
+/// cancellation in the sum, where [`DotKernel`](super::dot::DotKernel) loses
+/// precision as the vectors grow.
+pub struct CompensatedDotKernel {
+    passes: Vec<ExecutionStep>,
+    _partials: Vec<wgpu::Buffer>,
+}
+
+impl CompensatedDotKernel {
+    const WORKGROUP_SIZE: u32 = 256;
+
+    pub fn new(
+        device: &wgpu::Device,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+        output: &wgpu::Buffer,
+    ) -> Self {
+        let block_size = 2 * Self::WORKGROUP_SIZE;
+        let mut len = y.size() as u32 / std::mem::size_of::<f32>() as u32;
+        assert!(len > 0, "cannot compute the dot product of empty vectors");
+
+        // First pass: block sums of the products, later passes: block sums of
+        // the previous block sums, until a single value is left
+        let mut passes = Vec::new();
+        let mut partials: Vec<wgpu::Buffer> = Vec::new();
+        // index of the scratch buffer read by the next pass, `None` for x and y
+        let mut pass_input: Option<usize> = None;
+        loop {
+            let num_groups = len.div_ceil(block_size);
+            let last = num_groups == 1;
+            if !last {
+                partials.push(device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Compensated dot block results"),
+                    size: (num_groups as usize * 2 * std::mem::size_of::<f32>()) as u64,
+                    usage: wgpu::BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                }));
+            }
+            let pass_output = if last {
+                output
+            } else {
+                partials.last().unwrap()
+            };
+            let step = match pass_input {
+                None => Self::pass(
+                    device,
+                    include_str!("../shaders/compensated_dot.wgsl"),
+                    &[x, y, pass_output],
+                    last,
+                    num_groups,
+                ),
+                Some(i) => Self::pass(
+                    device,
+                    include_str!("../shaders/compensated_sum_reduce.wgsl"),
+                    &[&partials[i], pass_output],
+                    last,
+                    num_groups,
+                ),
+            };
+            passes.push(step);
+            if last {
+                break;
+            }
+            pass_input = Some(partials.len() - 1);
+            len = num_groups;
+        }
+        Self {
+            passes,
+            _partials: partials,
+        }
+    }
+
+    /// Creates a pass from `shader`, writing double-float block results, or
+    /// their sum as a single `f32` if this is the `last` pass.
+    fn pass(
+        device: &wgpu::Device,
+        shader: &str,
+        buffers: &[&wgpu::Buffer],
+        last: bool,
+        num_groups: u32,
+    ) -> ExecutionStep {
+        let (output_type, output_value) = if last {
+            ("f32", "v.x + v.y")
+        } else {
+            ("vec2<f32>", "v")
+        };
+        let patterns = [
+            ("WORKGROUP_SIZE", Self::WORKGROUP_SIZE.to_string()),
+            ("OUTPUT_TYPE", output_type.to_string()),
+            ("OUTPUT_VALUE", output_value.to_string()),
+        ];
+        let shader_string = patterns.iter().fold(
+            format!("{}\n{shader}", include_str!("../shaders/double_float.wgsl")),
+            |acc, (name, replacement)| {
+                let pattern = Regex::new(&format!(r"\{{{name}\}}")).unwrap();
+                pattern.replace_all(&acc, replacement.as_str()).to_string()
+            },
+        );
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Compensated dot shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compensated dot pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let entries: Vec<_> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind group for compensated dot"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        ExecutionStep::new(bind_group, pipeline, (num_groups, 1, 1))
+    }
+}
+
+impl Kernel for CompensatedDotKernel {
+    fn name(&self) -> &'static str {
+        "dot"
+    }
+
+    fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        for step in self.passes.iter() {
+            step.add_to_pass(pass);
+        }
+    }
+}
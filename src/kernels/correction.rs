@@ -0,0 +1,66 @@
+use super::{kernel::Kernel, ExecutionStep};
+use crate::precision::Precision;
+
+/// Performs y = y + x, where x is a vector of `f32` and y a vector of values
+/// of any [`Precision`].
+pub struct CorrectionKernel {
+    step: ExecutionStep,
+}
+
+impl CorrectionKernel {
+    pub fn new(
+        device: &wgpu::Device,
+        precision: Precision,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+    ) -> Self {
+        const WORKGROUP_SIZE: u32 = 256;
+        let work_size = x.size() as u32 / std::mem::size_of::<f32>() as u32;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Correction shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                precision
+                    .shader(include_str!("../shaders/correct.wgsl"))
+                    .into(),
+            ),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Correction pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind group for correction"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: x.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: y.as_entire_binding(),
+                },
+            ],
+        });
+
+        let workgroups = (work_size.div_ceil(WORKGROUP_SIZE), 1, 1);
+
+        Self {
+            step: ExecutionStep::new(bind_group, pipeline, workgroups),
+        }
+    }
+}
+
+impl Kernel for CorrectionKernel {
+    fn name(&self) -> &'static str {
+        "correction"
+    }
+
+    fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        self.step.add_to_pass(pass);
+    }
+}
@@ -0,0 +1,52 @@
+use super::{
+    kernel::Kernel,
+    reduce::{ReduceKernel, ReduceOp},
+};
+use crate::precision::Precision;
+
+/// Reduces a vector of `f32` to its sum, minimum, maximum and sum of squares.
+///
+/// The four results are written to the first four values of `output`, in
+/// that order. Any vector length is supported. With a `mask`, a `u32` per
+/// value, only the values whose mask entry is not zero are reduced.
+pub struct DiagnosticsKernel {
+    reductions: [ReduceKernel; 4],
+}
+
+impl DiagnosticsKernel {
+    const OPS: [ReduceOp; 4] = [
+        ReduceOp::Sum,
+        ReduceOp::Min,
+        ReduceOp::Max,
+        ReduceOp::SumOfSquares,
+    ];
+
+    pub fn new(
+        device: &wgpu::Device,
+        x: &wgpu::Buffer,
+        mask: Option<&wgpu::Buffer>,
+        output: &wgpu::Buffer,
+    ) -> Self {
+        let single = Precision::Single;
+        Self {
+            reductions: std::array::from_fn(|i| match mask {
+                Some(mask) => {
+                    ReduceKernel::masked(device, single, Self::OPS[i], x, mask, output, i as u32)
+                }
+                None => ReduceKernel::new(device, single, Self::OPS[i], x, output, i as u32),
+            }),
+        }
+    }
+}
+
+impl Kernel for DiagnosticsKernel {
+    fn name(&self) -> &'static str {
+        "diagnostics"
+    }
+
+    fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        for reduction in self.reductions.iter() {
+            reduction.add_to_pass(pass);
+        }
+    }
+}
@@ -1,28 +1,50 @@
-use regex::Regex;
-
-use super::{kernel::Kernel, ExecutionStep};
+use super::{
+    kernel::Kernel,
+    reduce::{ReduceKernel, ReduceOp},
+    ExecutionStep,
+};
+use crate::precision::Precision;
+
+/// How the dot products of the conjugate gradient solver are accumulated in
+/// single precision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Summation {
+    /// Plain `f32` sums, with [`DotKernel`].
+    #[default]
+    Plain,
+    /// Double-float sums, with
+    /// [`CompensatedDotKernel`](super::compensated_dot::CompensatedDotKernel),
+    /// accurate to about one rounding at any vector length.
+    Compensated,
+}
 
+/// Computes output = x . y, using `tmp` to hold the element-wise product.
+///
+/// All buffers hold values of the same [`Precision`].
 pub struct DotKernel {
     vec_mul: ExecutionStep,
-    block_sum_reduce: ExecutionStep,
-    sum_reduce: ExecutionStep,
+    sum_reduce: ReduceKernel,
 }
 
 impl DotKernel {
     pub fn new(
         device: &wgpu::Device,
+        precision: Precision,
         x: &wgpu::Buffer,
         y: &wgpu::Buffer,
-        tmp0: &wgpu::Buffer,
-        tmp1: &wgpu::Buffer,
+        tmp: &wgpu::Buffer,
         output: &wgpu::Buffer,
     ) -> Self {
         const WORKGROUP_SIZE: u32 = 256;
-        let work_size = y.size() as u32 / std::mem::size_of::<f32>() as u32;
+        let work_size = y.size() as u32 / precision.size() as u32;
         // First stage of iteration: tmp = x .* y
         let vec_mul_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Element-wise vector multiplication shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/vec_mul.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                precision
+                    .shader(include_str!("../shaders/vec_mul.wgsl"))
+                    .into(),
+            ),
         });
 
         let vec_mul_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
@@ -48,101 +70,30 @@ impl DotKernel {
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: tmp0.as_entire_binding(),
+                    resource: tmp.as_entire_binding(),
                 },
             ],
         });
 
-        let vec_mul_workgroups = ((work_size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+        let vec_mul_workgroups = (work_size.div_ceil(WORKGROUP_SIZE), 1, 1);
 
-        // Second stage of iteration: output_vec = block_sum(tmp)
-        let shader_string = include_str!("../shaders/sum_reduce.wgsl");
-        let pattern = Regex::new(r"\{WORKGROUP_SIZE\}").unwrap();
-        let shader_string = pattern.replace_all(shader_string, WORKGROUP_SIZE.to_string().as_str());
-        let block_sum_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Parallel block sum-reduce shader"),
-            source: wgpu::ShaderSource::Wgsl(shader_string),
-        });
-
-        let block_sum_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Parallel block sum-reduce pipeline"),
-            layout: None,
-            module: &block_sum_shader,
-            entry_point: "main",
-        });
-
-        let block_sum_bind_group_layout = block_sum_pipeline.get_bind_group_layout(0);
-
-        let block_sum_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Bind group for parallel block sum-reduce"),
-            layout: &block_sum_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: tmp0.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: tmp1.as_entire_binding(),
-                },
-            ],
-        });
-
-        let block_sum_workgroups = ((work_size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
-
-        // Third stage of iteration: output = sum(output_vec) (final output as scalar)
-        let sum_shader_string = include_str!("../shaders/sum_reduce_final.wgsl");
-        let pattern = Regex::new(r"\{NUM_GROUPS\}").unwrap();
-        let num_groups = block_sum_workgroups.0;
-        let sum_shader_string =
-            pattern.replace_all(sum_shader_string, num_groups.to_string().as_str());
-        let sum_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Parallel sum-reduce shader"),
-            source: wgpu::ShaderSource::Wgsl(sum_shader_string),
-        });
-
-        let sum_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Parallel sum-reduce pipeline"),
-            layout: None,
-            module: &sum_shader,
-            entry_point: "main",
-        });
-
-        let sum_bind_group_layout = sum_pipeline.get_bind_group_layout(0);
-
-        let sum_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Bind group for parallel sum-reduce"),
-            layout: &sum_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: tmp1.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: output.as_entire_binding(),
-                },
-            ],
-        });
-
-        let sum_workgroups = (1, 1, 1);
+        // Second stage of iteration: output = sum(tmp)
+        let sum_reduce = ReduceKernel::new(device, precision, ReduceOp::Sum, tmp, output, 0);
 
         Self {
             vec_mul: ExecutionStep::new(vec_mul_bind_group, vec_mul_pipeline, vec_mul_workgroups),
-            block_sum_reduce: ExecutionStep::new(
-                block_sum_bind_group,
-                block_sum_pipeline,
-                block_sum_workgroups,
-            ),
-            sum_reduce: ExecutionStep::new(sum_bind_group, sum_pipeline, sum_workgroups),
+            sum_reduce,
         }
     }
 }
 
 impl Kernel for DotKernel {
+    fn name(&self) -> &'static str {
+        "dot"
+    }
+
     fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
         self.vec_mul.add_to_pass(pass);
-        self.block_sum_reduce.add_to_pass(pass);
         self.sum_reduce.add_to_pass(pass);
     }
 }
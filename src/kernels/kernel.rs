@@ -1,3 +1,6 @@
 pub trait Kernel {
+    /// Name of the kernel in profiles, shared by the kernels of an operation.
+    fn name(&self) -> &'static str;
+
     fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>);
 }
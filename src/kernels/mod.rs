@@ -1,8 +1,19 @@
+pub mod cg_update;
+pub mod compensated_dot;
+pub mod convert;
+pub mod correction;
+pub mod diagnostics;
 pub mod dot;
 pub mod kernel;
+pub mod probe;
+pub mod reduce;
 pub mod saxpy_update;
 pub mod saxpy_update_div;
 pub mod spmv;
+pub mod spmv_csr;
+pub mod spmv_dots;
+pub mod spmv_ell;
+pub mod stencil;
 pub mod write_to_texture;
 
 pub struct ExecutionStep {
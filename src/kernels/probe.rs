@@ -0,0 +1,83 @@
+use regex::Regex;
+
+use super::{kernel::Kernel, ExecutionStep};
+
+/// Samples an `n x n` field at arbitrary points by bilinear interpolation.
+///
+/// `points` holds one `vec2<f32>` per sample, in grid units: grid point `(i, j)`
+/// sits at `(i, j)`, and the zero boundary at `-1` and `n`. `output` receives
+/// one value per point.
+pub struct ProbeKernel {
+    step: ExecutionStep,
+}
+
+impl ProbeKernel {
+    pub fn new(
+        device: &wgpu::Device,
+        x: &wgpu::Buffer,
+        n: usize,
+        points: &wgpu::Buffer,
+        output: &wgpu::Buffer,
+    ) -> Self {
+        const WORKGROUP_SIZE: u32 = 64;
+        let num_points = points.size() as u32 / (2 * std::mem::size_of::<f32>()) as u32;
+        let patterns = [
+            (Regex::new(r"\{N\}").unwrap(), n as u32),
+            (Regex::new(r"\{WORKGROUP_SIZE\}").unwrap(), WORKGROUP_SIZE),
+        ];
+        let shader_string = patterns.iter().fold(
+            include_str!("../shaders/probe.wgsl").to_string(),
+            |acc, (pattern, replacement)| {
+                pattern
+                    .replace_all(&acc, replacement.to_string())
+                    .to_string()
+            },
+        );
+        let probe_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Probe shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let probe_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Probe pipeline"),
+            layout: None,
+            module: &probe_shader,
+            entry_point: "main",
+        });
+
+        let probe_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind group for probes"),
+            layout: &probe_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: x.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: points.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+
+        let workgroups = (num_points.div_ceil(WORKGROUP_SIZE), 1, 1);
+
+        Self {
+            step: ExecutionStep::new(probe_bind_group, probe_pipeline, workgroups),
+        }
+    }
+}
+
+impl Kernel for ProbeKernel {
+    fn name(&self) -> &'static str {
+        "probe"
+    }
+
+    fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        self.step.add_to_pass(pass);
+    }
+}
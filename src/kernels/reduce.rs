@@ -0,0 +1,302 @@
+use regex::Regex;
+
+use super::{kernel::Kernel, ExecutionStep};
+use crate::precision::Precision;
+
+/// Operation of a [`ReduceKernel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceOp {
+    Sum,
+    Min,
+    Max,
+    /// Sum of the squares of the values.
+    SumOfSquares,
+}
+
+impl ReduceOp {
+    const F32_MAX: &'static str = "3.40282347e38";
+
+    fn identity(self) -> String {
+        match self {
+            ReduceOp::Sum | ReduceOp::SumOfSquares => "0.0".to_string(),
+            ReduceOp::Min => Self::F32_MAX.to_string(),
+            ReduceOp::Max => format!("-{}", Self::F32_MAX),
+        }
+    }
+
+    fn combine(self) -> &'static str {
+        match self {
+            ReduceOp::Sum | ReduceOp::SumOfSquares => "real_add(a, b)",
+            ReduceOp::Min => "real_min(a, b)",
+            ReduceOp::Max => "real_max(a, b)",
+        }
+    }
+
+    /// Expression applied to the values read by the first pass. The later
+    /// passes combine block results, which are read as they are.
+    fn load(self, first_pass: bool) -> &'static str {
+        match self {
+            ReduceOp::SumOfSquares if first_pass => "real_mul(v, v)",
+            _ => "v",
+        }
+    }
+}
+
+/// Reduces a vector of any length to a single value, which is written to
+/// `output[output_index]`, or several vectors of the same length at once.
+///
+/// The input, the output and the block results all hold values of the same
+/// [`Precision`].
+///
+/// Each pass combines blocks of `2 * WORKGROUP_SIZE` values, so vectors longer
+/// than a block take several passes, with the block results kept in scratch
+/// buffers owned by the kernel. A pass dispatches one workgroup per block, up
+/// to `max_compute_workgroups_per_dimension` of them (65535 by default), past
+/// which each workgroup combines several blocks in turn. The number of
+/// segments is limited to `max_compute_workgroups_per_dimension` as well.
+pub struct ReduceKernel {
+    passes: Vec<ExecutionStep>,
+    _partials: Vec<wgpu::Buffer>,
+}
+
+impl ReduceKernel {
+    pub const WORKGROUP_SIZE: u32 = 256;
+
+    pub fn new(
+        device: &wgpu::Device,
+        precision: Precision,
+        op: ReduceOp,
+        input: &wgpu::Buffer,
+        output: &wgpu::Buffer,
+        output_index: u32,
+    ) -> Self {
+        let len = input.size() as u32 / precision.size() as u32;
+        Self::segments(device, precision, op, input, len, 1, output, output_index)
+    }
+
+    /// Like [`ReduceKernel::new`], but skips the values whose entry of `mask`,
+    /// which holds a `u32` per value of `input`, is zero.
+    pub fn masked(
+        device: &wgpu::Device,
+        precision: Precision,
+        op: ReduceOp,
+        input: &wgpu::Buffer,
+        mask: &wgpu::Buffer,
+        output: &wgpu::Buffer,
+        output_index: u32,
+    ) -> Self {
+        let len = input.size() as u32 / precision.size() as u32;
+        assert_eq!(
+            mask.size(),
+            len as u64 * std::mem::size_of::<u32>() as u64,
+            "mask must hold a u32 per value"
+        );
+        Self::build(
+            device,
+            precision,
+            op,
+            input,
+            Some(mask),
+            len,
+            1,
+            output,
+            output_index,
+        )
+    }
+
+    /// Reduces the first `count` consecutive segments of `len` values of
+    /// `input` in the same passes, writing the result of segment `k` to
+    /// `output[output_index + k]`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn segments(
+        device: &wgpu::Device,
+        precision: Precision,
+        op: ReduceOp,
+        input: &wgpu::Buffer,
+        len: u32,
+        count: u32,
+        output: &wgpu::Buffer,
+        output_index: u32,
+    ) -> Self {
+        Self::build(
+            device,
+            precision,
+            op,
+            input,
+            None,
+            len,
+            count,
+            output,
+            output_index,
+        )
+    }
+
+    /// Reduces `count` segments as in `segments`, skipping the values masked
+    /// out by `mask`, which applies to each segment in turn.
+    #[allow(clippy::too_many_arguments)]
+    fn build(
+        device: &wgpu::Device,
+        precision: Precision,
+        op: ReduceOp,
+        input: &wgpu::Buffer,
+        mask: Option<&wgpu::Buffer>,
+        len: u32,
+        count: u32,
+        output: &wgpu::Buffer,
+        output_index: u32,
+    ) -> Self {
+        let block_size = 2 * Self::WORKGROUP_SIZE;
+        let mut len = len;
+        assert!(len > 0, "cannot reduce an empty vector");
+        assert!(
+            len as u64 * count as u64 * precision.size() as u64 <= input.size(),
+            "segments must lie inside the input"
+        );
+
+        let max_workgroups = device.limits().max_compute_workgroups_per_dimension;
+        assert!(
+            count <= max_workgroups,
+            "cannot reduce {count} segments: at most {max_workgroups} fit in a dispatch"
+        );
+
+        let mut passes = Vec::new();
+        let mut partials: Vec<wgpu::Buffer> = Vec::new();
+        // index of the scratch buffer read by the next pass, `None` for the input
+        let mut input_index: Option<usize> = None;
+        loop {
+            let num_groups = len.div_ceil(block_size).min(max_workgroups);
+            let last = num_groups == 1;
+            if !last {
+                partials.push(device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Reduce block results"),
+                    size: num_groups as u64 * count as u64 * precision.size() as u64,
+                    usage: wgpu::BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                }));
+            }
+            let (pass_output, offset) = if last {
+                (output, output_index)
+            } else {
+                (partials.last().unwrap(), 0)
+            };
+            let first_pass = input_index.is_none();
+            // only the first pass reads the input values the mask refers to
+            let pass_mask = mask.filter(|_| first_pass);
+            let source = Self::source(op, first_pass, pass_mask.is_some(), offset);
+            // the segments of a pass fill its input binding
+            let pass_input = wgpu::BufferBinding {
+                buffer: input_index.map_or(input, |i| &partials[i]),
+                offset: 0,
+                size: wgpu::BufferSize::new(len as u64 * count as u64 * precision.size() as u64),
+            };
+            passes.push(Self::pass(
+                device,
+                precision.shader(&source),
+                pass_input,
+                pass_output,
+                pass_mask,
+                (num_groups, count, 1),
+            ));
+            if last {
+                break;
+            }
+            input_index = Some(partials.len() - 1);
+            len = num_groups;
+        }
+        Self {
+            passes,
+            _partials: partials,
+        }
+    }
+
+    /// Shader of a pass, for the `real` type of any [`Precision`]. A `masked`
+    /// pass binds the mask and skips the values it masks out.
+    pub(crate) fn source(
+        op: ReduceOp,
+        first_pass: bool,
+        masked: bool,
+        output_offset: u32,
+    ) -> String {
+        let (mask_binding, mask) = if masked {
+            (
+                "@group(0) @binding(2) var<storage, read> mask: array<u32>;",
+                "if (mask[i] == 0u) {\n        return identity();\n    }",
+            )
+        } else {
+            ("", "")
+        };
+        let patterns = [
+            ("MASK_BINDING", mask_binding.to_string()),
+            ("MASK", mask.to_string()),
+            ("WORKGROUP_SIZE", Self::WORKGROUP_SIZE.to_string()),
+            ("IDENTITY", op.identity()),
+            ("COMBINE", op.combine().to_string()),
+            ("LOAD", op.load(first_pass).to_string()),
+            ("OUTPUT_OFFSET", output_offset.to_string()),
+        ];
+        patterns.iter().fold(
+            include_str!("../shaders/sum_reduce.wgsl").to_string(),
+            |acc, (name, replacement)| {
+                let pattern = Regex::new(&format!(r"\{{{name}\}}")).unwrap();
+                pattern.replace_all(&acc, replacement.as_str()).to_string()
+            },
+        )
+    }
+
+    fn pass(
+        device: &wgpu::Device,
+        shader_string: String,
+        input: wgpu::BufferBinding,
+        output: &wgpu::Buffer,
+        mask: Option<&wgpu::Buffer>,
+        workgroups: (u32, u32, u32),
+    ) -> ExecutionStep {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Parallel block reduce shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Parallel block reduce pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(input),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: output.as_entire_binding(),
+            },
+        ];
+        if let Some(mask) = mask {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2,
+                resource: mask.as_entire_binding(),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind group for parallel block reduce"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        ExecutionStep::new(bind_group, pipeline, workgroups)
+    }
+}
+
+impl Kernel for ReduceKernel {
+    fn name(&self) -> &'static str {
+        "reduce"
+    }
+
+    fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        for step in self.passes.iter() {
+            step.add_to_pass(pass);
+        }
+    }
+}
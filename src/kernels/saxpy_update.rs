@@ -1,4 +1,5 @@
 use super::{kernel::Kernel, ExecutionStep};
+use crate::precision::Precision;
 
 /// Performs y = x - y
 pub struct SAXPYUpdateKernel {
@@ -6,12 +7,21 @@ pub struct SAXPYUpdateKernel {
 }
 
 impl SAXPYUpdateKernel {
-    pub fn new(device: &wgpu::Device, x: &wgpu::Buffer, y: &wgpu::Buffer) -> Self {
+    pub fn new(
+        device: &wgpu::Device,
+        precision: Precision,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+    ) -> Self {
         const WORKGROUP_SIZE: u32 = 256;
-        let work_size = y.size() as u32 / std::mem::size_of::<f32>() as u32;
+        let work_size = y.size() as u32 / precision.size() as u32;
         let saxpy_update_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("SAXPY update shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/saxpy_update.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                precision
+                    .shader(include_str!("../shaders/saxpy_update.wgsl"))
+                    .into(),
+            ),
         });
 
         let saxpy_update_bind_group_layout =
@@ -73,7 +83,7 @@ impl SAXPYUpdateKernel {
                 entry_point: "main",
             });
 
-        let workgroups = ((work_size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+        let workgroups = (work_size.div_ceil(WORKGROUP_SIZE), 1, 1);
 
         Self {
             step: ExecutionStep::new(saxpy_update_bind_group, saxpy_update_pipeline, workgroups),
@@ -82,6 +92,10 @@ impl SAXPYUpdateKernel {
 }
 
 impl Kernel for SAXPYUpdateKernel {
+    fn name(&self) -> &'static str {
+        "saxpy"
+    }
+
     fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
         self.step.add_to_pass(pass);
     }
@@ -1,9 +1,11 @@
 use regex::Regex;
 
 use super::{kernel::Kernel, ExecutionStep};
+use crate::precision::Precision;
 
-/// Performs y = (a1/a2) * x OP y
-/// where OP is either addition or subtraction.
+/// Performs y = y OP (a1/a2) * x
+/// where OP is either addition or subtraction,
+/// or y = x + (a1/a2) * y (see `Operation::Aypx`).
 pub struct SAXPYUpdateDivKernel {
     step: ExecutionStep,
 }
@@ -12,11 +14,34 @@ pub struct SAXPYUpdateDivKernel {
 pub enum Operation {
     Add,
     Sub,
+    /// y = x + (a1/a2) * y
+    Aypx,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 3] = [Operation::Add, Operation::Sub, Operation::Aypx];
 }
 
 impl SAXPYUpdateDivKernel {
+    /// Shader of the update, for the `real` type of any [`Precision`].
+    pub(crate) fn source(op: Operation) -> String {
+        let shader_source = include_str!("../shaders/saxpy_update_div.wgsl");
+        let pattern = Regex::new(r"\{UPDATE\}").unwrap();
+        pattern
+            .replace_all(
+                shader_source,
+                match op {
+                    Operation::Add => "real_add(a, real_mul(alpha, b))",
+                    Operation::Sub => "real_sub(a, real_mul(alpha, b))",
+                    Operation::Aypx => "real_add(b, real_mul(alpha, a))",
+                },
+            )
+            .to_string()
+    }
+
     pub fn new(
         device: &wgpu::Device,
+        precision: Precision,
         a1: &wgpu::Buffer,
         a2: &wgpu::Buffer,
         x: &wgpu::Buffer,
@@ -24,19 +49,11 @@ impl SAXPYUpdateDivKernel {
         op: Operation,
     ) -> Self {
         const WORKGROUP_SIZE: u64 = 256;
-        let work_size = y.size() / std::mem::size_of::<f32>() as u64;
-        let shader_source = include_str!("../shaders/saxpy_update_div.wgsl");
-        let pattern = Regex::new(r"\{OP\}").unwrap();
-        let shader_string = pattern.replace_all(
-            shader_source,
-            match op {
-                Operation::Add => "+",
-                Operation::Sub => "-",
-            },
-        );
+        let work_size = y.size() / precision.size() as u64;
+        let shader_string = precision.shader(&Self::source(op));
         let saxpy_update_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("SAXPY update div shader"),
-            source: wgpu::ShaderSource::Wgsl(shader_string),
+            source: wgpu::ShaderSource::Wgsl(shader_string.into()),
         });
 
         let saxpy_update_bind_group_layout =
@@ -128,11 +145,7 @@ impl SAXPYUpdateDivKernel {
                 entry_point: "main",
             });
 
-        let workgroups = (
-            ((work_size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE) as u32,
-            1,
-            1,
-        );
+        let workgroups = (work_size.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
 
         Self {
             step: ExecutionStep::new(saxpy_update_bind_group, saxpy_update_pipeline, workgroups),
@@ -141,6 +154,10 @@ impl SAXPYUpdateDivKernel {
 }
 
 impl Kernel for SAXPYUpdateDivKernel {
+    fn name(&self) -> &'static str {
+        "saxpy"
+    }
+
     fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
         self.step.add_to_pass(pass);
     }
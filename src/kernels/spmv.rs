@@ -1,9 +1,9 @@
-use super::{kernel::Kernel, ExecutionStep};
-use crate::dia_matrix::DIAMatrixDescriptor;
+use super::{kernel::Kernel, spmv_dots, ExecutionStep};
+use crate::{dia_matrix::DIAMatrixDescriptor, sparse_matrix::SparseMatrix};
 
 /// Specialized sparse matrix-vector multiplication kernel.
 ///
-/// Describes y = A * x.
+/// Describes y = A * x, where x and y hold values of the precision of A.
 pub struct SpMVKernel {
     step: ExecutionStep,
 }
@@ -14,12 +14,31 @@ impl SpMVKernel {
         a: &DIAMatrixDescriptor,
         x: &wgpu::Buffer,
         y: &wgpu::Buffer,
+    ) -> Self {
+        Self::with_dots(device, a, x, y, None)
+    }
+
+    /// Also stores the products `x .* y` and `x .* x` in `products`, if any,
+    /// see [`spmv_dots`].
+    pub fn with_dots(
+        device: &wgpu::Device,
+        a: &DIAMatrixDescriptor,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+        products: Option<&wgpu::Buffer>,
     ) -> Self {
         const WORKGROUP_SIZE: u32 = 256;
-        let work_size = y.size() as u32 / std::mem::size_of::<f32>() as u32;
+        let work_size = y.size() as u32 / a.precision.size() as u32;
         let spmv_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Sparse matrix-vector multiplication shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/spmv.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                a.precision
+                    .shader(&spmv_dots::source(
+                        include_str!("../shaders/spmv.wgsl"),
+                        products.map(|_| 5),
+                    ))
+                    .into(),
+            ),
         });
 
         let spmv_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
@@ -31,34 +50,46 @@ impl SpMVKernel {
 
         let spmv_bind_group_layout = spmv_pipeline.get_bind_group_layout(0);
 
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: x.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: a.params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: a.data.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: a.offsets.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: y.as_entire_binding(),
+            },
+        ];
+        if let Some(products) = products {
+            assert_eq!(
+                a.num_rows(),
+                a.num_cols(),
+                "dot products need a square matrix"
+            );
+            entries.push(wgpu::BindGroupEntry {
+                binding: 5,
+                resource: products.as_entire_binding(),
+            });
+        }
         let spmv_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Bind group for sparse matrix-vector multiplication"),
             layout: &spmv_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: x.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: a.params.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: a.data.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: a.offsets.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: y.as_entire_binding(),
-                },
-            ],
+            entries: &entries,
         });
 
-        let workgroups = ((work_size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE, 1, 1);
+        let workgroups = (work_size.div_ceil(WORKGROUP_SIZE), 1, 1);
 
         Self {
             step: ExecutionStep::new(spmv_bind_group, spmv_pipeline, workgroups),
@@ -67,6 +98,10 @@ impl SpMVKernel {
 }
 
 impl Kernel for SpMVKernel {
+    fn name(&self) -> &'static str {
+        "spmv"
+    }
+
     fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
         self.step.add_to_pass(pass);
     }
@@ -0,0 +1,34 @@
+//! Dot products computed along with a sparse matrix-vector product.
+//!
+//! Every SpMV shader calls `spmv_dots` with each row of `y = A * x`. The
+//! function is either empty, or stores `x .* y` and `x .* x` in a `products`
+//! buffer, which saves the dot products another pass over both vectors: a
+//! [`ReduceKernel::segments`](super::reduce::ReduceKernel::segments) then
+//! sums both halves at once.
+//!
+//! The products are summed by a separate reduction rather than per workgroup
+//! in the SpMV shader, since the barriers of a workgroup reduction in every
+//! SpMV workgroup cost more than the stored products on CPU adapters.
+use regex::Regex;
+
+/// Completes the shader `source` of a SpMV kernel with `spmv_dots`, which
+/// writes to a `products` buffer at `products_binding` if any.
+pub(crate) fn source(source: &str, products_binding: Option<u32>) -> String {
+    let spmv_dots = match products_binding {
+        Some(binding) => Regex::new(r"\{PRODUCTS_BINDING\}")
+            .unwrap()
+            .replace_all(
+                include_str!("../shaders/spmv_dots.wgsl"),
+                binding.to_string(),
+            )
+            .to_string(),
+        None => "fn spmv_dots(row: u32, len: u32, y: real) {}\n".to_string(),
+    };
+    format!("{source}\n{spmv_dots}")
+}
+
+/// Number of values of the `products` buffer of a SpMV kernel over `len`
+/// rows: `len` for each of both dot products.
+pub fn products_len(len: usize) -> usize {
+    2 * len
+}
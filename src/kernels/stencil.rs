@@ -0,0 +1,107 @@
+use regex::Regex;
+
+use super::{kernel::Kernel, spmv_dots, ExecutionStep};
+use crate::stencil_operator::StencilOperator;
+
+/// Matrix-free sparse matrix-vector multiplication kernel, which applies a
+/// [`StencilOperator`] to a grid.
+///
+/// Describes y = A * x, where x and y hold values of the precision of A.
+pub struct StencilKernel {
+    step: ExecutionStep,
+}
+
+impl StencilKernel {
+    pub fn new(
+        device: &wgpu::Device,
+        a: &StencilOperator,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+    ) -> Self {
+        Self::with_dots(device, a, x, y, None)
+    }
+
+    /// Also stores the products `x .* y` and `x .* x` in `products`, if any,
+    /// see [`spmv_dots`].
+    pub fn with_dots(
+        device: &wgpu::Device,
+        a: &StencilOperator,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+        products: Option<&wgpu::Buffer>,
+    ) -> Self {
+        const WORKGROUP_SIZE: u32 = 256;
+        let work_size = y.size() as u32 / a.precision.size() as u32;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Stencil shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                a.precision
+                    .shader(&spmv_dots::source(
+                        &Self::source(a.stencil.is_nine_point()),
+                        products.map(|_| 3),
+                    ))
+                    .into(),
+            ),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Stencil pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: x.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: a.params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: y.as_entire_binding(),
+            },
+        ];
+        if let Some(products) = products {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: products.as_entire_binding(),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind group for stencil"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        let workgroups = (work_size.div_ceil(WORKGROUP_SIZE), 1, 1);
+
+        Self {
+            step: ExecutionStep::new(bind_group, pipeline, workgroups),
+        }
+    }
+
+    /// Shader for 5-point or 9-point stencils, before the precision prelude.
+    pub(crate) fn source(nine_point: bool) -> String {
+        let pattern = Regex::new(r"\{NINE_POINT\}").unwrap();
+        pattern
+            .replace_all(
+                include_str!("../shaders/stencil.wgsl"),
+                nine_point.to_string(),
+            )
+            .to_string()
+    }
+}
+
+impl Kernel for StencilKernel {
+    fn name(&self) -> &'static str {
+        "spmv"
+    }
+
+    fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
+        self.step.add_to_pass(pass);
+    }
+}
@@ -74,8 +74,14 @@ impl WriteToTextureKernel {
                 module: &write_to_texture_shader,
                 entry_point: "main",
             });
+        // the shader has 16x16 workgroups and skips the points outside the texture
+        const WORKGROUP_SIZE: u32 = 16;
         let texture_size = t.size();
-        let workgroups = (texture_size.width / 8, texture_size.height / 8, 1);
+        let workgroups = (
+            texture_size.width.div_ceil(WORKGROUP_SIZE),
+            texture_size.height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
 
         Self {
             step: ExecutionStep::new(
@@ -88,6 +94,10 @@ impl WriteToTextureKernel {
 }
 
 impl Kernel for WriteToTextureKernel {
+    fn name(&self) -> &'static str {
+        "write to texture"
+    }
+
     fn add_to_pass<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>) {
         self.step.add_to_pass(pass);
     }
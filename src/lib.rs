@@ -1,10 +1,32 @@
 pub mod app;
+pub mod checkpoint;
 pub mod compute;
+pub mod config;
 pub mod conjugate_gradient;
+pub mod coo_matrix;
+pub mod cpu;
+pub mod csr_matrix;
 pub mod dia_matrix;
+pub mod diagnostics;
 mod directional_bind_group;
+pub mod ell_matrix;
+pub mod field;
+pub mod headless;
 pub mod heat_equation;
+pub mod initial_condition;
+pub mod iterative_refinement;
 pub mod kernels;
+pub mod mask;
+pub mod material;
+pub mod matrix_market;
+pub mod output;
+pub mod precision;
+pub mod probes;
+pub mod profiler;
 pub mod renderer;
 mod shader_tests;
+pub mod solver;
+pub mod sparse_matrix;
+pub mod stencil_operator;
+pub mod verification;
 pub mod vertex;
@@ -1,4 +1,8 @@
-use heat_wgpu::app::App;
+use heat_wgpu::{
+    app::App,
+    config::{Config, USAGE},
+    headless::Headless,
+};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::EventLoop,
@@ -8,11 +12,31 @@ use winit::{
 
 fn main() {
     env_logger::init();
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            print!("{USAGE}");
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    config
+        .export_matrices()
+        .expect("Failed to export the matrices");
+    if config.headless {
+        let mut headless = pollster::block_on(Headless::new(&config));
+        headless.run(&config).expect("Headless run failed");
+        return;
+    }
+
     let event_loop = EventLoop::new().expect("Event loop creation failed");
     let window = WindowBuilder::new()
         .build(&event_loop)
         .expect("Window builder creation failed");
-    let mut app = pollster::block_on(App::new(&window));
+    let mut app = pollster::block_on(App::new(&window, &config));
 
     event_loop
         .run(move |event, event_loop_window_target| {
@@ -26,11 +50,15 @@ fn main() {
                         WindowEvent::ScaleFactorChanged { .. } => {
                             app.resize(window.inner_size());
                         }
-                        WindowEvent::CloseRequested => event_loop_window_target.exit(),
+                        WindowEvent::CloseRequested => {
+                            app.finish();
+                            event_loop_window_target.exit()
+                        }
                         WindowEvent::KeyboardInput { event, .. } => {
                             if event.state.is_pressed()
                                 && matches!(event.physical_key, Code(KeyCode::Escape))
                             {
+                                app.finish();
                                 event_loop_window_target.exit();
                             }
                         }
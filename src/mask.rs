@@ -0,0 +1,243 @@
+//! Non-rectangular domains, described by a mask over the grid.
+//!
+//! Every grid point of the mask is either part of the domain or a wall. The
+//! solver keeps the walls at zero and decouples them from the domain, so the
+//! conjugate gradient solver only works on the active points: a wall row of
+//! the Crank-Nicolson system reads `u_new = 0`, and the active points next to
+//! a wall see it as a boundary of the domain, held at zero or insulated.
+use std::{io, path::PathBuf, str::FromStr};
+
+use crate::{heat_equation::HeatEquation, initial_condition::file};
+
+/// Kind of a grid point of a [`Mask`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    /// Part of the domain.
+    Active,
+    /// Wall held at zero, like the edges of the grid.
+    Dirichlet,
+    /// Insulated wall, through which no heat flows.
+    Insulated,
+}
+
+impl Cell {
+    /// Value of the cell in a grayscale mask texture: black for the domain,
+    /// gray for insulated walls and white for the walls held at zero.
+    pub fn label(self) -> u8 {
+        match self {
+            Cell::Active => 0,
+            Cell::Insulated => 128,
+            Cell::Dirichlet => 255,
+        }
+    }
+
+    /// Kind of a pixel of a mask image with the given brightness in `0..=1`:
+    /// white for the domain, gray for insulated walls and black for the
+    /// walls held at zero.
+    pub fn from_brightness(value: f32) -> Self {
+        if value < 0.25 {
+            Cell::Dirichlet
+        } else if value < 0.75 {
+            Cell::Insulated
+        } else {
+            Cell::Active
+        }
+    }
+}
+
+/// Kind of every point of a `width * height` grid, in row-major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<Cell>,
+}
+
+impl Mask {
+    pub fn new(width: usize, height: usize, cells: Vec<Cell>) -> Self {
+        assert_eq!(
+            cells.len(),
+            width * height,
+            "mask must have width * height cells"
+        );
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+
+    /// Number of points in the domain.
+    pub fn active_cells(&self) -> usize {
+        self.cells.iter().filter(|&&c| c == Cell::Active).count()
+    }
+
+    /// Sets the values of `data`, a field over the same grid, to zero
+    /// outside the domain.
+    pub fn apply<T: Copy + Default>(&self, data: &mut [T]) {
+        assert_eq!(data.len(), self.cells.len(), "field must match the mask");
+        self.apply_at(0, data);
+    }
+
+    /// Sets the values of `data`, a run of the grid starting at point
+    /// `offset` in row-major order, to zero outside the domain.
+    pub fn apply_at<T: Copy + Default>(&self, offset: usize, data: &mut [T]) {
+        for (value, cell) in data.iter_mut().zip(&self.cells[offset..]) {
+            if *cell != Cell::Active {
+                *value = T::default();
+            }
+        }
+    }
+
+    /// Grayscale values of the cells, see [`Cell::label`].
+    pub fn labels(&self) -> Vec<u8> {
+        self.cells.iter().map(|c| c.label()).collect()
+    }
+}
+
+/// Where a mask comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaskSource {
+    /// The unit square without its top right quadrant, held at zero.
+    LShape,
+    /// A disk in the unit square, held at zero unless `insulated`.
+    Hole {
+        center: [f64; 2],
+        radius: f64,
+        insulated: bool,
+    },
+    /// An image, see [`Cell::from_brightness`], sampled at the nearest pixel.
+    File(PathBuf),
+}
+
+impl MaskSource {
+    /// Evaluates the mask on an `n * n` grid.
+    pub fn mask(&self, n: usize) -> io::Result<Mask> {
+        let coordinate = |i| HeatEquation::grid_coordinate(i, n);
+        let cells = match self {
+            MaskSource::LShape => (0..n * n)
+                .map(|i| {
+                    let (x, y) = (coordinate(i % n), coordinate(i / n));
+                    // rows run top to bottom in the window
+                    if x > 0.5 && y < 0.5 {
+                        Cell::Dirichlet
+                    } else {
+                        Cell::Active
+                    }
+                })
+                .collect(),
+            MaskSource::Hole {
+                center,
+                radius,
+                insulated,
+            } => (0..n * n)
+                .map(|i| {
+                    let (x, y) = (coordinate(i % n), coordinate(i / n));
+                    let inside = (x - center[0]).hypot(y - center[1]) < *radius;
+                    match (inside, insulated) {
+                        (false, _) => Cell::Active,
+                        (true, false) => Cell::Dirichlet,
+                        (true, true) => Cell::Insulated,
+                    }
+                })
+                .collect(),
+            MaskSource::File(path) => {
+                let image = file::load(path, [0.0, 1.0])?;
+                // nearest pixel, since the labels cannot be interpolated
+                let nearest = |i: usize, source_n: usize| (i * source_n / n).min(source_n - 1);
+                (0..n * n)
+                    .map(|i| {
+                        let (x, y) = (nearest(i % n, image.width), nearest(i / n, image.height));
+                        Cell::from_brightness(image.get(x, y))
+                    })
+                    .collect()
+            }
+        };
+        Ok(Mask::new(n, n, cells))
+    }
+}
+
+impl FromStr for MaskSource {
+    type Err = String;
+
+    /// Parses `l-shape`, `hole` with optional `x`, `y`, `radius` and
+    /// `insulated` parameters as in `hole:radius=0.1,insulated=1`, or else
+    /// takes `spec` as the path of an image.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (name, params) = spec.split_once(':').unwrap_or((spec, ""));
+        match name {
+            "l-shape" if params.is_empty() => Ok(MaskSource::LShape),
+            "l-shape" => Err(format!("l-shape takes no parameters, found {params:?}")),
+            "hole" => {
+                let (mut center, mut radius, mut insulated) = ([0.5, 0.5], 0.2, false);
+                for p in params.split(',').filter(|p| !p.is_empty()) {
+                    let (key, value) = p
+                        .split_once('=')
+                        .ok_or(format!("expected key=value in hole, found {p:?}"))?;
+                    let value = value
+                        .trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid value {value:?} for {key} in hole"))?;
+                    match key.trim() {
+                        "x" => center[0] = value,
+                        "y" => center[1] = value,
+                        "radius" => radius = value,
+                        "insulated" => insulated = value != 0.0,
+                        other => return Err(format!("unknown parameter {other:?} for hole")),
+                    }
+                }
+                Ok(MaskSource::Hole {
+                    center,
+                    radius,
+                    insulated,
+                })
+            }
+            _ => Ok(MaskSource::File(PathBuf::from(spec))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sources() {
+        assert_eq!("l-shape".parse(), Ok(MaskSource::LShape));
+        assert_eq!(
+            "hole:radius=0.1,insulated=1".parse(),
+            Ok(MaskSource::Hole {
+                center: [0.5, 0.5],
+                radius: 0.1,
+                insulated: true,
+            })
+        );
+        assert_eq!(
+            "walls.png".parse(),
+            Ok(MaskSource::File(PathBuf::from("walls.png")))
+        );
+        assert!("hole:depth=1".parse::<MaskSource>().is_err());
+        assert!("l-shape:x=1".parse::<MaskSource>().is_err());
+    }
+
+    #[test]
+    fn shapes() {
+        let mask = MaskSource::LShape.mask(4).unwrap();
+        assert_eq!(mask.active_cells(), 12);
+        assert_eq!(mask.get(3, 0), Cell::Dirichlet);
+        assert_eq!(mask.get(0, 0), Cell::Active);
+        assert_eq!(mask.get(3, 3), Cell::Active);
+        let hole: MaskSource = "hole:insulated=1".parse().unwrap();
+        let mask = hole.mask(9).unwrap();
+        assert_eq!(mask.get(4, 4), Cell::Insulated);
+        assert_eq!(mask.get(0, 4), Cell::Active);
+        let mut data = vec![1.0; 81];
+        mask.apply(&mut data);
+        assert_eq!(data.iter().sum::<f32>(), mask.active_cells() as f32);
+        assert_eq!(mask.labels()[40], 128);
+    }
+}
@@ -0,0 +1,283 @@
+//! Materials with their own density, specific heat and conductivity.
+//!
+//! A material table lists the materials, and a material map assigns one of
+//! them to every grid point. The solver then integrates
+//! `ρ c ∂u/∂t = ∇·(k ∇u)` instead of the constant coefficient heat equation:
+//! the heat capacity `ρ c` of each point scales its row of the mass matrix,
+//! and the conductivity across a face between two points is the harmonic
+//! mean of theirs, which keeps the heat flux continuous across interfaces.
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{checkpoint, initial_condition::file};
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Physical properties of a material.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub density: f64,
+    pub specific_heat: f64,
+    pub conductivity: f64,
+    /// Color of the material in map images, if any.
+    pub color: Option<[u8; 3]>,
+}
+
+impl Material {
+    /// Heat capacity per unit volume, `ρ c`.
+    pub fn heat_capacity(&self) -> f64 {
+        self.density * self.specific_heat
+    }
+
+    /// Thermal diffusivity, `k / (ρ c)`.
+    pub fn diffusivity(&self) -> f64 {
+        self.conductivity / self.heat_capacity()
+    }
+}
+
+/// Conductivity across the face between two points of conductivities `k1`
+/// and `k2`, the harmonic mean of both.
+pub fn interface_conductivity(k1: f64, k2: f64) -> f64 {
+    if k1 == k2 {
+        k1
+    } else {
+        2.0 * k1 * k2 / (k1 + k2)
+    }
+}
+
+/// Reads a material table: a header line, then one material per line as
+/// `name,density,specific_heat,conductivity` with an optional `#rrggbb`
+/// color. Empty lines and lines starting with `#` are skipped.
+pub fn read_table<R: BufRead>(r: R) -> io::Result<Vec<Material>> {
+    let mut materials = Vec::new();
+    let mut header = true;
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if std::mem::take(&mut header) {
+            continue;
+        }
+        let error = |msg: String| invalid(format!("line {}: {msg}", i + 1));
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let (name, values, color) = match fields.len() {
+            4 => (fields[0], &fields[1..4], None),
+            5 => (fields[0], &fields[1..4], Some(fields[4])),
+            len => return Err(error(format!("expected 4 or 5 values, found {len}"))),
+        };
+        let values = values
+            .iter()
+            .map(|v| match v.parse::<f64>() {
+                Ok(v) if v > 0.0 => Ok(v),
+                _ => Err(error(format!("invalid positive number {v:?}"))),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let color = color
+            .map(|color| {
+                color
+                    .strip_prefix('#')
+                    .filter(|hex| hex.len() == 6)
+                    .and_then(|hex| {
+                        let channel = |i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+                        Some([channel(0)?, channel(2)?, channel(4)?])
+                    })
+                    .ok_or_else(|| error(format!("invalid color {color:?}, expected #rrggbb")))
+            })
+            .transpose()?;
+        materials.push(Material {
+            name: name.to_string(),
+            density: values[0],
+            specific_heat: values[1],
+            conductivity: values[2],
+            color,
+        });
+    }
+    if materials.is_empty() {
+        return Err(invalid("the material table lists no materials"));
+    }
+    Ok(materials)
+}
+
+/// Loads a material table from `path`, see [`read_table`].
+pub fn load_table(path: impl AsRef<Path>) -> io::Result<Vec<Material>> {
+    read_table(BufReader::new(File::open(path)?))
+}
+
+/// Material of every point of a `width * height` grid, in row-major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialMap {
+    pub width: usize,
+    pub height: usize,
+    pub materials: Vec<Material>,
+    /// Index into `materials` of every point.
+    pub indices: Vec<usize>,
+}
+
+impl MaterialMap {
+    pub fn new(width: usize, height: usize, materials: Vec<Material>, indices: Vec<usize>) -> Self {
+        assert_eq!(
+            indices.len(),
+            width * height,
+            "material map must have width * height indices"
+        );
+        assert!(
+            indices.iter().all(|&i| i < materials.len()),
+            "material index out of range"
+        );
+        Self {
+            width,
+            height,
+            materials,
+            indices,
+        }
+    }
+
+    /// The same material everywhere.
+    pub fn uniform(width: usize, height: usize, material: Material) -> Self {
+        Self::new(width, height, vec![material], vec![0; width * height])
+    }
+
+    /// Material of grid point `i`, in row-major order.
+    pub fn material(&self, i: usize) -> &Material {
+        &self.materials[self.indices[i]]
+    }
+
+    /// Identifies the map in checkpoints, from the properties of the material
+    /// of every point, see [`checkpoint::fingerprint`].
+    pub fn fingerprint(&self) -> u64 {
+        let bytes: Vec<u8> = (0..self.indices.len())
+            .map(|i| self.material(i))
+            .flat_map(|m| [m.density, m.specific_heat, m.conductivity])
+            .flat_map(f64::to_le_bytes)
+            .collect();
+        checkpoint::fingerprint(&bytes)
+    }
+
+    /// Loads the map of `materials` from `path` onto an `n * n` grid, sampled
+    /// at the nearest pixel.
+    ///
+    /// `.npy`, CSV and TIFF files hold the index of the material of every
+    /// point. Any other file is an image whose colors are those of the table.
+    pub fn load(path: impl AsRef<Path>, materials: Vec<Material>, n: usize) -> io::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        let (width, height, source) = match extension.as_deref() {
+            Some("npy" | "csv" | "txt" | "tif" | "tiff") => {
+                let field = file::load_samples(path)?;
+                let indices = field
+                    .data
+                    .iter()
+                    .map(|&v| match v {
+                        v if v >= 0.0 && v.fract() == 0.0 && (v as usize) < materials.len() => {
+                            Ok(v as usize)
+                        }
+                        v => Err(invalid(format!("invalid material index {v}"))),
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                (field.width, field.height, indices)
+            }
+            _ => {
+                let image = image::open(path)
+                    .map_err(|e| match e {
+                        image::ImageError::IoError(e) => e,
+                        e => invalid(e.to_string()),
+                    })?
+                    .into_rgb8();
+                let indices = image
+                    .pixels()
+                    .map(|p| {
+                        materials
+                            .iter()
+                            .position(|m| m.color == Some(p.0))
+                            .ok_or_else(|| invalid(format!("no material has the color {:?}", p.0)))
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                (image.width() as usize, image.height() as usize, indices)
+            }
+        };
+        // nearest pixel, since the indices cannot be interpolated
+        let nearest = |i: usize, source_n: usize| (i * source_n / n).min(source_n - 1);
+        let indices = (0..n * n)
+            .map(|i| source[nearest(i / n, height) * width + nearest(i % n, width)])
+            .collect();
+        Ok(Self::new(n, n, materials, indices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = "\
+name,density,specific_heat,conductivity,color
+# metals
+copper, 8960, 385, 401, #b87333
+air,1.2,1005,0.026
+";
+
+    #[test]
+    fn reads_tables() {
+        let materials = read_table(TABLE.as_bytes()).unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "copper");
+        assert_eq!(materials[0].color, Some([0xb8, 0x73, 0x33]));
+        assert_eq!(materials[0].heat_capacity(), 8960.0 * 385.0);
+        assert_eq!(materials[1].color, None);
+        assert!((materials[1].diffusivity() - 0.026 / 1206.0).abs() < 1e-18);
+        assert!(read_table("name\n".as_bytes()).is_err());
+        assert!(read_table("h\nsteel,7850,-1,50\n".as_bytes()).is_err());
+        assert!(read_table("h\nsteel,7850,490,50,red\n".as_bytes()).is_err());
+        assert!(read_table("h\nsteel,7850,490\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn loads_tiff_indices() {
+        use tiff::encoder::{colortype::Gray8, TiffEncoder};
+
+        let path = std::env::temp_dir().join(format!("materials-{}.tif", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        TiffEncoder::new(&mut file)
+            .unwrap()
+            .write_image::<Gray8>(2, 2, &[0, 1, 2, 1])
+            .unwrap();
+        drop(file);
+        let materials = read_table(TABLE.as_bytes()).unwrap();
+        let three = [materials.clone(), materials[..1].to_vec()].concat();
+        let map = MaterialMap::load(&path, three, 2);
+        let too_few = MaterialMap::load(&path, materials, 2);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(map.unwrap().indices, vec![0, 1, 2, 1]);
+        assert!(too_few.is_err());
+    }
+
+    #[test]
+    fn fingerprints() {
+        let materials = read_table(TABLE.as_bytes()).unwrap();
+        let swapped = vec![materials[1].clone(), materials[0].clone()];
+        let map = MaterialMap::new(2, 1, materials.clone(), vec![0, 1]);
+        // only the properties of every point count, not the order of the table
+        let same = MaterialMap::new(2, 1, swapped, vec![1, 0]);
+        assert_eq!(map.fingerprint(), same.fingerprint());
+        let flipped = MaterialMap::new(2, 1, materials, vec![1, 0]);
+        assert_ne!(map.fingerprint(), flipped.fingerprint());
+    }
+
+    #[test]
+    fn interfaces() {
+        assert_eq!(interface_conductivity(2.0, 2.0), 2.0);
+        assert_eq!(interface_conductivity(1.0, 3.0), 1.5);
+        // a good conductor next to an insulator conducts like twice the insulator
+        assert!((interface_conductivity(1e6, 1.0) - 2.0).abs() < 1e-5);
+    }
+}
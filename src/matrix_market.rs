@@ -0,0 +1,212 @@
+//! Reading and writing sparse matrices as Matrix Market files.
+//!
+//! Matrix Market `.mtx` files are plain text, and are read by SciPy
+//! (`scipy.io.mmread`), MATLAB, Julia and most sparse solvers, so the matrices
+//! of the solver can be checked with external tools. Only the coordinate
+//! format with real or integer values is supported, as `general` or
+//! `symmetric` matrices:
+//!
+//! ```text
+//! %%MatrixMarket matrix coordinate real general
+//! % comments
+//! rows cols entries
+//! row col value
+//! ...
+//! ```
+//!
+//! Indices are 1-based. A symmetric file only holds the lower triangle.
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::coo_matrix::COOMatrix;
+
+/// Errors when reading a Matrix Market file.
+#[derive(Debug)]
+pub enum MatrixMarketError {
+    Io(io::Error),
+    /// The file is not a Matrix Market file, or is corrupted.
+    InvalidFormat(String),
+    /// The file holds a kind of matrix that is not supported.
+    Unsupported(String),
+}
+
+impl fmt::Display for MatrixMarketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixMarketError::Io(e) => write!(f, "{e}"),
+            MatrixMarketError::InvalidFormat(msg) => write!(f, "invalid Matrix Market file: {msg}"),
+            MatrixMarketError::Unsupported(msg) => {
+                write!(f, "unsupported Matrix Market file: {msg}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MatrixMarketError {}
+
+impl From<io::Error> for MatrixMarketError {
+    fn from(e: io::Error) -> Self {
+        MatrixMarketError::Io(e)
+    }
+}
+
+/// Writes the entries of `a` as a general real matrix, in the order they were
+/// pushed. The values are written with as many digits as it takes to read them
+/// back exactly.
+pub fn write<W: Write>(w: &mut W, a: &COOMatrix) -> io::Result<()> {
+    writeln!(w, "%%MatrixMarket matrix coordinate real general")?;
+    writeln!(w, "{} {} {}", a.num_rows, a.num_cols, a.entries.len())?;
+    for &(row, col, value) in a.entries.iter() {
+        writeln!(w, "{} {} {:e}", row + 1, col + 1, value)?;
+    }
+    Ok(())
+}
+
+pub fn read<R: BufRead>(r: &mut R) -> Result<COOMatrix, MatrixMarketError> {
+    let invalid = |msg: String| MatrixMarketError::InvalidFormat(msg);
+    let mut lines = r.lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid("empty file".to_string()))??;
+    let header: Vec<String> = header
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect();
+    if header.len() != 5 || header[0] != "%%matrixmarket" || header[1] != "matrix" {
+        return Err(invalid("missing %%MatrixMarket matrix header".to_string()));
+    }
+    let unsupported =
+        |what: &str, value: &str| MatrixMarketError::Unsupported(format!("{what} {value:?}"));
+    if header[2] != "coordinate" {
+        return Err(unsupported("format", &header[2]));
+    }
+    if header[3] != "real" && header[3] != "integer" {
+        return Err(unsupported("field", &header[3]));
+    }
+    let symmetric = match header[4].as_str() {
+        "general" => false,
+        "symmetric" => true,
+        other => return Err(unsupported("symmetry", other)),
+    };
+
+    // the size line and the entries, without comments and blank lines
+    let mut lines = lines.filter(|line| {
+        line.as_ref().map_or(true, |line| {
+            !line.starts_with('%') && !line.trim().is_empty()
+        })
+    });
+    let mut numbers = |count: usize| -> Result<Vec<String>, MatrixMarketError> {
+        let line = lines
+            .next()
+            .ok_or_else(|| invalid("truncated file".to_string()))??;
+        let words: Vec<String> = line.split_whitespace().map(String::from).collect();
+        if words.len() != count {
+            return Err(invalid(format!("expected {count} numbers, found {line:?}")));
+        }
+        Ok(words)
+    };
+    let parse_index = |word: &str| {
+        word.parse::<usize>()
+            .map_err(|_| invalid(format!("invalid index {word:?}")))
+    };
+    let size = numbers(3)?;
+    let [num_rows, num_cols, num_entries] = [0, 1, 2].map(|i| parse_index(&size[i]));
+    let (num_rows, num_cols, num_entries) = (num_rows?, num_cols?, num_entries?);
+    if symmetric && num_rows != num_cols {
+        return Err(invalid("a symmetric matrix must be square".to_string()));
+    }
+    let mut a = COOMatrix::new(num_cols, num_rows);
+    for _ in 0..num_entries {
+        let entry = numbers(3)?;
+        let (row, col) = (parse_index(&entry[0])?, parse_index(&entry[1])?);
+        if row == 0 || row > num_rows || col == 0 || col > num_cols {
+            return Err(invalid(format!("entry ({row}, {col}) outside the matrix")));
+        }
+        let value: f64 = entry[2]
+            .parse()
+            .map_err(|_| invalid(format!("invalid value {:?}", entry[2])))?;
+        a.push(row - 1, col - 1, value);
+        if symmetric && row != col {
+            a.push(col - 1, row - 1, value);
+        }
+    }
+    Ok(a)
+}
+
+pub fn save(path: impl AsRef<Path>, a: &COOMatrix) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write(&mut w, a)?;
+    w.flush()
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<COOMatrix, MatrixMarketError> {
+    read(&mut BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut a = COOMatrix::new(3, 2);
+        a.push(1, 2, 0.1);
+        a.push(0, 0, -1.0 / 3.0);
+        a.push(1, 2, 1e-300);
+        let mut bytes = Vec::new();
+        write(&mut bytes, &a).unwrap();
+        let text = String::from_utf8(bytes.clone()).unwrap();
+        assert!(text.starts_with("%%MatrixMarket matrix coordinate real general\n2 3 3\n2 3 "));
+        assert_eq!(read(&mut bytes.as_slice()).unwrap(), a);
+    }
+
+    #[test]
+    fn reads_symmetric_files() {
+        let text = "%%MatrixMarket matrix coordinate integer symmetric\n\
+                    % a comment\n\
+                    \n\
+                    2 2 2\n\
+                    1 1 4\n\
+                    2 1 -1\n";
+        let a = read(&mut text.as_bytes()).unwrap();
+        assert_eq!((a.num_rows, a.num_cols), (2, 2));
+        assert_eq!(
+            a.compressed(),
+            vec![(0, 0, 4.0), (0, 1, -1.0), (1, 0, -1.0)]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let read = |text: &str| read(&mut text.as_bytes());
+        assert!(matches!(
+            read("2 2 0\n"),
+            Err(MatrixMarketError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            read("%%MatrixMarket matrix array real general\n2 2\n"),
+            Err(MatrixMarketError::Unsupported(_))
+        ));
+        assert!(matches!(
+            read("%%MatrixMarket matrix coordinate complex general\n"),
+            Err(MatrixMarketError::Unsupported(_))
+        ));
+        let header = "%%MatrixMarket matrix coordinate real general\n";
+        assert!(matches!(
+            read(&format!("{header}2 2 2\n1 1 1.0\n")),
+            Err(MatrixMarketError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            read(&format!("{header}2 2 1\n3 1 1.0\n")),
+            Err(MatrixMarketError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            read(&format!("{header}2 2 1\n1 1 one\n")),
+            Err(MatrixMarketError::InvalidFormat(_))
+        ));
+    }
+}
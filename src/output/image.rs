@@ -0,0 +1,203 @@
+//! Writers for image files: colormapped PNGs that look like the window, and
+//! single channel 32-bit float EXR or TIFF files that keep the raw values.
+//!
+//! Row 0 of the field is the top row of the image, as on screen.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::field::Field;
+
+use super::Recorder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// 8-bit RGB, colormapped with [`turbo`].
+    Png,
+    /// OpenEXR with a single 32-bit float `Y` channel.
+    Exr,
+    /// TIFF with a single 32-bit float sample per pixel.
+    Tiff,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Exr => "exr",
+            Format::Tiff => "tiff",
+        }
+    }
+}
+
+/// Turbo colormap, the same polynomial approximation as `TurboColormap` in
+/// `render.wgsl`. Values outside `[0, 1]` are clamped.
+// the coefficients are copied verbatim from the shader
+#[allow(clippy::excessive_precision)]
+pub fn turbo(x: f32) -> [f32; 3] {
+    const RED_VEC4: [f32; 4] = [0.13572138, 4.61539260, -42.66032258, 132.13108234];
+    const GREEN_VEC4: [f32; 4] = [0.09140261, 2.19418839, 4.84296658, -14.18503333];
+    const BLUE_VEC4: [f32; 4] = [0.10667330, 12.64194608, -60.58204836, 110.36276771];
+    const RED_VEC2: [f32; 2] = [-152.94239396, 59.28637943];
+    const GREEN_VEC2: [f32; 2] = [4.27729857, 2.82956604];
+    const BLUE_VEC2: [f32; 2] = [-89.90310912, 27.34824973];
+
+    let y = x.clamp(0.0, 1.0);
+    let v4 = [1.0, y, y * y, y * y * y];
+    let v2 = [v4[2] * v4[2], v4[3] * v4[2]];
+    let channel = |k4: [f32; 4], k2: [f32; 2]| {
+        v4.iter().zip(k4).map(|(v, k)| v * k).sum::<f32>() + v2[0] * k2[0] + v2[1] * k2[1]
+    };
+    [
+        channel(RED_VEC4, RED_VEC2),
+        channel(GREEN_VEC4, GREEN_VEC2),
+        channel(BLUE_VEC4, BLUE_VEC2),
+    ]
+}
+
+/// Maps every value of the field through [`turbo`].
+pub fn colormap(field: &Field) -> image::RgbImage {
+    let mut img = image::RgbImage::new(field.width as u32, field.height as u32);
+    for (pixel, &value) in img.pixels_mut().zip(field.data.iter()) {
+        let rgb = turbo(value).map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        *pixel = image::Rgb(rgb);
+    }
+    img
+}
+
+/// Saves a field to `path` as a colormapped PNG.
+pub fn save_png(path: impl AsRef<Path>, field: &Field) -> io::Result<()> {
+    colormap(field)
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(io::Error::other)
+}
+
+/// Saves the raw values of a field to `path` as a single channel float EXR.
+pub fn save_exr(path: impl AsRef<Path>, field: &Field) -> io::Result<()> {
+    use exr::prelude::*;
+
+    let channel = AnyChannel::new("Y", FlatSamples::F32(field.data.clone()));
+    let layer = Layer::new(
+        (field.width, field.height),
+        LayerAttributes::named("temperature"),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(SmallVec::from_vec(vec![channel])),
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(|e| match e {
+            Error::Io(e) => e,
+            e => io::Error::other(e),
+        })
+}
+
+/// Writes the raw values of a field as a single channel float TIFF.
+pub fn write_tiff<W: Write + io::Seek>(w: &mut W, field: &Field) -> io::Result<()> {
+    use tiff::{encoder::colortype::Gray32Float, TiffError};
+
+    let to_io = |e| match e {
+        TiffError::IoError(e) => e,
+        e => io::Error::other(e),
+    };
+    let mut encoder = tiff::encoder::TiffEncoder::new(w).map_err(to_io)?;
+    encoder
+        .write_image::<Gray32Float>(field.width as u32, field.height as u32, &field.data)
+        .map_err(to_io)
+}
+
+/// Saves the raw values of a field to `path` as a single channel float TIFF.
+pub fn save_tiff(path: impl AsRef<Path>, field: &Field) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_tiff(&mut w, field)?;
+    w.flush()
+}
+
+/// Saves a field to `path` in the given format.
+pub fn save(path: impl AsRef<Path>, field: &Field, format: Format) -> io::Result<()> {
+    match format {
+        Format::Png => save_png(path, field),
+        Format::Exr => save_exr(path, field),
+        Format::Tiff => save_tiff(path, field),
+    }
+}
+
+/// Records the field as a numbered image sequence, `<prefix>_<step>.<ext>`.
+pub struct ImageRecorder {
+    directory: PathBuf,
+    prefix: String,
+    format: Format,
+    interval: usize,
+}
+
+impl ImageRecorder {
+    pub fn new(
+        directory: impl Into<PathBuf>,
+        prefix: &str,
+        format: Format,
+        interval: usize,
+    ) -> io::Result<Self> {
+        assert!(interval > 0, "recording interval must be at least one step");
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            prefix: prefix.to_string(),
+            format,
+            interval,
+        })
+    }
+}
+
+impl Recorder for ImageRecorder {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn record(&mut self, step: usize, _time: f32, field: &Field) -> io::Result<()> {
+        let name = format!("{}_{step:06}.{}", self.prefix, self.format.extension());
+        save(self.directory.join(name), field, self.format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turbo_endpoints() {
+        // dark at the bottom of the range, blue near it and dark red at the top
+        assert!(turbo(0.0).iter().sum::<f32>() < 0.5);
+        let [r, g, b] = turbo(0.15);
+        assert!(b > r && b > g);
+        let [r, g, b] = turbo(1.0);
+        assert!(r > g && r > b);
+        assert_eq!(turbo(-1.0), turbo(0.0));
+        assert_eq!(turbo(2.0), turbo(1.0));
+    }
+
+    #[test]
+    fn colormap_keeps_orientation() {
+        let field = Field::new(2, 2, vec![0.0, 0.0, 1.0, 1.0]);
+        let img = colormap(&field);
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(img.get_pixel(1, 0), img.get_pixel(0, 0));
+        assert_ne!(img.get_pixel(0, 1), img.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn tiff_round_trip() {
+        let field = Field::new(3, 2, vec![0.0, 0.5, 1.0, -1.5, 2.25, 1e-3]);
+        let mut bytes = io::Cursor::new(Vec::new());
+        write_tiff(&mut bytes, &field).unwrap();
+        bytes.set_position(0);
+        let mut decoder = tiff::decoder::Decoder::new(bytes).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+        match decoder.read_image().unwrap() {
+            tiff::decoder::DecodingResult::F32(data) => assert_eq!(data, field.data),
+            _ => panic!("expected 32-bit float samples"),
+        }
+    }
+}
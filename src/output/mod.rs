@@ -0,0 +1,85 @@
+//! Exporting the temperature field to files while the simulation runs.
+pub mod image;
+pub mod npy;
+pub mod vtk;
+
+use std::io;
+
+use crate::{field::Field, solver::Solver};
+
+/// Something that writes snapshots of the solution to disk.
+pub trait Recorder {
+    /// Number of time steps between two snapshots.
+    fn interval(&self) -> usize;
+
+    /// Writes the snapshot taken after `step` time steps, at simulation time `time`.
+    fn record(&mut self, step: usize, time: f32, field: &Field) -> io::Result<()>;
+
+    /// Flushes whatever the recorder still holds. Called after the last step,
+    /// or once `record` failed, and must do nothing when called again.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The set of recorders attached to a run.
+///
+/// The field is only copied back from the solver when at least one recorder
+/// is due, and then shared by all of them.
+#[derive(Default)]
+pub struct Outputs {
+    recorders: Vec<Box<dyn Recorder>>,
+}
+
+impl Outputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, recorder: Box<dyn Recorder>) {
+        self.recorders.push(recorder);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recorders.is_empty()
+    }
+
+    /// Number of time steps from `step` until a recorder is due, if any.
+    pub fn steps_until_due(&self, step: usize) -> Option<usize> {
+        self.recorders
+            .iter()
+            .map(|r| r.interval() - step % r.interval())
+            .min()
+    }
+
+    /// Hands the current solution to every recorder whose interval divides
+    /// the current iteration.
+    pub fn record<S: Solver>(&mut self, solver: &S, ctx: S::Context<'_>) -> io::Result<()> {
+        let step = solver.iteration();
+        let mut due = self
+            .recorders
+            .iter_mut()
+            .filter(|r| step.is_multiple_of(r.interval()))
+            .peekable();
+        if due.peek().is_none() {
+            return Ok(());
+        }
+        let field = solver.read_field(ctx)?;
+        let time = solver.time();
+        for recorder in due {
+            if let Err(e) = recorder.record(step, time, &field) {
+                // a recorder that cannot go on still closes what it recorded
+                recorder.finish()?;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        for recorder in self.recorders.iter_mut() {
+            recorder.finish()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,566 @@
+//! Readers and writers for NumPy `.npy` arrays, and writers for `.npz` bundles.
+//!
+//! Fields are written as little-endian `f32` with the version 1.0 header, so
+//! `numpy.load` reads them back with shape `[ny, nx]`. `.npz` files are plain
+//! (stored, uncompressed) zip archives of `.npy` entries, without the ZIP64
+//! extensions, so they hold at most 65535 arrays and 4 GiB.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::field::Field;
+
+use super::Recorder;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Number of arrays an `.npz` archive holds at most, without ZIP64.
+const MAX_NPZ_ENTRIES: usize = u16::MAX as usize;
+
+/// Type of the values of the arrays written by [`write_npy`].
+pub trait Element: Copy {
+    /// NumPy type string of the values, in little-endian byte order.
+    const DESCR: &'static str;
+
+    fn write_le<W: Write>(self, w: &mut W) -> io::Result<()>;
+}
+
+impl Element for f32 {
+    const DESCR: &'static str = "<f4";
+
+    fn write_le<W: Write>(self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+impl Element for i64 {
+    const DESCR: &'static str = "<i8";
+
+    fn write_le<W: Write>(self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+/// Serializes `data` as a `.npy` array with the given `shape`.
+pub fn write_npy<W: Write, T: Element>(w: &mut W, shape: &[usize], data: &[T]) -> io::Result<()> {
+    assert_eq!(
+        shape.iter().product::<usize>(),
+        data.len(),
+        "shape does not match the number of values"
+    );
+    let shape = match shape {
+        [len] => format!("({len},)"),
+        _ => {
+            let dims: Vec<String> = shape.iter().map(|d| d.to_string()).collect();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {shape}, }}",
+        T::DESCR
+    );
+    // magic + version + header length + header + '\n' must be a multiple of 64 bytes
+    let unpadded = NPY_MAGIC.len() + 2 + 2 + header.len() + 1;
+    header.extend(std::iter::repeat_n(' ', (64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    w.write_all(NPY_MAGIC)?;
+    w.write_all(&[1, 0])?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    for value in data {
+        value.write_le(w)?;
+    }
+    Ok(())
+}
+
+/// Reads a `.npy` array of little-endian `f4` or `f8` values, returning its
+/// shape and its values in C order.
+pub fn read_npy<R: Read>(r: &mut R) -> io::Result<(Vec<usize>, Vec<f32>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut preamble = [0u8; 8];
+    r.read_exact(&mut preamble)?;
+    if &preamble[..6] != NPY_MAGIC {
+        return Err(invalid("not a .npy file"));
+    }
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            r.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            r.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        _ => return Err(invalid("unsupported .npy version")),
+    };
+    let mut header = Vec::new();
+    r.take(header_len as u64).read_to_end(&mut header)?;
+    if header.len() != header_len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated .npy header",
+        ));
+    }
+    let header = String::from_utf8_lossy(&header);
+
+    let value_of = |key: &str| {
+        let start = header.find(&format!("'{key}':"))? + key.len() + 3;
+        Some(header[start..].trim_start())
+    };
+    let descr = value_of("descr")
+        .and_then(|v| v.strip_prefix('\''))
+        .and_then(|v| v.split('\'').next())
+        .ok_or_else(|| invalid("missing 'descr' in .npy header"))?;
+    let fortran_order = value_of("fortran_order")
+        .ok_or_else(|| invalid("missing 'fortran_order' in .npy header"))?
+        .starts_with("True");
+    let shape = value_of("shape")
+        .and_then(|v| v.strip_prefix('('))
+        .and_then(|v| v.split(')').next())
+        .ok_or_else(|| invalid("missing 'shape' in .npy header"))?;
+    let shape = shape
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| {
+            d.parse()
+                .map_err(|_| invalid("invalid shape in .npy header"))
+        })
+        .collect::<io::Result<Vec<usize>>>()?;
+
+    let size = match descr {
+        "<f4" => 4,
+        "<f8" => 8,
+        other => return Err(invalid(&format!("unsupported .npy dtype {other}"))),
+    };
+    let too_large = || invalid("array too large in .npy header");
+    let len = shape
+        .iter()
+        .try_fold(1usize, |len, &d| len.checked_mul(d))
+        .ok_or_else(too_large)?;
+    let bytes_len = len.checked_mul(size).ok_or_else(too_large)?;
+    // the shape comes from the file, so the buffer only grows with the data
+    // actually read rather than being allocated up front
+    let mut bytes = Vec::new();
+    r.take(bytes_len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != bytes_len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated .npy data",
+        ));
+    }
+    let mut data: Vec<f32> = match size {
+        4 => bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+        _ => bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+            .collect(),
+    };
+    if fortran_order && shape.len() == 2 {
+        let (rows, cols) = (shape[0], shape[1]);
+        let column_major = data;
+        data = (0..len)
+            .map(|i| column_major[(i % cols) * rows + i / cols])
+            .collect();
+    } else if fortran_order && shape.len() > 2 {
+        return Err(invalid(
+            "Fortran ordered .npy arrays must have at most 2 dimensions",
+        ));
+    }
+    Ok((shape, data))
+}
+
+/// Saves a field to `path` as a `.npy` array of shape `[height, width]`.
+pub fn save_npy(path: impl AsRef<Path>, field: &Field) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_npy(&mut w, &[field.height, field.width], &field.data)?;
+    w.flush()
+}
+
+/// Incremental writer for `.npz` archives.
+///
+/// Every array is appended as soon as it is added, so memory use does not grow
+/// with the number of snapshots. `finish` must be called to write the zip
+/// central directory, otherwise the archive is unreadable.
+pub struct NpzWriter<W: Write> {
+    w: W,
+    offset: u64,
+    entries: Vec<NpzEntry>,
+}
+
+struct NpzEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+impl<W: Write> NpzWriter<W> {
+    pub fn new(w: W) -> Self {
+        Self {
+            w,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Appends an array named `name`, which `numpy.load` exposes without the
+    /// `.npy` extension.
+    pub fn add_array<T: Element>(
+        &mut self,
+        name: &str,
+        shape: &[usize],
+        data: &[T],
+    ) -> io::Result<()> {
+        if self.entries.len() == MAX_NPZ_ENTRIES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                ".npz archives with more than 65535 arrays are not supported",
+            ));
+        }
+        let mut npy = Vec::new();
+        write_npy(&mut npy, shape, data)?;
+        let name = format!("{name}.npy");
+        let entry = NpzEntry {
+            crc: crc32(&npy),
+            size: zip_u32(npy.len() as u64)?,
+            offset: zip_u32(self.offset)?,
+            name,
+        };
+
+        let mut header = Vec::with_capacity(30 + entry.name.len());
+        header.extend(0x04034b50u32.to_le_bytes()); // local file header signature
+        header.extend(20u16.to_le_bytes()); // version needed to extract
+        header.extend(0u16.to_le_bytes()); // flags
+        header.extend(0u16.to_le_bytes()); // compression: stored
+        header.extend(0u16.to_le_bytes()); // modification time
+        header.extend(ZIP_DATE.to_le_bytes()); // modification date
+        header.extend(entry.crc.to_le_bytes());
+        header.extend(entry.size.to_le_bytes()); // compressed size
+        header.extend(entry.size.to_le_bytes()); // uncompressed size
+        header.extend((entry.name.len() as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes()); // extra field length
+        header.extend(entry.name.as_bytes());
+
+        self.w.write_all(&header)?;
+        self.w.write_all(&npy)?;
+        self.offset += (header.len() + npy.len()) as u64;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut directory = Vec::new();
+        for entry in self.entries.iter() {
+            directory.extend(0x02014b50u32.to_le_bytes()); // central file header signature
+            directory.extend(20u16.to_le_bytes()); // version made by
+            directory.extend(20u16.to_le_bytes()); // version needed to extract
+            directory.extend(0u16.to_le_bytes()); // flags
+            directory.extend(0u16.to_le_bytes()); // compression: stored
+            directory.extend(0u16.to_le_bytes()); // modification time
+            directory.extend(ZIP_DATE.to_le_bytes()); // modification date
+            directory.extend(entry.crc.to_le_bytes());
+            directory.extend(entry.size.to_le_bytes()); // compressed size
+            directory.extend(entry.size.to_le_bytes()); // uncompressed size
+            directory.extend((entry.name.len() as u16).to_le_bytes());
+            directory.extend(0u16.to_le_bytes()); // extra field length
+            directory.extend(0u16.to_le_bytes()); // comment length
+            directory.extend(0u16.to_le_bytes()); // disk number
+            directory.extend(0u16.to_le_bytes()); // internal attributes
+            directory.extend(0u32.to_le_bytes()); // external attributes
+            directory.extend(entry.offset.to_le_bytes());
+            directory.extend(entry.name.as_bytes());
+        }
+        // `add_array` keeps the number of entries within a u16
+        let num_entries = self.entries.len() as u16;
+        let mut end = Vec::with_capacity(22);
+        end.extend(0x06054b50u32.to_le_bytes()); // end of central directory signature
+        end.extend(0u16.to_le_bytes()); // number of this disk
+        end.extend(0u16.to_le_bytes()); // disk where the central directory starts
+        end.extend(num_entries.to_le_bytes()); // entries on this disk
+        end.extend(num_entries.to_le_bytes()); // total entries
+        end.extend(zip_u32(directory.len() as u64)?.to_le_bytes());
+        end.extend(zip_u32(self.offset)?.to_le_bytes());
+        end.extend(0u16.to_le_bytes()); // comment length
+
+        self.w.write_all(&directory)?;
+        self.w.write_all(&end)?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// 1980-01-01, the earliest date representable in a zip file.
+const ZIP_DATE: u16 = (1 << 5) | 1;
+
+fn zip_u32(value: u64) -> io::Result<u32> {
+    u32::try_from(value).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            ".npz archives larger than 4 GiB are not supported",
+        )
+    })
+}
+
+/// CRC-32 (IEEE 802.3), as required by the zip format.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Where an `NpyRecorder` puts its snapshots.
+enum Destination {
+    /// One `<prefix>_<step>.npy` file per snapshot inside a directory.
+    Files { directory: PathBuf, prefix: String },
+    /// A single `.npz` archive with one `u_<step>` array per snapshot, plus
+    /// `steps` and `times` arrays written when recording finishes.
+    Npz(Option<NpzWriter<BufWriter<File>>>),
+}
+
+/// Records the temperature field to NumPy files every `interval` steps.
+pub struct NpyRecorder {
+    destination: Destination,
+    interval: usize,
+    steps: Vec<i64>,
+    times: Vec<f32>,
+}
+
+impl NpyRecorder {
+    /// Writes numbered `<prefix>_<step>.npy` files into `directory`, creating it if needed.
+    pub fn files(directory: impl Into<PathBuf>, prefix: &str, interval: usize) -> io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self::with_destination(
+            Destination::Files {
+                directory,
+                prefix: prefix.to_string(),
+            },
+            interval,
+        ))
+    }
+
+    /// Bundles all snapshots into a single `.npz` archive at `path`, which
+    /// holds at most 65533 of them to leave room for `steps` and `times`.
+    pub fn npz(path: impl AsRef<Path>, interval: usize) -> io::Result<Self> {
+        let w = BufWriter::new(File::create(path)?);
+        Ok(Self::with_destination(
+            Destination::Npz(Some(NpzWriter::new(w))),
+            interval,
+        ))
+    }
+
+    fn with_destination(destination: Destination, interval: usize) -> Self {
+        assert!(interval > 0, "recording interval must be at least one step");
+        Self {
+            destination,
+            interval,
+            steps: Vec::new(),
+            times: Vec::new(),
+        }
+    }
+}
+
+impl Recorder for NpyRecorder {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn record(&mut self, step: usize, time: f32, field: &Field) -> io::Result<()> {
+        let shape = [field.height, field.width];
+        match &mut self.destination {
+            Destination::Files { directory, prefix } => {
+                save_npy(directory.join(format!("{prefix}_{step:06}.npy")), field)?;
+            }
+            Destination::Npz(Some(npz)) => {
+                // keep room for the `steps` and `times` arrays added at the end
+                if npz.entries.len() + 2 >= MAX_NPZ_ENTRIES {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            ".npz archives hold at most {} snapshots",
+                            MAX_NPZ_ENTRIES - 2
+                        ),
+                    ));
+                }
+                npz.add_array(&format!("u_{step:06}"), &shape, &field.data)?;
+            }
+            Destination::Npz(None) => panic!("recording after the .npz archive was finished"),
+        }
+        self.steps.push(step as i64);
+        self.times.push(time);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Destination::Npz(npz) = &mut self.destination {
+            if let Some(mut npz) = npz.take() {
+                npz.add_array("steps", &[self.steps.len()], &self.steps)?;
+                npz.add_array("times", &[self.times.len()], &self.times)?;
+                npz.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npy_header_is_aligned() {
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &[2, 3], &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(bytes.len(), 10 + header_len + 6 * 4);
+        assert_eq!(&bytes[bytes.len() - 4..], &5.0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let data = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &[2, 3], &data).unwrap();
+        let (shape, read) = read_npy(&mut bytes.as_slice()).unwrap();
+        assert_eq!(shape, [2, 3]);
+        assert_eq!(read, data);
+
+        // the same array, stored as f8 in Fortran order
+        let header = "{'descr': '<f8', 'fortran_order': True, 'shape': (2, 3), }\n";
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        for value in [0.0f64, 3.0, 1.0, 4.0, 2.0, 5.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        let (shape, read) = read_npy(&mut bytes.as_slice()).unwrap();
+        assert_eq!(shape, [2, 3]);
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn npy_rejects_invalid_shapes() {
+        let npy = |shape: &str| {
+            let header =
+                format!("{{'descr': '<f8', 'fortran_order': False, 'shape': {shape}, }}\n");
+            let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+            bytes.extend((header.len() as u16).to_le_bytes());
+            bytes.extend(header.as_bytes());
+            bytes.extend(1.0f64.to_le_bytes());
+            read_npy(&mut bytes.as_slice())
+        };
+        assert_eq!(npy("(1,)").unwrap().1, [1.0]);
+        // a huge shape must not allocate before the data runs out
+        let huge = npy("(1000000000, 1000000000)").unwrap_err();
+        assert_eq!(huge.kind(), io::ErrorKind::UnexpectedEof);
+        // nor overflow the number of values or bytes
+        let overflow = npy(&format!("({}, 2)", usize::MAX)).unwrap_err();
+        assert_eq!(overflow.kind(), io::ErrorKind::InvalidData);
+        let overflow = npy(&format!("({},)", usize::MAX / 2)).unwrap_err();
+        assert_eq!(overflow.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn npz_layout() {
+        let mut npz = NpzWriter::new(Vec::new());
+        npz.add_array("a", &[2], &[1.0, 2.0]).unwrap();
+        npz.add_array("b", &[1], &[3i64]).unwrap();
+        let bytes = npz.finish().unwrap();
+        // the end of central directory record is the last 22 bytes
+        let end = &bytes[bytes.len() - 22..];
+        assert_eq!(&end[..4], &0x06054b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let directory_offset = u32::from_le_bytes(end[16..20].try_into().unwrap()) as usize;
+        assert_eq!(
+            &bytes[directory_offset..directory_offset + 4],
+            &0x02014b50u32.to_le_bytes()
+        );
+        assert_eq!(&bytes[30..35], b"a.npy");
+        // "b" holds 64-bit integers
+        let b = &bytes[directory_offset - 136..directory_offset];
+        assert_eq!(&b[..6], NPY_MAGIC);
+        assert!(std::str::from_utf8(&b[10..128])
+            .unwrap()
+            .starts_with("{'descr': '<i8'"));
+        assert_eq!(&b[128..], &3i64.to_le_bytes());
+    }
+
+    #[test]
+    fn npz_entry_limit() {
+        let mut npz = NpzWriter::new(io::sink());
+        for i in 0..u16::MAX {
+            npz.add_array::<f32>(&i.to_string(), &[0], &[]).unwrap();
+        }
+        let error = npz.add_array::<f32>("one too many", &[0], &[]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        npz.finish().unwrap();
+    }
+
+    #[test]
+    fn npz_recorder_keeps_room_for_steps_and_times() {
+        let path = std::env::temp_dir().join(format!("snapshots-{}.npz", std::process::id()));
+        let mut recorder = NpyRecorder::npz(&path, 1).unwrap();
+        let field = Field::new(1, 1, vec![0.5]);
+        let snapshots = MAX_NPZ_ENTRIES - 2;
+        for step in 0..snapshots {
+            recorder.record(step, step as f32, &field).unwrap();
+        }
+        let error = recorder.record(snapshots, 0.0, &field).unwrap_err();
+        recorder.finish().unwrap();
+        let bytes = std::fs::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        let bytes = bytes.unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // walk the central directory to the last entry, the times
+        let end = &bytes[bytes.len() - 22..];
+        assert_eq!(
+            u16::from_le_bytes([end[10], end[11]]) as usize,
+            MAX_NPZ_ENTRIES
+        );
+        let mut entry = u32::from_le_bytes(end[16..20].try_into().unwrap()) as usize;
+        let mut names = Vec::new();
+        let mut offset = 0;
+        for _ in 0..MAX_NPZ_ENTRIES {
+            let header = &bytes[entry..entry + 46];
+            assert_eq!(&header[..4], &0x02014b50u32.to_le_bytes());
+            let name_len = u16::from_le_bytes([header[28], header[29]]) as usize;
+            offset = u32::from_le_bytes(header[42..46].try_into().unwrap()) as usize;
+            names.push(String::from_utf8(
+                bytes[entry + 46..entry + 46 + name_len].to_vec(),
+            ));
+            entry += 46 + name_len;
+        }
+        assert_eq!(names[0].as_deref(), Ok("u_000000.npy"));
+        assert_eq!(names[snapshots].as_deref(), Ok("steps.npy"));
+        assert_eq!(names[snapshots + 1].as_deref(), Ok("times.npy"));
+        let (shape, times) = read_npy(&mut &bytes[offset + 30 + "times.npy".len()..]).unwrap();
+        assert_eq!(shape, [snapshots]);
+        assert_eq!(times[snapshots - 1], (snapshots - 1) as f32);
+    }
+}
@@ -0,0 +1,233 @@
+//! Writers for VTK image data, readable by ParaView and VisIt.
+//!
+//! The field is stored as point data on a `nx * ny * 1` image whose origin and
+//! spacing match the grid used by the discretization.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::field::Field;
+
+use super::Recorder;
+
+/// Placement of the grid points in physical space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub origin: [f64; 2],
+    pub spacing: [f64; 2],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Legacy `.vtk` structured points, binary.
+    Legacy,
+    /// XML `.vti` image data, with the values appended as raw binary.
+    Xml,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Legacy => "vtk",
+            Format::Xml => "vti",
+        }
+    }
+}
+
+/// Writes a field as a legacy VTK `STRUCTURED_POINTS` dataset.
+pub fn write_legacy<W: Write>(
+    w: &mut W,
+    field: &Field,
+    geometry: &Geometry,
+    title: &str,
+) -> io::Result<()> {
+    let Geometry { origin, spacing } = geometry;
+    writeln!(w, "# vtk DataFile Version 3.0")?;
+    writeln!(w, "{}", title.replace('\n', " "))?;
+    writeln!(w, "BINARY")?;
+    writeln!(w, "DATASET STRUCTURED_POINTS")?;
+    writeln!(w, "DIMENSIONS {} {} 1", field.width, field.height)?;
+    writeln!(w, "ORIGIN {} {} 0", origin[0], origin[1])?;
+    writeln!(w, "SPACING {} {} 1", spacing[0], spacing[1])?;
+    writeln!(w, "POINT_DATA {}", field.data.len())?;
+    writeln!(w, "SCALARS temperature float 1")?;
+    writeln!(w, "LOOKUP_TABLE default")?;
+    // legacy binary files are big-endian
+    for value in field.data.iter() {
+        w.write_all(&value.to_be_bytes())?;
+    }
+    writeln!(w)
+}
+
+/// Writes a field as an XML VTK `ImageData` file, tagged with its simulation time.
+pub fn write_xml<W: Write>(
+    w: &mut W,
+    field: &Field,
+    geometry: &Geometry,
+    time: f32,
+) -> io::Result<()> {
+    let Geometry { origin, spacing } = geometry;
+    let extent = format!("0 {} 0 {} 0 0", field.width - 1, field.height - 1);
+    writeln!(w, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        w,
+        r#"<VTKFile type="ImageData" version="1.0" byte_order="LittleEndian" header_type="UInt32">"#
+    )?;
+    writeln!(
+        w,
+        r#"  <ImageData WholeExtent="{extent}" Origin="{} {} 0" Spacing="{} {} 1">"#,
+        origin[0], origin[1], spacing[0], spacing[1]
+    )?;
+    writeln!(w, r#"    <FieldData>"#)?;
+    writeln!(
+        w,
+        r#"      <DataArray type="Float32" Name="TimeValue" NumberOfTuples="1" format="ascii">{time}</DataArray>"#
+    )?;
+    writeln!(w, r#"    </FieldData>"#)?;
+    writeln!(w, r#"    <Piece Extent="{extent}">"#)?;
+    writeln!(w, r#"      <PointData Scalars="temperature">"#)?;
+    writeln!(
+        w,
+        r#"        <DataArray type="Float32" Name="temperature" format="appended" offset="0"/>"#
+    )?;
+    writeln!(w, r#"      </PointData>"#)?;
+    writeln!(w, r#"    </Piece>"#)?;
+    writeln!(w, r#"  </ImageData>"#)?;
+    write!(w, r#"  <AppendedData encoding="raw">"#)?;
+    w.write_all(b"\n   _")?;
+    let num_bytes = std::mem::size_of_val(field.data.as_slice());
+    let num_bytes = u32::try_from(num_bytes)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "field too large for VTK"))?;
+    w.write_all(&num_bytes.to_le_bytes())?;
+    for value in field.data.iter() {
+        w.write_all(&value.to_le_bytes())?;
+    }
+    writeln!(w)?;
+    writeln!(w, r#"  </AppendedData>"#)?;
+    writeln!(w, r#"</VTKFile>"#)
+}
+
+/// Writes a ParaView `.pvd` collection, listing `(time, file name)` pairs.
+pub fn write_pvd<W: Write>(w: &mut W, datasets: &[(f32, String)]) -> io::Result<()> {
+    writeln!(w, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        w,
+        r#"<VTKFile type="Collection" version="0.1" byte_order="LittleEndian">"#
+    )?;
+    writeln!(w, r#"  <Collection>"#)?;
+    for (time, file) in datasets {
+        writeln!(
+            w,
+            r#"    <DataSet timestep="{time}" group="" part="0" file="{file}"/>"#
+        )?;
+    }
+    writeln!(w, r#"  </Collection>"#)?;
+    writeln!(w, r#"</VTKFile>"#)
+}
+
+/// Records the field as a numbered sequence of VTK files, along with a
+/// `<prefix>.pvd` collection so ParaView loads them as a time series.
+pub struct VtkRecorder {
+    directory: PathBuf,
+    prefix: String,
+    format: Format,
+    geometry: Geometry,
+    interval: usize,
+    datasets: Vec<(f32, String)>,
+}
+
+impl VtkRecorder {
+    pub fn new(
+        directory: impl Into<PathBuf>,
+        prefix: &str,
+        format: Format,
+        geometry: Geometry,
+        interval: usize,
+    ) -> io::Result<Self> {
+        assert!(interval > 0, "recording interval must be at least one step");
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            prefix: prefix.to_string(),
+            format,
+            geometry,
+            interval,
+            datasets: Vec::new(),
+        })
+    }
+
+    fn pvd_path(&self) -> PathBuf {
+        self.directory.join(format!("{}.pvd", self.prefix))
+    }
+}
+
+fn create(path: impl AsRef<Path>) -> io::Result<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+impl Recorder for VtkRecorder {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn record(&mut self, step: usize, time: f32, field: &Field) -> io::Result<()> {
+        let name = format!("{}_{step:06}.{}", self.prefix, self.format.extension());
+        let mut w = create(self.directory.join(&name))?;
+        match self.format {
+            Format::Legacy => {
+                let title = format!("heat-wgpu temperature, step {step}, t = {time}");
+                write_legacy(&mut w, field, &self.geometry, &title)?
+            }
+            Format::Xml => write_xml(&mut w, field, &self.geometry, time)?,
+        }
+        w.flush()?;
+        self.datasets.push((time, name));
+        // rewritten every time, so that interrupted runs can still be opened
+        let mut pvd = create(self.pvd_path())?;
+        write_pvd(&mut pvd, &self.datasets)?;
+        pvd.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GEOMETRY: Geometry = Geometry {
+        origin: [0.0, 0.0],
+        spacing: [0.5, 0.25],
+    };
+
+    #[test]
+    fn legacy_layout() {
+        let field = Field::new(2, 3, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        let mut bytes = Vec::new();
+        write_legacy(&mut bytes, &field, &GEOMETRY, "test").unwrap();
+        let header = "# vtk DataFile Version 3.0\ntest\nBINARY\nDATASET STRUCTURED_POINTS\n\
+                      DIMENSIONS 2 3 1\nORIGIN 0 0 0\nSPACING 0.5 0.25 1\nPOINT_DATA 6\n\
+                      SCALARS temperature float 1\nLOOKUP_TABLE default\n";
+        assert_eq!(&bytes[..header.len()], header.as_bytes());
+        assert_eq!(bytes.len(), header.len() + 6 * 4 + 1);
+        assert_eq!(
+            &bytes[header.len() + 4..header.len() + 8],
+            &1.0f32.to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn xml_appended_data() {
+        let field = Field::new(2, 1, vec![1.5, -2.0]);
+        let mut bytes = Vec::new();
+        write_xml(&mut bytes, &field, &GEOMETRY, 0.25).unwrap();
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.contains(r#"WholeExtent="0 1 0 0 0 0" Origin="0 0 0" Spacing="0.5 0.25 1""#));
+        assert!(text.contains(r#"format="ascii">0.25</DataArray>"#));
+        let start = bytes.windows(5).position(|w| w == b"\n   _").unwrap() + 5;
+        assert_eq!(&bytes[start..start + 4], &8u32.to_le_bytes());
+        assert_eq!(&bytes[start + 4..start + 8], &1.5f32.to_le_bytes());
+        assert_eq!(&bytes[start + 8..start + 12], &(-2.0f32).to_le_bytes());
+    }
+}
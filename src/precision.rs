@@ -0,0 +1,210 @@
+//! Floating point format of the GPU solver vectors and matrices.
+
+/// Format of the values in the vectors and matrices of the GPU solver.
+///
+/// The shaders of the solver kernels are written for a `real` type and the
+/// `real_*` functions of a prelude, which [`Precision::shader`] prepends to
+/// them. The solution handed to the rest of the program, for rendering,
+/// probes, diagnostics and output files, is always single precision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// `f32`.
+    #[default]
+    Single,
+    /// Native `f64`, which needs [`wgpu::Features::SHADER_F64`].
+    Double,
+    /// Emulated double precision: double-float numbers, unevaluated sums of
+    /// two `f32`, with about 48 bits of mantissa and the range of `f32`. Runs
+    /// on any adapter, at several times the cost of single precision.
+    DoubleFloat,
+}
+
+impl Precision {
+    /// Size of a value in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Precision::Single => 4,
+            Precision::Double | Precision::DoubleFloat => 8,
+        }
+    }
+
+    /// Device features needed by the shaders.
+    pub fn features(self) -> wgpu::Features {
+        match self {
+            Precision::Double => wgpu::Features::SHADER_F64,
+            Precision::Single | Precision::DoubleFloat => wgpu::Features::empty(),
+        }
+    }
+
+    /// This precision if an adapter with `features` supports it, or else the
+    /// emulated [`Precision::DoubleFloat`] instead of native `f64`.
+    pub fn supported(self, features: wgpu::Features) -> Self {
+        if features.contains(self.features()) {
+            self
+        } else {
+            Precision::DoubleFloat
+        }
+    }
+
+    /// Prepends the prelude defining `real` and its arithmetic to `source`.
+    pub(crate) fn shader(self, source: &str) -> String {
+        match self {
+            Precision::Single => format!("{}\n{source}", include_str!("shaders/real_f32.wgsl")),
+            Precision::Double => format!("{}\n{source}", include_str!("shaders/real_f64.wgsl")),
+            Precision::DoubleFloat => format!(
+                "{}\n{}\n{source}",
+                include_str!("shaders/double_float.wgsl"),
+                include_str!("shaders/real_double_float.wgsl")
+            ),
+        }
+    }
+
+    /// Converts `values` to the contents of a GPU buffer.
+    pub fn encode(self, values: impl IntoIterator<Item = f64>) -> Vec<u8> {
+        let values = values.into_iter();
+        match self {
+            Precision::Single => values.flat_map(|v| (v as f32).to_le_bytes()).collect(),
+            Precision::Double => values.flat_map(|v| v.to_le_bytes()).collect(),
+            Precision::DoubleFloat => values
+                .flat_map(|v| {
+                    let hi = v as f32;
+                    let lo = (v - hi as f64) as f32;
+                    [hi.to_le_bytes(), lo.to_le_bytes()]
+                })
+                .flatten()
+                .collect(),
+        }
+    }
+
+    /// Converts the contents of a GPU buffer back to values.
+    pub fn decode(self, bytes: &[u8]) -> Vec<f64> {
+        match self {
+            Precision::Single => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+                .collect(),
+            Precision::Double => bytes
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+            Precision::DoubleFloat => bytes
+                .chunks_exact(8)
+                .map(|b| {
+                    let hi = f32::from_le_bytes(b[..4].try_into().unwrap());
+                    let lo = f32::from_le_bytes(b[4..].try_into().unwrap());
+                    hi as f64 + lo as f64
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Precision; 3] = [Precision::Single, Precision::Double, Precision::DoubleFloat];
+
+    #[test]
+    fn encodes_values() {
+        let values = [0.0, 1.0, -2.5, std::f64::consts::PI, 1e-30];
+        for precision in ALL {
+            let bytes = precision.encode(values);
+            assert_eq!(bytes.len(), values.len() * precision.size());
+            let decoded = precision.decode(&bytes);
+            let tolerance = match precision {
+                Precision::Single => f32::EPSILON as f64,
+                Precision::Double => 0.0,
+                Precision::DoubleFloat => 1e-14,
+            };
+            for (a, b) in decoded.iter().zip(values) {
+                assert!(
+                    (a - b).abs() <= tolerance * b.abs(),
+                    "{precision:?}: {a} != {b}"
+                );
+            }
+        }
+        assert_eq!(
+            Precision::Single.encode([1.5]),
+            bytemuck::cast_slice::<f32, u8>(&[1.5])
+        );
+    }
+
+    #[test]
+    fn falls_back_to_double_float() {
+        assert_eq!(
+            Precision::Double.supported(wgpu::Features::empty()),
+            Precision::DoubleFloat
+        );
+        assert_eq!(
+            Precision::Double.supported(wgpu::Features::SHADER_F64),
+            Precision::Double
+        );
+        assert_eq!(
+            Precision::Single.supported(wgpu::Features::empty()),
+            Precision::Single
+        );
+    }
+
+    /// The adapters used for testing may not support `f64`, so the native
+    /// double precision shaders are at least validated here.
+    #[test]
+    fn shaders_validate() {
+        use crate::kernels::{reduce::ReduceOp, spmv_dots};
+
+        let mut shaders = vec![
+            include_str!("shaders/vec_mul.wgsl").to_string(),
+            include_str!("shaders/saxpy_update.wgsl").to_string(),
+            include_str!("shaders/convert.wgsl").to_string(),
+            include_str!("shaders/correct.wgsl").to_string(),
+            include_str!("shaders/cg_update.wgsl").to_string(),
+        ];
+        for parity in [0, 1] {
+            shaders.push(crate::kernels::cg_update::PipelinedUpdateKernel::source(
+                parity,
+            ));
+        }
+        for products_binding in [None, Some(5)] {
+            for spmv in [
+                include_str!("shaders/spmv.wgsl"),
+                include_str!("shaders/spmv_csr.wgsl"),
+                include_str!("shaders/spmv_ell.wgsl"),
+            ] {
+                shaders.push(spmv_dots::source(spmv, products_binding));
+            }
+        }
+        for nine_point in [false, true] {
+            let stencil = crate::kernels::stencil::StencilKernel::source(nine_point);
+            shaders.push(spmv_dots::source(&stencil, None));
+            shaders.push(spmv_dots::source(&stencil, Some(3)));
+        }
+        for update in crate::kernels::saxpy_update_div::Operation::ALL {
+            shaders.push(crate::kernels::saxpy_update_div::SAXPYUpdateDivKernel::source(update));
+        }
+        for op in [
+            ReduceOp::Sum,
+            ReduceOp::Min,
+            ReduceOp::Max,
+            ReduceOp::SumOfSquares,
+        ] {
+            for (first_pass, masked) in [(true, false), (true, true), (false, false)] {
+                shaders.push(crate::kernels::reduce::ReduceKernel::source(
+                    op, first_pass, masked, 0,
+                ));
+            }
+        }
+        for precision in ALL {
+            for source in shaders.iter() {
+                let source = precision.shader(source);
+                let module = naga::front::wgsl::parse_str(&source)
+                    .unwrap_or_else(|e| panic!("{precision:?}: {}", e.emit_to_string(&source)));
+                naga::valid::Validator::new(
+                    naga::valid::ValidationFlags::all(),
+                    naga::valid::Capabilities::FLOAT64,
+                )
+                .validate(&module)
+                .unwrap_or_else(|e| panic!("{precision:?}: {e:?}"));
+            }
+        }
+    }
+}
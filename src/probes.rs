@@ -0,0 +1,324 @@
+//! Sampling the temperature at fixed points and along lines, like thermocouples
+//! placed on the plate.
+//!
+//! Positions are physical coordinates in the unit square, the same as for the
+//! analytic initial conditions. The field is interpolated bilinearly between
+//! the four surrounding grid points, using the zero boundary value next to the
+//! edges. The samples are computed on the GPU, so only the sampled values are
+//! copied back every step.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    field::Field,
+    heat_equation::{read_buffer, HeatEquation},
+    kernels::{kernel::Kernel, probe::ProbeKernel},
+};
+
+/// A single sampling point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub name: String,
+    pub position: [f64; 2],
+}
+
+/// `samples` equally spaced points from `start` to `end`, both included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineProfile {
+    pub name: String,
+    pub start: [f64; 2],
+    pub end: [f64; 2],
+    pub samples: usize,
+}
+
+/// Splits `name=v0,v1,...` into the name and `count` numbers.
+fn parse_named(spec: &str, count: usize, what: &str) -> Result<(String, Vec<f64>), String> {
+    let (name, values) = spec
+        .split_once('=')
+        .ok_or(format!("expected NAME=... for the {what}, found {spec:?}"))?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(',') {
+        return Err(format!("invalid {what} name {name:?}"));
+    }
+    let values = values
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid value {v:?} for the {what} {name}"))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if values.len() != count {
+        return Err(format!(
+            "expected {count} values for the {what} {name}, found {}",
+            values.len()
+        ));
+    }
+    Ok((name.to_string(), values))
+}
+
+fn check_position(name: &str, position: [f64; 2]) -> Result<[f64; 2], String> {
+    if position.iter().all(|c| (0.0..=1.0).contains(c)) {
+        Ok(position)
+    } else {
+        Err(format!("{name} lies outside the unit square"))
+    }
+}
+
+/// Parses `NAME=X,Y`.
+impl FromStr for Probe {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (name, v) = parse_named(spec, 2, "probe")?;
+        let position = check_position(&name, [v[0], v[1]])?;
+        Ok(Self { name, position })
+    }
+}
+
+/// Parses `NAME=X0,Y0,X1,Y1,SAMPLES`.
+impl FromStr for LineProfile {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (name, v) = parse_named(spec, 5, "line")?;
+        let start = check_position(&name, [v[0], v[1]])?;
+        let end = check_position(&name, [v[2], v[3]])?;
+        if v[4].fract() != 0.0 || v[4] < 2.0 {
+            return Err(format!("{name} needs a whole number of at least 2 samples"));
+        }
+        Ok(Self {
+            name,
+            start,
+            end,
+            samples: v[4] as usize,
+        })
+    }
+}
+
+/// Every probe and line profile of a run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Probes {
+    pub points: Vec<Probe>,
+    pub lines: Vec<LineProfile>,
+}
+
+impl Probes {
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty() && self.lines.is_empty()
+    }
+
+    /// Positions of all samples: the probes, then the points of each line.
+    pub fn positions(&self) -> Vec<[f64; 2]> {
+        let mut positions: Vec<_> = self.points.iter().map(|p| p.position).collect();
+        for line in self.lines.iter() {
+            for s in 0..line.samples {
+                let t = s as f64 / (line.samples - 1) as f64;
+                positions.push([
+                    line.start[0] + t * (line.end[0] - line.start[0]),
+                    line.start[1] + t * (line.end[1] - line.start[1]),
+                ]);
+            }
+        }
+        positions
+    }
+
+    /// Names of the samples in the order of [`Probes::positions`]: the name of
+    /// each probe, and `NAME[i]` for the points of a line.
+    pub fn columns(&self) -> Vec<String> {
+        let mut columns: Vec<_> = self.points.iter().map(|p| p.name.clone()).collect();
+        for line in self.lines.iter() {
+            columns.extend((0..line.samples).map(|s| format!("{}[{s}]", line.name)));
+        }
+        columns
+    }
+
+    /// Samples a field on the host, in the order of [`Probes::positions`].
+    pub fn sample_field(&self, field: &Field) -> Vec<f32> {
+        assert_eq!(field.width, field.height, "the grid must be square");
+        let n = field.width;
+        let value = |i: i64, j: i64| {
+            if (0..n as i64).contains(&i) && (0..n as i64).contains(&j) {
+                field.get(i as usize, j as usize) as f64
+            } else {
+                0.0
+            }
+        };
+        self.positions()
+            .iter()
+            .map(|&position| {
+                let [x, y] = grid_units(position, n);
+                let (i, j) = (x.floor() as i64, y.floor() as i64);
+                let (tx, ty) = (x - x.floor(), y - y.floor());
+                let top = value(i, j) * (1.0 - tx) + value(i + 1, j) * tx;
+                let bottom = value(i, j + 1) * (1.0 - tx) + value(i + 1, j + 1) * tx;
+                (top * (1.0 - ty) + bottom * ty) as f32
+            })
+            .collect()
+    }
+}
+
+/// Converts a physical position to grid units, where grid point `(i, j)` sits
+/// at `(i, j)`.
+fn grid_units(position: [f64; 2], n: usize) -> [f64; 2] {
+    let h = HeatEquation::grid_spacing(n);
+    position.map(|c| c / h - 1.0)
+}
+
+/// Samples the solution of a [`HeatEquation`] at a fixed set of probes.
+pub struct ProbeSampler {
+    kernels: [ProbeKernel; 2],
+    output: wgpu::Buffer,
+    _points: wgpu::Buffer,
+}
+
+impl ProbeSampler {
+    pub fn new(device: &wgpu::Device, heat_eqn: &HeatEquation, probes: &Probes) -> Self {
+        assert!(!probes.is_empty(), "there must be at least one probe");
+        let n = heat_eqn.n();
+        let points: Vec<f32> = probes
+            .positions()
+            .into_iter()
+            .flat_map(|position| grid_units(position, n).map(|c| c as f32))
+            .collect();
+        let points = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Probe positions"),
+            contents: bytemuck::cast_slice(&points),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Probe values"),
+            size: points.size() / 2,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let kernels = heat_eqn
+            .solution_buffers()
+            .map(|u| ProbeKernel::new(device, u, n, &points, &output));
+        Self {
+            kernels,
+            output,
+            _points: points,
+        }
+    }
+
+    /// Samples the current solution of `heat_eqn`, blocking until the values
+    /// are available.
+    pub fn sample(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        heat_eqn: &HeatEquation,
+    ) -> Result<Vec<f32>, wgpu::BufferAsyncError> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Probe Encoder"),
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Probe Compute Pass"),
+            timestamp_writes: None,
+        });
+        self.kernels[heat_eqn.iteration() % 2].add_to_pass(&mut compute_pass);
+        drop(compute_pass);
+        queue.submit(Some(encoder.finish()));
+        read_buffer(device, queue, &self.output)
+    }
+}
+
+/// Writes probe samples to a CSV file, one row per time step.
+pub struct ProbeLog<W: Write> {
+    writer: W,
+}
+
+impl ProbeLog<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, probes: &Probes) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), probes)
+    }
+}
+
+impl<W: Write> ProbeLog<W> {
+    /// Writes the header line, `step,time` followed by [`Probes::columns`].
+    pub fn new(mut writer: W, probes: &Probes) -> io::Result<Self> {
+        writeln!(writer, "step,time,{}", probes.columns().join(","))?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, step: usize, time: f64, values: &[f32]) -> io::Result<()> {
+        write!(self.writer, "{step},{time}")?;
+        for value in values {
+            write!(self.writer, ",{value}")?;
+        }
+        writeln!(self.writer)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probes() -> Probes {
+        Probes {
+            points: vec!["center=0.5,0.5".parse().unwrap()],
+            lines: vec!["edge=0,0.25,1,0.25,3".parse().unwrap()],
+        }
+    }
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(
+            "tc1 = 0.25, 0.75".parse(),
+            Ok(Probe {
+                name: "tc1".to_string(),
+                position: [0.25, 0.75],
+            })
+        );
+        assert!("0.25,0.75".parse::<Probe>().is_err());
+        assert!("tc=0.25".parse::<Probe>().is_err());
+        assert!("tc=1.5,0.5".parse::<Probe>().is_err());
+        assert!("l=0,0,1,1,1".parse::<LineProfile>().is_err());
+        assert!("l=0,0,1,1,2.5".parse::<LineProfile>().is_err());
+        assert_eq!(
+            probes().positions(),
+            [[0.5, 0.5], [0.0, 0.25], [0.5, 0.25], [1.0, 0.25]]
+        );
+        assert_eq!(
+            probes().columns(),
+            ["center", "edge[0]", "edge[1]", "edge[2]"]
+        );
+    }
+
+    #[test]
+    fn bilinear_interpolation() {
+        // n = 3, h = 1/4: the grid points sit at 0.25, 0.5 and 0.75
+        let field = Field::new(3, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]);
+        let probes = Probes {
+            points: vec![
+                "on_point=0.5,0.25".parse().unwrap(),
+                "between=0.375,0.375".parse().unwrap(),
+                "near_edge=0.125,0.5".parse().unwrap(),
+            ],
+            lines: vec!["row=0,0.75,1,0.75,3".parse().unwrap()],
+        };
+        assert_eq!(probes.sample_field(&field), [2.0, 3.0, 2.0, 0.0, 8.0, 0.0]);
+    }
+
+    #[test]
+    fn csv_rows() {
+        let mut log = ProbeLog::new(Vec::new(), &probes()).unwrap();
+        log.write(3, 0.5, &[1.0, 0.0, 2.5, 0.0]).unwrap();
+        let csv = String::from_utf8(log.writer).unwrap();
+        assert_eq!(
+            csv,
+            "step,time,center,edge[0],edge[1],edge[2]\n3,0.5,1,0,2.5,0\n"
+        );
+    }
+}
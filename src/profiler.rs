@@ -0,0 +1,497 @@
+//! Time spent in the kernels of the solver.
+//!
+//! With [`wgpu::Features::TIMESTAMP_QUERY`], every kernel of a profiled time
+//! step runs in a compute pass of its own, whose beginning and end the GPU
+//! timestamps. Adapters without the feature, such as the GL backend, fall
+//! back to timing whole steps on the host, waiting for the GPU after each
+//! one.
+//!
+//! The spans add up to a summary per kernel as they are recorded. Only a trace
+//! keeps the spans themselves, up to a limit, and exports them to the trace
+//! event format of `chrome://tracing` and Perfetto.
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{heat_equation::read_bytes_async, kernels::kernel::Kernel};
+
+/// Number of kernels timed between two steps; the following ones run untimed.
+const MAX_SPANS: usize = 2048;
+
+/// Number of spans kept for a trace; the following ones are only summarized.
+const MAX_TRACE_SPANS: usize = 1 << 20;
+
+/// Name of the spans of whole time steps.
+pub const STEP: &str = "step";
+
+/// Time span of a kernel, or of a whole step, in nanoseconds since the first
+/// profiled step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub name: &'static str,
+    /// Number of the time step, as in [`HeatEquation::iteration`] after it.
+    ///
+    /// [`HeatEquation::iteration`]: crate::heat_equation::HeatEquation::iteration
+    pub step: usize,
+    pub start: f64,
+    pub duration: f64,
+}
+
+/// Durations of the spans of a kernel, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KernelSummary {
+    pub name: &'static str,
+    pub count: usize,
+    pub total: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl KernelSummary {
+    pub fn mean(&self) -> f64 {
+        self.total / self.count as f64
+    }
+}
+
+enum Timer {
+    Timestamps {
+        query_set: wgpu::QuerySet,
+        resolve: wgpu::Buffer,
+        period: f64,         // nanoseconds per tick
+        origin: Option<u64>, // first timestamp
+    },
+    Host {
+        origin: Option<Instant>,  // start of the first step
+        started: Option<Instant>, // start of the current step
+    },
+}
+
+/// Records the spans of the kernels of every time step.
+pub struct Profiler {
+    timer: Timer,
+    pending: RefCell<Vec<&'static str>>, // kernels timed in the current step
+    dropped: Cell<usize>,                // kernels run untimed
+    summary: Vec<KernelSummary>,         // of the spans so far, in order of appearance
+    trace: Option<Vec<Span>>,            // spans so far, when tracing
+    untraced: usize,                     // spans past the capacity of the trace
+}
+
+impl Profiler {
+    /// Device features the profiler uses among the `available` ones.
+    pub fn features(available: wgpu::Features) -> wgpu::Features {
+        available & wgpu::Features::TIMESTAMP_QUERY
+    }
+
+    /// Times each kernel if the device was created with
+    /// [`wgpu::Features::TIMESTAMP_QUERY`], and whole steps otherwise. The
+    /// spans are kept for [`Profiler::write_trace`] only if `trace` is set.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, trace: bool) -> Self {
+        let timer = if device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            let count = 2 * MAX_SPANS;
+            Timer::Timestamps {
+                query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Profiler timestamps"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: count as u32,
+                }),
+                resolve: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler resolved timestamps"),
+                    size: (count * std::mem::size_of::<u64>()) as u64,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                period: queue.get_timestamp_period() as f64,
+                origin: None,
+            }
+        } else {
+            Timer::Host {
+                origin: None,
+                started: None,
+            }
+        };
+        Self::with_timer(timer, trace)
+    }
+
+    fn with_timer(timer: Timer, trace: bool) -> Self {
+        Self {
+            timer,
+            pending: RefCell::new(Vec::new()),
+            dropped: Cell::new(0),
+            summary: Vec::new(),
+            trace: trace.then(Vec::new),
+            untraced: 0,
+        }
+    }
+
+    /// Whether the profiler times each kernel, rather than whole steps.
+    pub fn per_kernel(&self) -> bool {
+        matches!(self.timer, Timer::Timestamps { .. })
+    }
+
+    /// Spans kept for the trace, kernels first and then their step, in the
+    /// order of the steps. Empty unless the profiler traces.
+    pub fn spans(&self) -> &[Span] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    /// Number of kernels which ran untimed, past the capacity of a step.
+    pub fn dropped(&self) -> usize {
+        self.dropped.get()
+    }
+
+    /// Number of spans left out of the trace, past its capacity. They still
+    /// count in the summary.
+    pub fn untraced(&self) -> usize {
+        self.untraced
+    }
+
+    /// Adds `span` to the summary, and to the trace if there is room.
+    fn record(&mut self, span: Span) {
+        match self.summary.iter_mut().find(|s| s.name == span.name) {
+            Some(s) => {
+                s.count += 1;
+                s.total += span.duration;
+                s.min = s.min.min(span.duration);
+                s.max = s.max.max(span.duration);
+            }
+            None => self.summary.push(KernelSummary {
+                name: span.name,
+                count: 1,
+                total: span.duration,
+                min: span.duration,
+                max: span.duration,
+            }),
+        }
+        match &mut self.trace {
+            Some(trace) if trace.len() < MAX_TRACE_SPANS => trace.push(span),
+            Some(_) => self.untraced += 1,
+            None => {}
+        }
+    }
+
+    /// Timestamp writes of a compute pass running the kernel `name`, if
+    /// there are queries left.
+    fn timestamp_writes(&self, name: &'static str) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let Timer::Timestamps { query_set, .. } = &self.timer else {
+            return None;
+        };
+        let mut pending = self.pending.borrow_mut();
+        if pending.len() == MAX_SPANS {
+            self.dropped.set(self.dropped.get() + 1);
+            return None;
+        }
+        let index = 2 * pending.len() as u32;
+        pending.push(name);
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    /// Starts timing a step, before its work is recorded.
+    pub(crate) fn begin_step(&mut self) {
+        if let Timer::Host { origin, started } = &mut self.timer {
+            let now = Instant::now();
+            origin.get_or_insert(now);
+            *started = Some(now);
+        }
+    }
+
+    /// Waits for the work of the step `step` and records its spans.
+    pub(crate) fn end_step(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, step: usize) {
+        let spans = match &mut self.timer {
+            Timer::Timestamps {
+                query_set,
+                resolve,
+                period,
+                origin,
+            } => {
+                let names = std::mem::take(self.pending.get_mut());
+                if names.is_empty() {
+                    return;
+                }
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Profiler Encoder"),
+                });
+                encoder.resolve_query_set(query_set, 0..2 * names.len() as u32, resolve, 0);
+                queue.submit(Some(encoder.finish()));
+                let bytes = read_bytes_async(device, queue, resolve);
+                device.poll(wgpu::Maintain::Wait);
+                let ticks: Vec<u64> = pollster::block_on(bytes)
+                    .expect("failed to map the timestamp buffer")
+                    .chunks_exact(8)
+                    .take(2 * names.len())
+                    .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect();
+                let origin = *origin.get_or_insert(ticks[0]);
+                let time = |tick: u64| tick.saturating_sub(origin) as f64 * *period;
+                let mut spans: Vec<Span> = names
+                    .into_iter()
+                    .zip(ticks.chunks_exact(2))
+                    .map(|(name, ticks)| {
+                        let start = time(ticks[0]);
+                        Span {
+                            name,
+                            step,
+                            start,
+                            duration: (time(ticks[1]) - start).max(0.0),
+                        }
+                    })
+                    .collect();
+                let start = spans.iter().map(|s| s.start).fold(f64::INFINITY, f64::min);
+                let end = spans
+                    .iter()
+                    .map(|s| s.start + s.duration)
+                    .fold(start, f64::max);
+                spans.push(Span {
+                    name: STEP,
+                    step,
+                    start,
+                    duration: end - start,
+                });
+                spans
+            }
+            Timer::Host { origin, started } => {
+                let (Some(origin), Some(started)) = (*origin, started.take()) else {
+                    return;
+                };
+                device.poll(wgpu::Maintain::Wait);
+                vec![Span {
+                    name: STEP,
+                    step,
+                    start: (started - origin).as_nanos() as f64,
+                    duration: started.elapsed().as_nanos() as f64,
+                }]
+            }
+        };
+        for span in spans {
+            self.record(span);
+        }
+    }
+
+    /// Durations of every kernel, steps first and then the kernels by
+    /// decreasing total time.
+    pub fn summary(&self) -> Vec<KernelSummary> {
+        let mut summary = self.summary.clone();
+        summary.sort_by(|a, b| {
+            (b.name == STEP)
+                .cmp(&(a.name == STEP))
+                .then(b.total.total_cmp(&a.total))
+        });
+        summary
+    }
+
+    /// Writes the spans kept for the trace as complete events of the trace
+    /// event format, steps and kernels on separate tracks.
+    pub fn write_trace(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[")?;
+        let spans = self.spans();
+        for (i, span) in spans.iter().enumerate() {
+            let separator = if i + 1 < spans.len() { "," } else { "" };
+            let (category, track) = if span.name == STEP {
+                ("step", 0)
+            } else {
+                ("kernel", 1)
+            };
+            writeln!(
+                writer,
+                "{{\"name\":{:?},\"cat\":\"{category}\",\"ph\":\"X\",\"pid\":0,\"tid\":{track},\
+                 \"ts\":{},\"dur\":{},\"args\":{{\"step\":{}}}}}{separator}",
+                span.name,
+                span.start / 1e3,
+                span.duration / 1e3,
+                span.step,
+            )?;
+        }
+        writeln!(writer, "]}}")
+    }
+
+    pub fn save_trace(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_trace(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// Table of the [`Profiler::summary`], in milliseconds and microseconds.
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = self.summary();
+        let steps = summary
+            .iter()
+            .find(|s| s.name == STEP)
+            .map_or(0.0, |s| s.total);
+        writeln!(
+            f,
+            "{:<18} {:>7} {:>11} {:>10} {:>10} {:>10} {:>6}",
+            "kernel", "calls", "total (ms)", "mean (us)", "min (us)", "max (us)", "share"
+        )?;
+        for s in &summary {
+            writeln!(
+                f,
+                "{:<18} {:>7} {:>11.3} {:>10.1} {:>10.1} {:>10.1} {:>5.1}%",
+                s.name,
+                s.count,
+                s.total / 1e6,
+                s.mean() / 1e3,
+                s.min / 1e3,
+                s.max / 1e3,
+                100.0 * s.total / steps,
+            )?;
+        }
+        if !self.per_kernel() {
+            writeln!(
+                f,
+                "the device has no timestamp queries: only whole steps were timed, on the host"
+            )?;
+        }
+        if self.dropped() > 0 {
+            writeln!(f, "{} kernels ran untimed", self.dropped())?;
+        }
+        if self.untraced() > 0 {
+            writeln!(
+                f,
+                "{} spans past the first {MAX_TRACE_SPANS} were left out of the trace",
+                self.untraced()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Where to report a profile once the run is over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileReport {
+    /// Print the summary to the standard output.
+    pub summary: bool,
+    /// Write the trace to this file.
+    pub trace: Option<PathBuf>,
+}
+
+impl ProfileReport {
+    pub fn write(&self, profiler: &Profiler) -> io::Result<()> {
+        if self.summary {
+            print!("{profiler}");
+        }
+        match &self.trace {
+            Some(path) => profiler.save_trace(path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Records `kernels` into `encoder`: a single compute pass, or one per
+/// kernel timed by `profiler` when it has timestamp queries.
+pub fn encode_kernels<'a>(
+    encoder: &mut wgpu::CommandEncoder,
+    label: Option<&str>,
+    kernels: impl IntoIterator<Item = &'a dyn Kernel>,
+    profiler: Option<&Profiler>,
+) {
+    match profiler.filter(|p| p.per_kernel()) {
+        Some(profiler) => {
+            for kernel in kernels {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some(kernel.name()),
+                    timestamp_writes: profiler.timestamp_writes(kernel.name()),
+                });
+                kernel.add_to_pass(&mut compute_pass);
+            }
+        }
+        None => {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label,
+                timestamp_writes: None,
+            });
+            for kernel in kernels {
+                kernel.add_to_pass(&mut compute_pass);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiler(spans: Vec<Span>, trace: bool) -> Profiler {
+        let timer = Timer::Host {
+            origin: None,
+            started: None,
+        };
+        let mut profiler = Profiler::with_timer(timer, trace);
+        for span in spans {
+            profiler.record(span);
+        }
+        profiler
+    }
+
+    fn span(name: &'static str, step: usize, start: f64, duration: f64) -> Span {
+        Span {
+            name,
+            step,
+            start,
+            duration,
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let spans = vec![
+            span("spmv", 1, 0.0, 2e3),
+            span("dot", 1, 2e3, 1e3),
+            span("spmv", 1, 3e3, 4e3),
+            span(STEP, 1, 0.0, 8e3),
+        ];
+        // the summary does not need the spans, which only a trace keeps
+        let traced = profiler(spans.clone(), true);
+        assert_eq!(traced.spans(), spans);
+        let profiler = profiler(spans, false);
+        assert!(profiler.spans().is_empty());
+        assert_eq!(profiler.summary(), traced.summary());
+        let summary = profiler.summary();
+        let names: Vec<_> = summary.iter().map(|s| s.name).collect();
+        assert_eq!(names, [STEP, "spmv", "dot"]);
+        assert_eq!(
+            summary[1],
+            KernelSummary {
+                name: "spmv",
+                count: 2,
+                total: 6e3,
+                min: 2e3,
+                max: 4e3,
+            }
+        );
+        assert_eq!(summary[1].mean(), 3e3);
+        let table = profiler.to_string();
+        assert!(table.contains(" 75.0%"), "{table}");
+        assert!(table.contains("only whole steps"));
+    }
+
+    #[test]
+    fn trace_events() {
+        let profiler = profiler(
+            vec![span("spmv", 3, 1500.0, 2500.0), span(STEP, 3, 0.0, 5e3)],
+            true,
+        );
+        let mut trace = Vec::new();
+        profiler.write_trace(&mut trace).unwrap();
+        let trace = String::from_utf8(trace).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(lines[0], "{\"displayTimeUnit\":\"ms\",\"traceEvents\":[");
+        assert_eq!(
+            lines[1],
+            "{\"name\":\"spmv\",\"cat\":\"kernel\",\"ph\":\"X\",\"pid\":0,\"tid\":1,\
+             \"ts\":1.5,\"dur\":2.5,\"args\":{\"step\":3}},"
+        );
+        assert!(lines[2].starts_with("{\"name\":\"step\",\"cat\":\"step\""));
+        assert!(lines[2].ends_with("}}"));
+        assert_eq!(lines[3], "]}");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::vertex::Vertex;
+use crate::{mask::Mask, vertex::Vertex};
 use wgpu::util::DeviceExt;
 use winit::window::Window;
 
@@ -20,10 +20,14 @@ pub struct Renderer {
 }
 
 impl Renderer {
+    /// Creates a renderer of `texture`, drawing the cells outside of `mask`
+    /// in gray instead of their temperature.
     pub fn new(
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         config: &wgpu::SurfaceConfiguration,
         texture: &wgpu::TextureView,
+        mask: Option<&Mask>,
     ) -> Self {
         let render_texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -47,9 +51,52 @@ impl Renderer {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), // TODO: check if this is desired
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
                 ],
             });
 
+        // Labels of the cells, see `Cell::label`. Without a mask, a single
+        // texel marks the whole grid as active.
+        let (mask_size, labels) = match mask {
+            Some(mask) => ((mask.width as u32, mask.height as u32), mask.labels()),
+            None => ((1, 1), vec![0]),
+        };
+        let mask_size = wgpu::Extent3d {
+            width: mask_size.0,
+            height: mask_size.1,
+            depth_or_array_layers: 1,
+        };
+        let mask_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Mask Texture"),
+            size: mask_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            mask_texture.as_image_copy(),
+            &labels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(mask_size.width),
+                rows_per_image: None,
+            },
+            mask_size,
+        );
+        let mask_view = mask_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
         let render_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -76,6 +123,10 @@ impl Renderer {
                         binding: 1,
                         resource: wgpu::BindingResource::Sampler(&render_sampler),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&mask_view),
+                    },
                 ],
             });
 
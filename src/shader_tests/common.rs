@@ -0,0 +1,42 @@
+//! Device setup shared by the GPU tests.
+
+pub(super) const ERR_DID_NOT_FIND_ADAPTER: &str = "Failed to find an appropriate adapter";
+
+pub(super) async fn request_device(
+) -> Result<(wgpu::Device, wgpu::Queue), Box<dyn std::error::Error>> {
+    request_device_with(wgpu::Limits::downlevel_defaults()).await
+}
+
+/// Requests a device with `limits`, for the kernels which bind more
+/// storage buffers than the downlevel defaults allow.
+pub(super) async fn request_device_with(
+    limits: wgpu::Limits,
+) -> Result<(wgpu::Device, wgpu::Queue), Box<dyn std::error::Error>> {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await
+        .ok_or(ERR_DID_NOT_FIND_ADAPTER)?;
+    let device_and_queue = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits,
+            },
+            None,
+        )
+        .await?;
+    Ok(device_and_queue)
+}
+
+/// Passes when `result` failed only because there is no adapter to run on.
+pub(super) fn skip_without_adapter(result: Result<(), Box<dyn std::error::Error>>) {
+    if let Err(e) = result {
+        if e.to_string() == ERR_DID_NOT_FIND_ADAPTER {
+            println!("Skipping test, no adapter found");
+        } else {
+            panic!("{:?}", e)
+        }
+    }
+}
@@ -0,0 +1,663 @@
+#[cfg(test)]
+mod tests {
+    use wgpu::util::DeviceExt;
+
+    use crate::{
+        cpu,
+        dia_matrix::{DIAMatrix, DIAMatrixDescriptor},
+        heat_equation::HeatEquation,
+        kernels::{
+            compensated_dot::CompensatedDotKernel,
+            diagnostics::DiagnosticsKernel,
+            dot::DotKernel,
+            kernel::Kernel,
+            saxpy_update::SAXPYUpdateKernel,
+            saxpy_update_div::{Operation, SAXPYUpdateDivKernel},
+            spmv::SpMVKernel,
+            write_to_texture::WriteToTextureKernel,
+        },
+        precision::Precision,
+        shader_tests::common::{request_device, request_device_with, skip_without_adapter},
+    };
+
+    fn storage_buffer(device: &wgpu::Device, contents: &[f32]) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(contents),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        })
+    }
+
+    /// Runs `kernel` and copies `output` back to the host.
+    async fn run_and_read(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        kernel: &dyn Kernel,
+        output: &wgpu::Buffer,
+    ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: output.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            kernel.add_to_pass(&mut cpass);
+        }
+        encoder.copy_buffer_to_buffer(output, 0, &staging_buffer, 0, output.size());
+        queue.submit(Some(encoder.finish()));
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        if let Some(Ok(())) = receiver.receive().await {
+            let data = buffer_slice.get_mapped_range();
+            let result = bytemuck::cast_slice(&data).to_vec();
+            drop(data);
+            staging_buffer.unmap();
+            Ok(result)
+        } else {
+            Err("failed to read back kernel output".into())
+        }
+    }
+
+    fn test_vector(len: usize) -> Vec<f32> {
+        (0..len).map(|i| ((i * 37) % 101) as f32 / 101.0).collect()
+    }
+
+    fn assert_close(gpu: &[f32], cpu: &[f64]) {
+        assert_eq!(gpu.len(), cpu.len());
+        for (i, (g, c)) in gpu.iter().zip(cpu).enumerate() {
+            assert!(
+                (*g as f64 - c).abs() <= 1e-5 * c.abs().max(1.0),
+                "mismatch at {i}: gpu = {g}, cpu = {c}"
+            );
+        }
+    }
+
+    async fn spmv_matches_cpu() -> Result<(), Box<dyn std::error::Error>> {
+        const N: usize = 32;
+        let (device, queue) = request_device().await?;
+        let b = HeatEquation::b_matrix(1.0, N, 0.001);
+        let x = test_vector(N * N);
+
+        let a = DIAMatrixDescriptor::from_matrix(&device, &b, Precision::Single);
+        let x_buffer = storage_buffer(&device, &x);
+        let y_buffer = storage_buffer(&device, &vec![0.0; N * N]);
+        let kernel = SpMVKernel::new(&device, &a, &x_buffer, &y_buffer);
+        let gpu = run_and_read(&device, &queue, &kernel, &y_buffer).await?;
+
+        let x: Vec<f64> = x.iter().map(|&v| v as f64).collect();
+        let mut expected = vec![0.0; N * N];
+        cpu::kernels::spmv(&b, &x, &mut expected);
+        assert_close(&gpu, &expected);
+        Ok(())
+    }
+
+    async fn saxpy_update_div_matches_cpu() -> Result<(), Box<dyn std::error::Error>> {
+        const M: usize = 1000;
+        let (device, queue) = request_device().await?;
+        let x = test_vector(M);
+        let y: Vec<f32> = x.iter().rev().copied().collect();
+        for op in [Operation::Add, Operation::Sub, Operation::Aypx] {
+            let a1 = storage_buffer(&device, &[3.0]);
+            let a2 = storage_buffer(&device, &[4.0]);
+            let x_buffer = storage_buffer(&device, &x);
+            let y_buffer = storage_buffer(&device, &y);
+            let kernel = SAXPYUpdateDivKernel::new(
+                &device,
+                Precision::Single,
+                &a1,
+                &a2,
+                &x_buffer,
+                &y_buffer,
+                op,
+            );
+            let gpu = run_and_read(&device, &queue, &kernel, &y_buffer).await?;
+
+            let x: Vec<f64> = x.iter().map(|&v| v as f64).collect();
+            let mut expected: Vec<f64> = y.iter().map(|&v| v as f64).collect();
+            cpu::kernels::saxpy_update_div(3.0, 4.0, &x, &mut expected, op);
+            assert_close(&gpu, &expected);
+        }
+        Ok(())
+    }
+
+    async fn dot_matches_cpu() -> Result<(), Box<dyn std::error::Error>> {
+        const M: usize = 128 * 128;
+        let (device, queue) = request_device().await?;
+        let x = test_vector(M);
+        let y: Vec<f32> = x.iter().rev().copied().collect();
+        let x_buffer = storage_buffer(&device, &x);
+        let y_buffer = storage_buffer(&device, &y);
+        let tmp = storage_buffer(&device, &vec![0.0; M]);
+        let output = storage_buffer(&device, &[0.0]);
+        let kernel = DotKernel::new(
+            &device,
+            Precision::Single,
+            &x_buffer,
+            &y_buffer,
+            &tmp,
+            &output,
+        );
+        let gpu = run_and_read(&device, &queue, &kernel, &output).await?;
+
+        let x: Vec<f64> = x.iter().map(|&v| v as f64).collect();
+        let y: Vec<f64> = y.iter().map(|&v| v as f64).collect();
+        assert_close(&gpu, &[cpu::kernels::dot(&x, &y)]);
+        Ok(())
+    }
+
+    async fn compensated_dot_accuracy() -> Result<(), Box<dyn std::error::Error>> {
+        // a million entries with heavy cancellation: the entries are about
+        // 1000 while the dot product is about the sum of the fractional parts
+        const M: usize = 1 << 20;
+        let (device, queue) = request_device().await?;
+        let x: Vec<f32> = (0..M)
+            .map(|i| 1000.0 + ((i * 7919) % 1009) as f32 / 1009.0)
+            .collect();
+        let y: Vec<f32> = (0..M)
+            .map(|i| if i % 3 == 0 { 1.0 } else { -0.5 })
+            .collect();
+        let x_buffer = storage_buffer(&device, &x);
+        let y_buffer = storage_buffer(&device, &y);
+        let tmp = storage_buffer(&device, &vec![0.0; M]);
+        let output = storage_buffer(&device, &[0.0]);
+        let plain = DotKernel::new(
+            &device,
+            Precision::Single,
+            &x_buffer,
+            &y_buffer,
+            &tmp,
+            &output,
+        );
+        let plain = run_and_read(&device, &queue, &plain, &output).await?[0] as f64;
+        let output = storage_buffer(&device, &[0.0]);
+        let compensated = CompensatedDotKernel::new(&device, &x_buffer, &y_buffer, &output);
+        let compensated = run_and_read(&device, &queue, &compensated, &output).await?[0] as f64;
+
+        let x: Vec<f64> = x.iter().map(|&v| v as f64).collect();
+        let y: Vec<f64> = y.iter().map(|&v| v as f64).collect();
+        let exact = cpu::kernels::dot(&x, &y);
+        let plain_error = ((plain - exact) / exact).abs();
+        let compensated_error = ((compensated - exact) / exact).abs();
+        // the compensated result is the exact one rounded to f32, so no f32
+        // result is closer; how far off the plain sum is depends on the
+        // adapter's summation order (about 17 roundings on llvmpipe)
+        let eps = f32::EPSILON as f64;
+        assert!(compensated_error <= eps / 2.0, "{compensated_error:e}");
+        assert!(compensated_error <= plain_error, "{plain_error:e}");
+        Ok(())
+    }
+
+    async fn diagnostics_matches_cpu() -> Result<(), Box<dyn std::error::Error>> {
+        let (device, queue) = request_device().await?;
+        // lengths below, between and above multiples of the 512 values per block
+        for len in [1, 300, 37 * 91, 70_000] {
+            let x: Vec<f32> = test_vector(len).iter().map(|v| v - 0.3).collect();
+            let x_buffer = storage_buffer(&device, &x);
+            let output = storage_buffer(&device, &[0.0; 4]);
+            let kernel = DiagnosticsKernel::new(&device, &x_buffer, None, &output);
+            let gpu = run_and_read(&device, &queue, &kernel, &output).await?;
+            let reductions = |x: &[f64]| {
+                [
+                    x.iter().sum(),
+                    x.iter().cloned().fold(f64::INFINITY, f64::min),
+                    x.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                    x.iter().map(|v| v * v).sum(),
+                ]
+            };
+            let x64: Vec<f64> = x.iter().map(|&v| v as f64).collect();
+            assert_close(&gpu, &reductions(&x64));
+
+            // mask out every third value, including the largest one
+            let mut x = x;
+            let mask: Vec<u32> = (0..len).map(|i| (i % 3 != 1) as u32).collect();
+            if len > 1 {
+                x[1] = 2.0;
+            }
+            let x_buffer = storage_buffer(&device, &x);
+            let mask_buffer = storage_buffer(&device, bytemuck::cast_slice(&mask));
+            let kernel = DiagnosticsKernel::new(&device, &x_buffer, Some(&mask_buffer), &output);
+            let gpu = run_and_read(&device, &queue, &kernel, &output).await?;
+            let active: Vec<f64> = (0..len)
+                .filter(|&i| mask[i] != 0)
+                .map(|i| x[i] as f64)
+                .collect();
+            assert_close(&gpu, &reductions(&active));
+        }
+        Ok(())
+    }
+
+    async fn odd_sizes_match_cpu() -> Result<(), Box<dyn std::error::Error>> {
+        // a 37 x 91 grid, which no workgroup size divides
+        const W: usize = 37;
+        const M: usize = W * 91;
+        let (device, queue) = request_device().await?;
+        let x = test_vector(M);
+        let y: Vec<f32> = x.iter().rev().copied().collect();
+        let x64: Vec<f64> = x.iter().map(|&v| v as f64).collect();
+        let y64: Vec<f64> = y.iter().map(|&v| v as f64).collect();
+
+        let offsets = vec![-(W as i32), -1, 0, 1, W as i32];
+        let data = (0..5 * M).map(|i| ((i % 7) as f64 - 3.0) / 4.0).collect();
+        let a = DIAMatrix::new(M, M, offsets, data);
+        let a_buffers = DIAMatrixDescriptor::from_matrix(&device, &a, Precision::Single);
+        let x_buffer = storage_buffer(&device, &x);
+        let out = storage_buffer(&device, &vec![0.0; M]);
+        let kernel = SpMVKernel::new(&device, &a_buffers, &x_buffer, &out);
+        let gpu = run_and_read(&device, &queue, &kernel, &out).await?;
+        let mut expected = vec![0.0; M];
+        cpu::kernels::spmv(&a, &x64, &mut expected);
+        assert_close(&gpu, &expected);
+
+        let y_buffer = storage_buffer(&device, &y);
+        let kernel = SAXPYUpdateKernel::new(&device, Precision::Single, &x_buffer, &y_buffer);
+        let gpu = run_and_read(&device, &queue, &kernel, &y_buffer).await?;
+        let mut expected = y64.clone();
+        cpu::kernels::saxpy_update(&x64, &mut expected);
+        assert_close(&gpu, &expected);
+
+        let y_buffer = storage_buffer(&device, &y);
+        let a1 = storage_buffer(&device, &[3.0]);
+        let a2 = storage_buffer(&device, &[4.0]);
+        let kernel = SAXPYUpdateDivKernel::new(
+            &device,
+            Precision::Single,
+            &a1,
+            &a2,
+            &x_buffer,
+            &y_buffer,
+            Operation::Add,
+        );
+        let gpu = run_and_read(&device, &queue, &kernel, &y_buffer).await?;
+        let mut expected = y64.clone();
+        cpu::kernels::saxpy_update_div(3.0, 4.0, &x64, &mut expected, Operation::Add);
+        assert_close(&gpu, &expected);
+
+        let y_buffer = storage_buffer(&device, &y);
+        let tmp = storage_buffer(&device, &vec![0.0; M]);
+        let dot = storage_buffer(&device, &[0.0]);
+        let kernel = DotKernel::new(&device, Precision::Single, &x_buffer, &y_buffer, &tmp, &dot);
+        let gpu = run_and_read(&device, &queue, &kernel, &dot).await?;
+        assert_close(&gpu, &[cpu::kernels::dot(&x64, &y64)]);
+        Ok(())
+    }
+
+    async fn double_float_matches_cpu() -> Result<(), Box<dyn std::error::Error>> {
+        const N: usize = 32;
+        const M: usize = N * N;
+        let precision = Precision::DoubleFloat;
+        let (device, queue) = request_device().await?;
+        let buffer = |values: &[f64]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &precision.encode(values.iter().copied()),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            })
+        };
+        let decode = |values: Vec<f32>| precision.decode(bytemuck::cast_slice(&values));
+        // none of these values is an f32
+        let x: Vec<f64> = (0..M).map(|i| 1.0 / 3.0 + i as f64 / 7.0).collect();
+        let y: Vec<f64> = x.iter().rev().map(|v| v.sqrt()).collect();
+        let assert_close = |gpu: &[f64], cpu: &[f64]| {
+            assert_eq!(gpu.len(), cpu.len());
+            for (i, (g, c)) in gpu.iter().zip(cpu).enumerate() {
+                assert!(
+                    (g - c).abs() <= 1e-12 * c.abs().max(1.0),
+                    "mismatch at {i}: gpu = {g}, cpu = {c}"
+                );
+            }
+        };
+
+        let b = HeatEquation::b_matrix(1.0, N, 0.001);
+        let a = DIAMatrixDescriptor::from_matrix(&device, &b, precision);
+        let x_buffer = buffer(&x);
+        let out = buffer(&[0.0; M]);
+        let kernel = SpMVKernel::new(&device, &a, &x_buffer, &out);
+        let gpu = decode(run_and_read(&device, &queue, &kernel, &out).await?);
+        let mut expected = vec![0.0; M];
+        cpu::kernels::spmv(&b, &x, &mut expected);
+        assert_close(&gpu, &expected);
+
+        let y_buffer = buffer(&y);
+        let kernel = SAXPYUpdateKernel::new(&device, precision, &x_buffer, &y_buffer);
+        let gpu = decode(run_and_read(&device, &queue, &kernel, &y_buffer).await?);
+        let mut expected = y.clone();
+        cpu::kernels::saxpy_update(&x, &mut expected);
+        assert_close(&gpu, &expected);
+
+        for op in Operation::ALL {
+            let (a1, a2) = (1.0 / 3.0, 1.0 / 7.0);
+            let y_buffer = buffer(&y);
+            let kernel = SAXPYUpdateDivKernel::new(
+                &device,
+                precision,
+                &buffer(&[a1]),
+                &buffer(&[a2]),
+                &x_buffer,
+                &y_buffer,
+                op,
+            );
+            let gpu = decode(run_and_read(&device, &queue, &kernel, &y_buffer).await?);
+            let mut expected = y.clone();
+            cpu::kernels::saxpy_update_div(a1, a2, &x, &mut expected, op);
+            assert_close(&gpu, &expected);
+        }
+
+        let y_buffer = buffer(&y);
+        let tmp = buffer(&[0.0; M]);
+        let dot = buffer(&[0.0]);
+        let kernel = DotKernel::new(&device, precision, &x_buffer, &y_buffer, &tmp, &dot);
+        let gpu = decode(run_and_read(&device, &queue, &kernel, &dot).await?);
+        assert_close(&gpu, &[cpu::kernels::dot(&x, &y)]);
+        Ok(())
+    }
+
+    async fn sparse_formats_match_cpu() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            conjugate_gradient::{CGBuffers, CGVariant, CG},
+            csr_matrix::{CSRMatrix, CSRMatrixDescriptor},
+            ell_matrix::{ELLMatrix, ELLMatrixDescriptor},
+            kernels::{dot::Summation, spmv_dots},
+            sparse_matrix::SparseMatrix,
+        };
+
+        // the fused kernels bind up to 7 storage buffers
+        let (device, queue) = request_device_with(wgpu::Limits::default()).await?;
+        let upload = |a: &CSRMatrix, precision| -> Vec<Box<dyn SparseMatrix>> {
+            vec![
+                Box::new(DIAMatrixDescriptor::from_matrix(
+                    &device,
+                    &DIAMatrix::from(a),
+                    precision,
+                )),
+                Box::new(CSRMatrixDescriptor::from_matrix(&device, a, precision)),
+                Box::new(ELLMatrixDescriptor::from_matrix(
+                    &device,
+                    &ELLMatrix::from(a),
+                    precision,
+                )),
+            ]
+        };
+
+        // rows with 0 to 4 entries scattered over the columns
+        const M: usize = 37 * 11;
+        let mut rows = vec![0];
+        let (mut cols, mut data) = (Vec::new(), Vec::new());
+        for i in 0..M {
+            let mut row: Vec<usize> = (0..i % 5).map(|j| (i + j * j * 31) % M).collect();
+            row.sort_unstable();
+            row.dedup();
+            for col in row {
+                cols.push(col as u32);
+                data.push(((i + col) % 7) as f64 / 3.0 - 1.0);
+            }
+            rows.push(cols.len() as u32);
+        }
+        let a = CSRMatrix::new(M, M, rows, cols, data);
+        let x: Vec<f64> = (0..M).map(|i| 1.0 / 3.0 + i as f64 / 7.0).collect();
+        let mut expected = vec![0.0; M];
+        cpu::kernels::spmv(&DIAMatrix::from(&a), &x, &mut expected);
+        for precision in [Precision::Single, Precision::DoubleFloat] {
+            let buffer = |values: &[f64]| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: &precision.encode(values.iter().copied()),
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                })
+            };
+            let tolerance = match precision {
+                Precision::DoubleFloat => 1e-12,
+                _ => 1e-5,
+            };
+            for matrix in upload(&a, precision) {
+                let (x_buffer, out) = (buffer(&x), buffer(&[0.0; M]));
+                let kernel = matrix.spmv(&device, &x_buffer, &out);
+                let gpu = run_and_read(&device, &queue, kernel.as_ref(), &out).await?;
+                let gpu = precision.decode(bytemuck::cast_slice(&gpu));
+                for (i, (g, c)) in gpu.iter().zip(&expected).enumerate() {
+                    assert!(
+                        (g - c).abs() <= tolerance * c.abs().max(1.0),
+                        "{precision:?}: mismatch at {i}: gpu = {g}, cpu = {c}"
+                    );
+                }
+
+                // the products of x . y and x . x add up to the dot products
+                let products = buffer(&vec![0.0; spmv_dots::products_len(M)]);
+                let kernel = matrix.spmv_dots(&device, &x_buffer, &out, &products);
+                let gpu = run_and_read(&device, &queue, kernel.as_ref(), &products).await?;
+                let gpu = precision.decode(bytemuck::cast_slice(&gpu));
+                let (xy, xx) = gpu.split_at(gpu.len() / 2);
+                for (sum, dot) in [
+                    (xy.iter().sum::<f64>(), cpu::kernels::dot(&x, &expected)),
+                    (xx.iter().sum::<f64>(), cpu::kernels::dot(&x, &x)),
+                ] {
+                    assert!(
+                        (sum - dot).abs() <= tolerance * dot.abs(),
+                        "{precision:?}: block sums add up to {sum}, expected {dot}"
+                    );
+                }
+            }
+        }
+
+        // the conjugate gradient solver gives the same result in every format
+        // and variant
+        const N: usize = 16;
+        let a = HeatEquation::a_matrix(1.0, N, 0.001);
+        let b: Vec<f64> = test_vector(N * N).iter().map(|&v| v as f64).collect();
+        let mut expected = vec![0.0; N * N];
+        cpu::conjugate_gradient::CG::new(N * N).run(&a, &b, &mut expected);
+        let b_buffer = storage_buffer(&device, &test_vector(N * N));
+        for variant in [CGVariant::Standard, CGVariant::Fused, CGVariant::Pipelined] {
+            let buffers =
+                std::rc::Rc::new(CGBuffers::new(&device, Precision::Single, N * N, variant));
+            for matrix in upload(&CSRMatrix::from(&a), Precision::Single) {
+                let x_buffer = storage_buffer(&device, &[0.0; N * N]);
+                let cg = CG::new(
+                    &device,
+                    buffers.clone(),
+                    matrix.as_ref(),
+                    &b_buffer,
+                    &x_buffer,
+                    Summation::Plain,
+                );
+                cg.run(&device, &queue);
+                let gpu = run_and_read(&device, &queue, &NoKernel, &x_buffer).await?;
+                assert_close(&gpu, &expected);
+            }
+        }
+        Ok(())
+    }
+
+    async fn stencil_matches_cpu() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            sparse_matrix::SparseMatrix,
+            stencil_operator::{Stencil, StencilOperator},
+        };
+
+        // a grid which no workgroup size divides
+        const W: usize = 37;
+        const H: usize = 11;
+        let (device, queue) = request_device().await?;
+        let x: Vec<f64> = (0..W * H).map(|i| 1.0 / 3.0 + i as f64 / 7.0).collect();
+        for stencil in [
+            Stencil::five_point(1.0 + 4.0 / 3.0, -1.0 / 3.0),
+            Stencil::nine_point(8.0 / 3.0, -1.0 / 3.0, -1.0 / 7.0),
+        ] {
+            let mut expected = vec![0.0; W * H];
+            cpu::kernels::spmv(
+                &DIAMatrix::from(&stencil.to_matrix(W, H)),
+                &x,
+                &mut expected,
+            );
+            for precision in [Precision::Single, Precision::DoubleFloat] {
+                let buffer = |values: &[f64]| {
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: None,
+                        contents: &precision.encode(values.iter().copied()),
+                        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                    })
+                };
+                let tolerance = match precision {
+                    Precision::DoubleFloat => 1e-12,
+                    _ => 1e-5,
+                };
+                let a = StencilOperator::new(&device, stencil, W as u32, H as u32, precision);
+                let (x_buffer, out) = (buffer(&x), buffer(&[0.0; W * H]));
+                let kernel = a.spmv(&device, &x_buffer, &out);
+                let gpu = run_and_read(&device, &queue, kernel.as_ref(), &out).await?;
+                let gpu = precision.decode(bytemuck::cast_slice(&gpu));
+                for (i, (g, c)) in gpu.iter().zip(&expected).enumerate() {
+                    assert!(
+                        (g - c).abs() <= tolerance * c.abs().max(1.0),
+                        "{stencil:?} in {precision:?}: mismatch at {i}: gpu = {g}, cpu = {c}"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a buffer back without running anything.
+    struct NoKernel;
+
+    impl Kernel for NoKernel {
+        fn name(&self) -> &'static str {
+            "none"
+        }
+
+        fn add_to_pass<'a>(&'a self, _: &mut wgpu::ComputePass<'a>) {}
+    }
+
+    async fn write_to_texture_odd_sizes() -> Result<(), Box<dyn std::error::Error>> {
+        let (device, queue) = request_device().await?;
+        // smaller than, and not a multiple of, the 16 x 16 workgroups
+        for (width, height) in [(37, 91), (5, 3)] {
+            let x = test_vector(width * height);
+            let x_buffer = storage_buffer(&device, &x);
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: width as u32,
+                    height: height as u32,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R32Float,
+                usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let kernel = WriteToTextureKernel::new(&device, &x_buffer, &texture);
+
+            // rows of a texture copy are padded to 256 bytes
+            let padded_width = (width * 4).next_multiple_of(256) / 4;
+            let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: (padded_width * height * std::mem::size_of::<f32>()) as u64,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: None,
+                    timestamp_writes: None,
+                });
+                kernel.add_to_pass(&mut cpass);
+            }
+            encoder.copy_texture_to_buffer(
+                texture.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &staging_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_width as u32 * 4),
+                        rows_per_image: None,
+                    },
+                },
+                texture.size(),
+            );
+            queue.submit(Some(encoder.finish()));
+
+            let buffer_slice = staging_buffer.slice(..);
+            let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+            buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+            device.poll(wgpu::Maintain::Wait);
+            receiver
+                .receive()
+                .await
+                .ok_or("failed to read back the texture")??;
+            let data = buffer_slice.get_mapped_range();
+            let texels: &[f32] = bytemuck::cast_slice(&data);
+            for row in 0..height {
+                assert_eq!(
+                    &texels[row * padded_width..row * padded_width + width],
+                    &x[row * width..(row + 1) * width],
+                    "row {row} of a {width} x {height} texture"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn spmv() {
+        skip_without_adapter(pollster::block_on(spmv_matches_cpu()));
+    }
+
+    #[test]
+    fn saxpy_update_div() {
+        skip_without_adapter(pollster::block_on(saxpy_update_div_matches_cpu()));
+    }
+
+    #[test]
+    fn dot() {
+        skip_without_adapter(pollster::block_on(dot_matches_cpu()));
+    }
+
+    #[test]
+    fn sparse_formats() {
+        skip_without_adapter(pollster::block_on(sparse_formats_match_cpu()));
+    }
+
+    #[test]
+    fn stencil() {
+        skip_without_adapter(pollster::block_on(stencil_matches_cpu()));
+    }
+
+    #[test]
+    fn compensated_dot() {
+        skip_without_adapter(pollster::block_on(compensated_dot_accuracy()));
+    }
+
+    #[test]
+    fn double_float() {
+        skip_without_adapter(pollster::block_on(double_float_matches_cpu()));
+    }
+
+    #[test]
+    fn diagnostics() {
+        skip_without_adapter(pollster::block_on(diagnostics_matches_cpu()));
+    }
+
+    #[test]
+    fn odd_sizes() {
+        skip_without_adapter(pollster::block_on(odd_sizes_match_cpu()));
+    }
+
+    #[test]
+    fn write_to_texture() {
+        skip_without_adapter(pollster::block_on(write_to_texture_odd_sizes()));
+    }
+}
@@ -0,0 +1,848 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        heat_equation::HeatEquation,
+        shader_tests::common::{
+            request_device, request_device_with, skip_without_adapter, ERR_DID_NOT_FIND_ADAPTER,
+        },
+    };
+
+    fn create_texture(device: &wgpu::Device, n: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: n,
+                height: n,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    async fn read_field_inner() -> Result<(), Box<dyn std::error::Error>> {
+        const N: usize = 32;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N).map(|i| i as f32).collect();
+        let mut heat_eqn = HeatEquation::new(&device, 1e-3, N, 0.01, &u0, &texture);
+
+        let field = heat_eqn.read_field(&device, &queue)?;
+        assert_eq!((field.width, field.height), (N, N));
+        assert_eq!(field.data, u0);
+        assert_eq!(field.get(3, 2), (2 * N + 3) as f32);
+
+        // after a step, the solution lives in the other buffer
+        heat_eqn.compute_step(&device, &queue);
+        let field = heat_eqn.read_field(&device, &queue)?;
+        assert_eq!(field.data.len(), N * N);
+        assert_ne!(field.data, u0);
+        Ok(())
+    }
+
+    async fn set_field_inner() -> Result<(), Box<dyn std::error::Error>> {
+        const N: usize = 16;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let mut heat_eqn = HeatEquation::new(&device, 1e-3, N, 0.01, &[0.0; N * N], &texture);
+        heat_eqn.compute_step(&device, &queue);
+
+        let data: Vec<f32> = (0..N * N).map(|i| (i % 7) as f32).collect();
+        heat_eqn.set_field(&device, &queue, &data);
+        assert_eq!(heat_eqn.read_field(&device, &queue)?.data, data);
+
+        // resetting to zero must not break the solver
+        heat_eqn.set_field(&device, &queue, &[0.0; N * N]);
+        heat_eqn.compute_step(&device, &queue);
+        let field = heat_eqn.read_field(&device, &queue)?;
+        assert!(field.data.iter().all(|&v| v == 0.0));
+
+        heat_eqn.set_field(&device, &queue, &data);
+        heat_eqn.set_region(&device, &queue, (2, 3), 3, &[-1.0; 6]);
+        let field = heat_eqn.read_field(&device, &queue)?;
+        for y in 0..N {
+            for x in 0..N {
+                let expected = if (2..5).contains(&x) && (3..5).contains(&y) {
+                    -1.0
+                } else {
+                    data[y * N + x]
+                };
+                assert_eq!(field.get(x, y), expected, "at ({x}, {y})");
+            }
+        }
+        Ok(())
+    }
+
+    async fn checkpoint_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{heat_equation::SolverOptions, precision::Precision};
+
+        const N: usize = 16;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N).map(|i| (i % 5) as f32).collect();
+        let mut original = HeatEquation::new(&device, 1e-3, N, 0.01, &u0, &texture);
+        for _ in 0..3 {
+            original.compute_step(&device, &queue);
+        }
+        let checkpoint = original.checkpoint(&device, &queue)?;
+        assert_eq!(checkpoint.iteration, 3);
+
+        // the restored run continues exactly like the original one
+        let mut restored = HeatEquation::new(&device, 1e-3, N, 0.01, &[0.0; N * N], &texture);
+        restored.restore(&device, &queue, &checkpoint)?;
+        assert_eq!(restored.iteration(), 3);
+        original.compute_step(&device, &queue);
+        restored.compute_step(&device, &queue);
+        assert_eq!(
+            restored.read_field(&device, &queue)?,
+            original.read_field(&device, &queue)?
+        );
+
+        let mut other_grid = HeatEquation::new(&device, 1e-3, 8, 0.01, &[0.0; 64], &texture);
+        assert!(other_grid.restore(&device, &queue, &checkpoint).is_err());
+        let mut other_dt = HeatEquation::new(&device, 1e-3, N, 0.02, &u0, &texture);
+        assert!(other_dt.restore(&device, &queue, &checkpoint).is_err());
+
+        // double precision runs are saved without rounding to f32, including
+        // the previous solution of steps recorded together
+        let options = SolverOptions {
+            precision: Precision::DoubleFloat,
+            ..SolverOptions::default()
+        };
+        let mut original =
+            HeatEquation::with_options(&device, 1e-3, N, 0.01, &u0, &texture, options.clone());
+        original.compute_steps(&device, &queue, 3);
+        let checkpoint = original.checkpoint(&device, &queue)?;
+        assert_eq!(checkpoint.field, original.read_values(&device, &queue)?);
+        let mut restored =
+            HeatEquation::with_options(&device, 1e-3, N, 0.01, &[0.0; N * N], &texture, options);
+        restored.restore(&device, &queue, &checkpoint)?;
+        original.compute_step(&device, &queue);
+        restored.compute_step(&device, &queue);
+        // a double-float value is saved as its f64 sum, which may split into
+        // a different pair when restored, so the runs agree to within f64
+        // rounding rather than bit for bit
+        let max_difference = restored
+            .read_values(&device, &queue)?
+            .iter()
+            .zip(original.read_values(&device, &queue)?)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert!(max_difference < 1e-14, "{max_difference:e}");
+        assert!(other_grid.restore(&device, &queue, &checkpoint).is_err());
+        let mut single = HeatEquation::new(&device, 1e-3, N, 0.01, &u0, &texture);
+        assert!(single.restore(&device, &queue, &checkpoint).is_err());
+        Ok(())
+    }
+
+    async fn eigenmode_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            cpu,
+            heat_equation::SolverOptions,
+            kernels::dot::Summation,
+            verification::{eigenmode_error, Eigenmode, Reference},
+        };
+
+        const N: usize = 15;
+        const ALPHA: f32 = 1.0;
+        const DT: f32 = 1e-3;
+        let mode = Eigenmode { k: 1, l: 2 };
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0 = mode.initial_condition(N);
+        let mut cpu = cpu::heat_equation::HeatEquation::new(ALPHA, N, DT, &u0);
+        let cpu_error = eigenmode_error(&mut cpu, (), mode, ALPHA, DT, 20, Reference::Exact)?;
+
+        for summation in [Summation::Plain, Summation::Compensated] {
+            let options = SolverOptions {
+                summation,
+                ..SolverOptions::default()
+            };
+            let mut gpu = HeatEquation::with_options(&device, ALPHA, N, DT, &u0, &texture, options);
+
+            // single precision only adds a small error to the discretization error
+            let gpu_error = eigenmode_error(
+                &mut gpu,
+                (&device, &queue),
+                mode,
+                ALPHA,
+                DT,
+                20,
+                Reference::Exact,
+            )?;
+            assert!(
+                (gpu_error.l2 - cpu_error.l2).abs() < 0.05 * cpu_error.l2,
+                "{summation:?}: GPU error {gpu_error:?}, CPU error {cpu_error:?}"
+            );
+        }
+        Ok(())
+    }
+
+    async fn double_float_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{cpu, heat_equation::SolverOptions, precision::Precision};
+
+        const N: usize = 24;
+        const ALPHA: f32 = 1e-3;
+        const DT: f32 = 0.01;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        let mut cpu = cpu::heat_equation::HeatEquation::new(ALPHA, N, DT, &u0);
+
+        // the GPU solvers run the same algorithm as the f64 CPU solver, so
+        // they only differ from it by rounding errors
+        let mut errors = Vec::new();
+        for precision in [Precision::Single, Precision::DoubleFloat] {
+            let options = SolverOptions {
+                precision,
+                ..SolverOptions::default()
+            };
+            let mut gpu = HeatEquation::with_options(&device, ALPHA, N, DT, &u0, &texture, options);
+            assert_eq!(gpu.precision(), precision);
+            for _ in 0..10 {
+                gpu.compute_step(&device, &queue);
+            }
+            let values = gpu.read_values(&device, &queue)?;
+            errors.push((values, gpu.read_field(&device, &queue)?));
+        }
+        for _ in 0..10 {
+            cpu.compute_step();
+        }
+        let max_error = |values: &[f64]| {
+            values
+                .iter()
+                .zip(cpu.field())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max)
+        };
+        let single = max_error(&errors[0].0);
+        let double_float = max_error(&errors[1].0);
+        assert!(double_float < 1e-10, "{double_float:e}");
+        assert!(single > 1e3 * double_float, "{single:e}");
+        // the single precision field is the rounded solution
+        let rounded: Vec<f32> = errors[1].0.iter().map(|&v| v as f32).collect();
+        assert_eq!(errors[1].1.data, rounded);
+        Ok(())
+    }
+
+    async fn iterative_refinement_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            cpu::kernels::{dot, spmv},
+            heat_equation::SolverOptions,
+            iterative_refinement::RefinementOptions,
+            precision::Precision,
+        };
+
+        const N: usize = 24;
+        const ALPHA: f32 = 0.2;
+        const DT: f32 = 0.01;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        let a = HeatEquation::a_matrix(ALPHA, N, DT);
+        let b = HeatEquation::b_matrix(ALPHA, N, DT);
+        // relative residual of the Crank-Nicolson system, in f64
+        let relative_residual = |u_old: &[f64], u_new: &[f64]| {
+            let mut rhs = vec![0.0; N * N];
+            spmv(&b, u_old, &mut rhs);
+            let mut r = vec![0.0; N * N];
+            spmv(&a, u_new, &mut r);
+            r.iter_mut().zip(&rhs).for_each(|(r, b)| *r = b - *r);
+            (dot(&r, &r) / dot(&rhs, &rhs)).sqrt()
+        };
+        let refinement = RefinementOptions {
+            tolerance: 1e-12,
+            ..RefinementOptions::default()
+        };
+
+        let mut residuals = Vec::new();
+        for refinement in [None, Some(refinement)] {
+            let options = SolverOptions {
+                precision: Precision::DoubleFloat,
+                refinement,
+                ..SolverOptions::default()
+            };
+            let mut gpu = HeatEquation::with_options(&device, ALPHA, N, DT, &u0, &texture, options);
+            assert!(gpu.refinement_report().is_none());
+            // both the forward and the backward solvers
+            let mut u_old: Vec<f64> = u0.iter().map(|&v| v as f64).collect();
+            let mut worst: f64 = 0.0;
+            for _ in 0..2 {
+                gpu.compute_step(&device, &queue);
+                let u_new = gpu.read_values(&device, &queue)?;
+                worst = worst.max(relative_residual(&u_old, &u_new));
+                assert_eq!(gpu.refinement_report().is_some(), refinement.is_some());
+                if let Some(report) = gpu.refinement_report() {
+                    assert!(report.iterations() > 0);
+                    assert!(report.residual() <= refinement.unwrap().tolerance);
+                    // the GPU residual agrees with the one computed on the host
+                    let host = relative_residual(&u_old, &u_new);
+                    assert!((report.residual() - host).abs() < 1e-13, "{host:e}");
+                }
+                u_old = u_new;
+            }
+            residuals.push(worst);
+        }
+        assert!(residuals[1] < 1e-12, "{:e}", residuals[1]);
+        assert!(residuals[0] > 1e2 * residuals[1], "{:e}", residuals[0]);
+        Ok(())
+    }
+
+    async fn matrix_free_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            heat_equation::SolverOptions, iterative_refinement::RefinementOptions,
+            precision::Precision,
+        };
+
+        const N: usize = 24;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        let refined = SolverOptions {
+            precision: Precision::DoubleFloat,
+            refinement: Some(RefinementOptions::default()),
+            ..SolverOptions::default()
+        };
+        for options in [SolverOptions::default(), refined] {
+            let mut values = Vec::new();
+            for matrix_free in [false, true] {
+                let options = SolverOptions {
+                    matrix_free,
+                    ..options.clone()
+                };
+                let mut gpu =
+                    HeatEquation::with_options(&device, 0.2, N, 0.01, &u0, &texture, options);
+                for _ in 0..5 {
+                    gpu.compute_step(&device, &queue);
+                }
+                values.push(gpu.read_values(&device, &queue)?);
+            }
+            // the stencil multiplies in the same order as the DIA matrix
+            let difference = values[0]
+                .iter()
+                .zip(&values[1])
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max);
+            assert!(difference < 1e-6, "{options:?}: {difference:e}");
+        }
+        Ok(())
+    }
+
+    async fn cg_variants_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            conjugate_gradient::CGVariant, heat_equation::SolverOptions, precision::Precision,
+        };
+
+        const N: usize = 24;
+        // the fused kernels bind up to 7 storage buffers
+        let (device, queue) = request_device_with(wgpu::Limits::default()).await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        for (precision, tolerance) in [(Precision::Single, 1e-5), (Precision::DoubleFloat, 1e-10)] {
+            for matrix_free in [false, true] {
+                let mut values = Vec::new();
+                for cg in [CGVariant::Standard, CGVariant::Fused, CGVariant::Pipelined] {
+                    let options = SolverOptions {
+                        precision,
+                        matrix_free,
+                        cg,
+                        ..SolverOptions::default()
+                    };
+                    let mut gpu =
+                        HeatEquation::with_options(&device, 0.2, N, 0.01, &u0, &texture, options);
+                    for _ in 0..5 {
+                        gpu.compute_step(&device, &queue);
+                    }
+                    values.push(gpu.read_values(&device, &queue)?);
+                }
+                // the variants only differ in the rounding of the iterations
+                for (cg, other) in [CGVariant::Fused, CGVariant::Pipelined]
+                    .iter()
+                    .zip(&values[1..])
+                {
+                    let difference = values[0]
+                        .iter()
+                        .zip(other)
+                        .map(|(a, b)| (a - b).abs())
+                        .fold(0.0, f64::max);
+                    assert!(
+                        difference < tolerance,
+                        "{cg:?}, {precision:?}, matrix_free = {matrix_free}: {difference:e}"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn batched_steps_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            heat_equation::SolverOptions, iterative_refinement::RefinementOptions,
+            precision::Precision,
+        };
+
+        const N: usize = 24;
+        const STEPS: usize = 5;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        let refined = SolverOptions {
+            precision: Precision::DoubleFloat,
+            refinement: Some(RefinementOptions::default()),
+            ..SolverOptions::default()
+        };
+        for options in [SolverOptions::default(), refined] {
+            let mut single =
+                HeatEquation::with_options(&device, 0.2, N, 0.01, &u0, &texture, options.clone());
+            for _ in 0..STEPS {
+                single.compute_step(&device, &queue);
+            }
+            let mut batched =
+                HeatEquation::with_options(&device, 0.2, N, 0.01, &u0, &texture, options.clone());
+            batched.compute_steps(&device, &queue, STEPS);
+            assert_eq!(batched.iteration(), STEPS);
+            // the same kernels run in the same order, in fewer submissions
+            assert_eq!(
+                batched.read_values(&device, &queue)?,
+                single.read_values(&device, &queue)?,
+                "{options:?}"
+            );
+        }
+        Ok(())
+    }
+
+    async fn profile_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::profiler::{ProfileReport, Profiler, STEP};
+
+        const N: usize = 24;
+        const STEPS: usize = 3;
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .ok_or(ERR_DID_NOT_FIND_ADAPTER)?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: Profiler::features(adapter.features()),
+                    limits: wgpu::Limits::downlevel_defaults(),
+                },
+                None,
+            )
+            .await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        let report = ProfileReport {
+            summary: true,
+            trace: Some("trace.json".into()),
+        };
+        let mut values = Vec::new();
+        for profile in [false, true] {
+            let mut gpu = HeatEquation::new(&device, 0.2, N, 0.01, &u0, &texture);
+            gpu.profile(&device, &queue, profile.then_some(&report));
+            assert_eq!(gpu.profiler().is_some(), profile);
+            gpu.compute_steps(&device, &queue, STEPS);
+            values.push(gpu.read_values(&device, &queue)?);
+            let Some(profiler) = gpu.profiler() else {
+                continue;
+            };
+            // profiled steps are submitted one at a time
+            let steps: Vec<_> = profiler
+                .spans()
+                .iter()
+                .filter(|s| s.name == STEP)
+                .map(|s| s.step)
+                .collect();
+            assert_eq!(steps, [1, 2, 3]);
+            assert!(profiler.spans().iter().all(|s| s.duration >= 0.0));
+            let summary = profiler.summary();
+            assert_eq!(summary[0].name, STEP);
+            assert_eq!(summary[0].count, STEPS);
+            if profiler.per_kernel() {
+                for name in ["spmv", "dot", "saxpy", "write to texture"] {
+                    assert!(summary.iter().any(|s| s.name == name), "{name}");
+                }
+            } else {
+                assert_eq!(summary.len(), 1);
+            }
+            let table = profiler.to_string();
+            assert!(table.starts_with("kernel"), "{table}");
+            assert_eq!(table.contains("only whole steps"), !profiler.per_kernel());
+        }
+        // timing the kernels in passes of their own does not change them
+        assert_eq!(values[0], values[1]);
+        Ok(())
+    }
+
+    async fn mask_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            cpu::{conjugate_gradient::CG, kernels::spmv},
+            dia_matrix::DIAMatrix,
+            diagnostics::Diagnostics,
+            heat_equation::SolverOptions,
+            mask::{Cell, MaskSource},
+            precision::Precision,
+        };
+
+        const N: usize = 16;
+        const ALPHA: f32 = 1e-3;
+        const DT: f32 = 0.01;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        // an L-shaped domain with an insulated wall across its lower half
+        let mut mask = MaskSource::LShape.mask(N)?;
+        for y in 12..N {
+            mask.cells[y * N + 6] = Cell::Insulated;
+        }
+        let options = SolverOptions {
+            precision: Precision::DoubleFloat,
+            mask: Some(mask.clone()),
+            ..SolverOptions::default()
+        };
+        let mut gpu = HeatEquation::with_options(&device, ALPHA, N, DT, &u0, &texture, options);
+        assert_eq!(gpu.mask(), Some(&mask));
+
+        let [a, b] =
+            HeatEquation::masked_matrices(ALPHA, N, DT, &mask).map(|m| DIAMatrix::from(&m));
+        let mut cpu: Vec<f64> = u0.iter().map(|&v| v as f64).collect();
+        for (value, cell) in cpu.iter_mut().zip(&mask.cells) {
+            if *cell != Cell::Active {
+                *value = 0.0;
+            }
+        }
+        let mut cg = CG::new(N * N);
+        let mut tmp = vec![0.0; N * N];
+        for _ in 0..5 {
+            gpu.compute_step(&device, &queue);
+            spmv(&b, &cpu, &mut tmp);
+            cg.run(&a, &tmp, &mut cpu);
+        }
+        let values = gpu.read_values(&device, &queue)?;
+        for (i, cell) in mask.cells.iter().enumerate() {
+            if *cell != Cell::Active {
+                assert_eq!(values[i], 0.0, "wall at {i}");
+            }
+        }
+        let max_error = values
+            .iter()
+            .zip(&cpu)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert!(max_error < 1e-10, "{max_error:e}");
+
+        // the diagnostics only cover the domain
+        let field = gpu.read_field(&device, &queue)?;
+        let expected = Diagnostics::from_field(5, 5.0 * DT as f64, &field, Some(&mask));
+        let diagnostics = gpu.diagnostics(&device, &queue);
+        for (g, c) in [
+            (diagnostics.total_heat, expected.total_heat),
+            (diagnostics.min, expected.min),
+            (diagnostics.max, expected.max),
+            (diagnostics.mean, expected.mean),
+        ] {
+            assert!(
+                (g - c).abs() <= 1e-5 * c.abs().max(1.0),
+                "{diagnostics:?} != {expected:?}"
+            );
+        }
+        assert!(expected.min > 0.0, "{expected:?}");
+
+        // fields written from the host keep the walls at zero
+        let walls = |values: Vec<f64>| {
+            mask.cells
+                .iter()
+                .zip(values)
+                .filter(|(cell, _)| **cell != Cell::Active)
+                .all(|(_, value)| value == 0.0)
+        };
+        gpu.set_field(&device, &queue, &vec![1.0; N * N]);
+        assert!(walls(gpu.read_values(&device, &queue)?));
+        gpu.set_region(&device, &queue, (4, 2), 8, &[1.0; 8 * 12]);
+        assert!(walls(gpu.read_values(&device, &queue)?));
+
+        // checkpoints only resume into a solver with the same mask
+        let checkpoint = gpu.checkpoint(&device, &queue)?;
+        let mut restored = HeatEquation::with_options(
+            &device,
+            ALPHA,
+            N,
+            DT,
+            &u0,
+            &texture,
+            SolverOptions {
+                precision: Precision::DoubleFloat,
+                mask: Some(mask.clone()),
+                ..SolverOptions::default()
+            },
+        );
+        restored.restore(&device, &queue, &checkpoint)?;
+        let unmasked = SolverOptions {
+            precision: Precision::DoubleFloat,
+            ..SolverOptions::default()
+        };
+        let mut unmasked =
+            HeatEquation::with_options(&device, ALPHA, N, DT, &u0, &texture, unmasked);
+        assert!(unmasked.restore(&device, &queue, &checkpoint).is_err());
+        let unmasked_checkpoint = unmasked.checkpoint(&device, &queue)?;
+        assert!(restored
+            .restore(&device, &queue, &unmasked_checkpoint)
+            .is_err());
+        Ok(())
+    }
+
+    async fn materials_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            cpu::{conjugate_gradient::CG, kernels::spmv},
+            dia_matrix::DIAMatrix,
+            heat_equation::SolverOptions,
+            mask::MaskSource,
+            material::{Material, MaterialMap},
+            precision::Precision,
+        };
+
+        const N: usize = 16;
+        const DT: f32 = 0.01;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N)
+            .map(|i| ((i * 37) % 101) as f32 / 101.0)
+            .collect();
+        let material = |density, conductivity| Material {
+            name: String::new(),
+            density,
+            specific_heat: 1.0,
+            conductivity,
+            color: None,
+        };
+        // a slow, heavy disk inside a fast, light plate with a hole
+        let indices = (0..N * N)
+            .map(|i| {
+                let (x, y) = ((i % N) as f64 / N as f64, (i / N) as f64 / N as f64);
+                usize::from((x - 0.6).hypot(y - 0.4) < 0.25)
+            })
+            .collect();
+        let materials = MaterialMap::new(
+            N,
+            N,
+            vec![material(1.0, 2e-3), material(4.0, 1e-4)],
+            indices,
+        );
+        let mask = "hole:x=0.2,y=0.7,radius=0.1"
+            .parse::<MaskSource>()?
+            .mask(N)?;
+        let options = SolverOptions {
+            precision: Precision::DoubleFloat,
+            mask: Some(mask.clone()),
+            materials: Some(materials.clone()),
+            ..SolverOptions::default()
+        };
+        let mut gpu = HeatEquation::with_options(&device, 1.0, N, DT, &u0, &texture, options);
+
+        let [a, b] = HeatEquation::material_matrices(N, DT, &materials, Some(&mask))
+            .map(|m| DIAMatrix::from(&m));
+        let mut masked = u0.clone();
+        mask.apply(&mut masked);
+        let mut cpu: Vec<f64> = masked.iter().map(|&v| v as f64).collect();
+        let mut cg = CG::new(N * N);
+        let mut tmp = vec![0.0; N * N];
+        for _ in 0..5 {
+            gpu.compute_step(&device, &queue);
+            spmv(&b, &cpu, &mut tmp);
+            cg.run(&a, &tmp, &mut cpu);
+        }
+        let values = gpu.read_values(&device, &queue)?;
+        let max_error = values
+            .iter()
+            .zip(&cpu)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert!(max_error < 1e-10, "{max_error:e}");
+
+        // checkpoints only resume into a solver with the same materials
+        let checkpoint = gpu.checkpoint(&device, &queue)?;
+        let other = MaterialMap::new(
+            N,
+            N,
+            vec![material(1.0, 2e-3), material(4.0, 2e-4)],
+            materials.indices.clone(),
+        );
+        let options = |materials| SolverOptions {
+            precision: Precision::DoubleFloat,
+            mask: Some(mask.clone()),
+            materials: Some(materials),
+            ..SolverOptions::default()
+        };
+        let mut restored =
+            HeatEquation::with_options(&device, 1.0, N, DT, &u0, &texture, options(materials));
+        restored.restore(&device, &queue, &checkpoint)?;
+        let mut other =
+            HeatEquation::with_options(&device, 1.0, N, DT, &u0, &texture, options(other));
+        assert!(other.restore(&device, &queue, &checkpoint).is_err());
+        Ok(())
+    }
+
+    async fn diagnostics_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::diagnostics::Diagnostics;
+
+        const N: usize = 37;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N).map(|i| (i % 11) as f32).collect();
+        let mut heat_eqn = HeatEquation::new(&device, 1e-3, N, 0.01, &u0, &texture);
+        assert!(heat_eqn.last_diagnostics().is_none());
+        heat_eqn.track_diagnostics(&device, &queue, true);
+        let mut history = vec![*heat_eqn.last_diagnostics().unwrap()];
+        for _ in 0..4 {
+            heat_eqn.compute_step(&device, &queue);
+            history.push(*heat_eqn.last_diagnostics().unwrap());
+        }
+
+        assert_eq!(history[0].iteration, 0);
+        assert_eq!(history[4].iteration, 4);
+        // heat leaves through the zero Dirichlet edges
+        for pair in history.windows(2) {
+            assert!(pair[1].total_heat < pair[0].total_heat, "{pair:?}");
+            assert!(pair[1].l2 < pair[0].l2, "{pair:?}");
+        }
+        let field = heat_eqn.read_field(&device, &queue)?;
+        let expected = Diagnostics::from_field(4, history[4].time, &field, None);
+        let gpu = history[4];
+        for (g, c) in [
+            (gpu.total_heat, expected.total_heat),
+            (gpu.min, expected.min),
+            (gpu.max, expected.max),
+            (gpu.mean, expected.mean),
+            (gpu.l2, expected.l2),
+        ] {
+            assert!(
+                (g - c).abs() <= 1e-5 * c.abs().max(1.0),
+                "{gpu:?} != {expected:?}"
+            );
+        }
+        Ok(())
+    }
+
+    async fn probes_inner() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::probes::{ProbeSampler, Probes};
+
+        const N: usize = 37;
+        let (device, queue) = request_device().await?;
+        let texture = create_texture(&device, N as u32);
+        let u0: Vec<f32> = (0..N * N).map(|i| (i % 13) as f32).collect();
+        let mut heat_eqn = HeatEquation::new(&device, 1e-3, N, 0.01, &u0, &texture);
+        let probes = Probes {
+            points: vec![
+                "center=0.5,0.5".parse()?,
+                "corner=0,0".parse()?,
+                "near_edge=0.99,0.3".parse()?,
+            ],
+            lines: vec!["diagonal=0.1,0.2,0.9,0.7,17".parse()?],
+        };
+        let sampler = ProbeSampler::new(&device, &heat_eqn, &probes);
+        // both solution buffers are sampled
+        for _ in 0..3 {
+            let gpu = sampler.sample(&device, &queue, &heat_eqn)?;
+            let cpu = probes.sample_field(&heat_eqn.read_field(&device, &queue)?);
+            assert_eq!(gpu.len(), 20);
+            assert_eq!(gpu[1], 0.0);
+            for (g, c) in gpu.iter().zip(cpu.iter()) {
+                assert!(
+                    (g - c).abs() <= 1e-4 * c.abs().max(1.0),
+                    "{gpu:?} != {cpu:?}"
+                );
+            }
+            heat_eqn.compute_step(&device, &queue);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn probes() {
+        skip_without_adapter(pollster::block_on(probes_inner()));
+    }
+
+    #[test]
+    fn double_float() {
+        skip_without_adapter(pollster::block_on(double_float_inner()));
+    }
+
+    #[test]
+    fn iterative_refinement() {
+        skip_without_adapter(pollster::block_on(iterative_refinement_inner()));
+    }
+
+    #[test]
+    fn matrix_free() {
+        skip_without_adapter(pollster::block_on(matrix_free_inner()));
+    }
+
+    #[test]
+    fn cg_variants() {
+        skip_without_adapter(pollster::block_on(cg_variants_inner()));
+    }
+
+    #[test]
+    fn batched_steps() {
+        skip_without_adapter(pollster::block_on(batched_steps_inner()));
+    }
+
+    #[test]
+    fn profile() {
+        skip_without_adapter(pollster::block_on(profile_inner()));
+    }
+
+    #[test]
+    fn mask() {
+        skip_without_adapter(pollster::block_on(mask_inner()));
+    }
+
+    #[test]
+    fn materials() {
+        skip_without_adapter(pollster::block_on(materials_inner()));
+    }
+
+    #[test]
+    fn diagnostics() {
+        skip_without_adapter(pollster::block_on(diagnostics_inner()));
+    }
+
+    #[test]
+    fn eigenmode() {
+        skip_without_adapter(pollster::block_on(eigenmode_inner()));
+    }
+
+    #[test]
+    fn checkpoint() {
+        skip_without_adapter(pollster::block_on(checkpoint_inner()));
+    }
+
+    #[test]
+    fn read_field() {
+        skip_without_adapter(pollster::block_on(read_field_inner()));
+    }
+
+    #[test]
+    fn set_field() {
+        skip_without_adapter(pollster::block_on(set_field_inner()));
+    }
+}
@@ -1,3 +1,7 @@
+#[cfg(test)]
+mod common;
+mod cpu_reference;
+mod heat_equation;
 mod spmv;
 mod sum_reduce;
 mod vec_mul;
@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
     use wgpu::util::DeviceExt;
+
+    use crate::precision::Precision;
     const ERR_DID_NOT_FIND_ADAPTER: &str = "Failed to find an appropriate adapter";
 
     #[repr(C)]
@@ -39,12 +41,6 @@ mod tests {
             .await
             .unwrap();
 
-        let info = adapter.get_info();
-        // skip this on LavaPipe temporarily
-        if info.vendor == 0x10005 {
-            return Err("LavaPipe not supported".into());
-        }
-
         execute_gpu_inner(&device, &queue, x, params, data, offsets).await
     }
 
@@ -59,7 +55,14 @@ mod tests {
         // Loads the shader from WGSL
         let cs_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/spmv.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                Precision::Single
+                    .shader(&crate::kernels::spmv_dots::source(
+                        include_str!("../shaders/spmv.wgsl"),
+                        None,
+                    ))
+                    .into(),
+            ),
         });
 
         // Gets the size in bytes of the buffer.
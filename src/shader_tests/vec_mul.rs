@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
     use wgpu::util::DeviceExt;
+
+    use crate::precision::Precision;
     const ERR_DID_NOT_FIND_ADAPTER: &str = "Failed to find an appropriate adapter";
 
     async fn execute_gpu(
@@ -30,12 +32,6 @@ mod tests {
             .await
             .unwrap();
 
-        let info = adapter.get_info();
-        // skip this on LavaPipe temporarily
-        if info.vendor == 0x10005 {
-            return Err("LavaPipe not supported".into());
-        }
-
         execute_gpu_inner(&device, &queue, vec_a, vec_b).await
     }
 
@@ -48,7 +44,11 @@ mod tests {
         // Loads the shader from WGSL
         let cs_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/vec_mul.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                Precision::Single
+                    .shader(include_str!("../shaders/vec_mul.wgsl"))
+                    .into(),
+            ),
         });
 
         // Gets the size in bytes of the buffer.
@@ -0,0 +1,57 @@
+@group(0) @binding(0) var<storage, read_write> x: array<real>;
+@group(0) @binding(1) var<storage, read_write> r: array<real>;
+@group(0) @binding(2) var<storage, read_write> p: array<real>;
+@group(0) @binding(3) var<storage, read_write> s: array<real>;
+@group(0) @binding(4) var<storage, read> w: array<real>;
+// two slots of [delta, gamma, alpha]: delta = r . w and gamma = r . r of the
+// current residual in the current slot, and gamma and alpha of the previous
+// iteration in the other one
+@group(0) @binding(5) var<storage, read_write> scalars: array<real>;
+
+const CURRENT: u32 = {CURRENT}u;
+const PREVIOUS: u32 = {PREVIOUS}u;
+
+// One iteration of the Chronopoulos-Gear conjugate gradient method, where
+// s = A * p is updated by recurrence from w = A * r:
+// p = r + beta * p, s = w + beta * s, x = x + alpha * p, r = r - alpha * s
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let row = global_id.x;
+    let len = arrayLength(&x);
+    real_init(min(len, 1u));
+    let delta = scalars[CURRENT];
+    let gamma = scalars[CURRENT + 1u];
+    let gamma_prev = scalars[PREVIOUS + 1u];
+    let alpha_prev = scalars[PREVIOUS + 2u];
+
+    // zero scalars of the previous iteration restart the directions, as in
+    // the first iteration, and a zero denominator means CG has converged
+    var beta = real_from_f32(0.0);
+    var denominator = delta;
+    if (!real_is_zero(gamma_prev) && !real_is_zero(alpha_prev)) {
+        beta = real_div(gamma, gamma_prev);
+        denominator = real_sub(delta, real_mul(beta, real_div(gamma, alpha_prev)));
+    }
+    var alpha = real_from_f32(0.0);
+    if (!real_is_zero(denominator)) {
+        alpha = real_div(gamma, denominator);
+    }
+    if (row == 0u) {
+        // not read by this iteration, but as alpha_prev by the next one
+        scalars[CURRENT + 2u] = alpha;
+    }
+    if (row >= len) {
+        return;
+    }
+
+    var p_new = r[row];
+    var s_new = w[row];
+    if (!real_is_zero(beta)) {
+        p_new = real_add(p_new, real_mul(beta, p[row]));
+        s_new = real_add(s_new, real_mul(beta, s[row]));
+    }
+    p[row] = p_new;
+    s[row] = s_new;
+    x[row] = real_add(x[row], real_mul(alpha, p_new));
+    r[row] = real_sub(r[row], real_mul(alpha, s_new));
+}
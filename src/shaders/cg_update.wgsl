@@ -0,0 +1,29 @@
+@group(0) @binding(0) var<storage, read_write> x: array<real>;
+@group(0) @binding(1) var<storage, read_write> r: array<real>;
+@group(0) @binding(2) var<storage, read> p: array<real>;
+@group(0) @binding(3) var<storage, read> q: array<real>;
+@group(0) @binding(4) var<storage, read> rho: real;
+@group(0) @binding(5) var<storage, read> pq: real;
+@group(0) @binding(6) var<storage, read_write> products: array<real>;
+
+// x = x + alpha * p and r = r - alpha * q with alpha = rho / (p . q), then
+// the products r .* r of the next rho
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let row = global_id.x;
+    let len = arrayLength(&x);
+    real_init(min(len, 1u));
+    if (row >= len) {
+        return;
+    }
+    // a zero denominator means the residual vanished: CG has converged
+    var alpha = real_from_f32(0.0);
+    if (!real_is_zero(pq)) {
+        alpha = real_div(rho, pq);
+    }
+
+    x[row] = real_add(x[row], real_mul(alpha, p[row]));
+    let r_new = real_sub(r[row], real_mul(alpha, q[row]));
+    r[row] = r_new;
+    products[row] = real_mul(r_new, r_new);
+}
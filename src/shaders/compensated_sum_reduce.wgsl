@@ -0,0 +1,39 @@
+@group(0) @binding(0) var<storage, read> input: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read_write> output: array<{OUTPUT_TYPE}>;
+
+var<workgroup> sdata: array<vec2<f32>, {WORKGROUP_SIZE}>;
+
+@compute @workgroup_size({WORKGROUP_SIZE})
+fn main(@builtin(local_invocation_id) local_id: vec3<u32>, @builtin(workgroup_id) group_id: vec3<u32>) {
+    // each workgroup sums 2 * WORKGROUP_SIZE consecutive double-float block
+    // results of the previous pass
+
+    let tid = local_id.x;
+    let len = arrayLength(&input);
+    let i = group_id.x * 2u * {WORKGROUP_SIZE}u + tid;
+    df_init(min(len, 1u));
+
+    var value = vec2<f32>(0.0, 0.0);
+    if (i < len) {
+        value = input[i];
+    }
+    if (i + {WORKGROUP_SIZE}u < len) {
+        value = df_add(value, input[i + {WORKGROUP_SIZE}u]);
+    }
+    sdata[tid] = value;
+
+    workgroupBarrier();
+
+    for (var s = {WORKGROUP_SIZE}u / 2u; s > 0u; s >>= 1u) {
+        if (tid < s) {
+            sdata[tid] = df_add(sdata[tid], sdata[tid + s]);
+        }
+
+        workgroupBarrier();
+    }
+
+    if (tid == 0u) {
+        let v = sdata[0];
+        output[group_id.x] = {OUTPUT_VALUE};
+    }
+}
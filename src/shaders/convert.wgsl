@@ -0,0 +1,15 @@
+@group(0) @binding(0) var<storage, read> input_vec: array<real>;
+@group(0) @binding(1) var<storage, read_write> output: array<f32>;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    let len = arrayLength(&output);
+    real_init(min(len, 1u));
+    if (index >= len) {
+        return;
+    }
+
+    // round the solver precision to single precision
+    output[index] = real_to_f32(input_vec[index]);
+}
@@ -0,0 +1,15 @@
+@group(0) @binding(0) var<storage, read> correction: array<f32>;
+@group(0) @binding(1) var<storage, read_write> output: array<real>;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    let len = arrayLength(&output);
+    real_init(min(len, 1u));
+    if (index >= len) {
+        return;
+    }
+
+    // add the single precision correction in the precision of the output
+    output[index] = real_add(output[index], real_from_f32(correction[index]));
+}
@@ -0,0 +1,68 @@
+// Error-free transformations of f32 arithmetic, and sums of double-float
+// numbers: unevaluated sums hi + lo of two f32, stored as vec2(hi, lo).
+
+// Shader compilers are free to reassociate floating point arithmetic, which
+// turns the rounding errors computed below into zeros. Multiplying every
+// intermediate result whose rounding matters by this value hides it from such
+// rewrites: it must be set to 1.0 at run time by `df_init`, from a value the
+// compiler cannot know, before any other function of this file is called.
+var<private> df_one: f32;
+
+// `one` must be 1 at run time, e.g. min(arrayLength(&x), 1u) for non-empty x
+fn df_init(one: u32) {
+    df_one = f32(one);
+}
+
+// s + e == a + b exactly
+fn two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = (a + b) * df_one;
+    let bb = (s - a) * df_one;
+    let da = (a - (s - bb) * df_one) * df_one;
+    let db = (b - bb) * df_one;
+    return vec2<f32>(s, da + db);
+}
+
+// s + e == a + b exactly, provided that |a| >= |b|
+fn fast_two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = (a + b) * df_one;
+    let e = b - (s - a) * df_one;
+    return vec2<f32>(s, e);
+}
+
+// hi + lo == a, with hi and lo holding 12 significant bits each
+fn split(a: f32) -> vec2<f32> {
+    let c = (4097.0 * a) * df_one;
+    let d = (c - a) * df_one;
+    let hi = (c - d) * df_one;
+    return vec2<f32>(hi, a - hi);
+}
+
+// p + e == a * b exactly. WGSL does not require fma to round only once, so
+// the error of the product comes from Dekker's splitting instead
+fn two_prod(a: f32, b: f32) -> vec2<f32> {
+    let p = (a * b) * df_one;
+    let a_split = split(a);
+    let b_split = split(b);
+    let e1 = (a_split.x * b_split.x - p) * df_one;
+    let e2 = (e1 + a_split.x * b_split.y) * df_one;
+    let e3 = (e2 + a_split.y * b_split.x) * df_one;
+    return vec2<f32>(p, e3 + a_split.y * b_split.y);
+}
+
+fn df_add(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let s = two_sum(a.x, b.x);
+    return fast_two_sum(s.x, s.y + (a.y + b.y));
+}
+
+fn df_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let p = two_prod(a.x, b.x);
+    return fast_two_sum(p.x, p.y + (a.x * b.y + a.y * b.x));
+}
+
+// one Newton correction of the f32 quotient
+fn df_div(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let q1 = a.x / b.x;
+    let r = df_add(a, -df_mul(b, vec2<f32>(q1, 0.0)));
+    let q2 = r.x / b.x;
+    return fast_two_sum(q1, q2);
+}
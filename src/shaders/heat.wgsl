@@ -14,6 +14,9 @@ const k: f32 = 0.05;
 @compute @workgroup_size(16, 16)
 fn heat_main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let dimensions: vec2<u32> = textureDimensions(input_texture);
+    if (global_id.x >= dimensions.x || global_id.y >= dimensions.y) {
+        return;
+    }
     let coords = vec2<i32>(global_id.xy);
 
     var sum: vec4<f32> = vec4<f32>(0.0);
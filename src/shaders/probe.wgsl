@@ -0,0 +1,32 @@
+@group(0) @binding(0) var<storage, read> field: array<f32>;
+// sample positions in grid units: grid point (i, j) sits at (i, j)
+@group(0) @binding(1) var<storage, read> points: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> output: array<f32>;
+
+const N: i32 = {N};
+
+fn value(i: i32, j: i32) -> f32 {
+    // the points just outside the grid hold the zero boundary value
+    if (i < 0 || j < 0 || i >= N || j >= N) {
+        return 0.0;
+    }
+    return field[j * N + i];
+}
+
+@compute @workgroup_size({WORKGROUP_SIZE})
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let k = global_id.x;
+    if (k >= arrayLength(&points)) {
+        return;
+    }
+
+    // bilinear interpolation between the four surrounding grid points
+    let p = points[k];
+    let corner = floor(p);
+    let t = p - corner;
+    let i = i32(corner.x);
+    let j = i32(corner.y);
+    let top = mix(value(i, j), value(i + 1, j), t.x);
+    let bottom = mix(value(i, j + 1), value(i + 1, j + 1), t.x);
+    output[k] = mix(top, bottom, t.y);
+}
@@ -0,0 +1,48 @@
+// Arithmetic of the solver vectors in emulated double precision, see
+// precision.rs: each value is a double-float number from double_float.wgsl.
+
+alias real = vec2<f32>;
+
+fn real_init(one: u32) {
+    df_init(one);
+}
+
+fn real_from_f32(v: f32) -> real {
+    return vec2<f32>(v, 0.0);
+}
+
+fn real_to_f32(v: real) -> f32 {
+    return v.x + v.y;
+}
+
+fn real_add(a: real, b: real) -> real {
+    return df_add(a, b);
+}
+
+fn real_sub(a: real, b: real) -> real {
+    return df_add(a, -b);
+}
+
+fn real_mul(a: real, b: real) -> real {
+    return df_mul(a, b);
+}
+
+fn real_div(a: real, b: real) -> real {
+    return df_div(a, b);
+}
+
+fn real_less(a: real, b: real) -> bool {
+    return a.x < b.x || (a.x == b.x && a.y < b.y);
+}
+
+fn real_min(a: real, b: real) -> real {
+    return select(b, a, real_less(a, b));
+}
+
+fn real_max(a: real, b: real) -> real {
+    return select(a, b, real_less(a, b));
+}
+
+fn real_is_zero(a: real) -> bool {
+    return a.x == 0.0;
+}
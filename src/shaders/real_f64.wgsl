@@ -0,0 +1,41 @@
+// Arithmetic of the solver vectors in native double precision, see precision.rs.
+
+alias real = f64;
+
+fn real_init(one: u32) {}
+
+fn real_from_f32(v: f32) -> real {
+    return real(v);
+}
+
+fn real_to_f32(v: real) -> f32 {
+    return f32(v);
+}
+
+fn real_add(a: real, b: real) -> real {
+    return a + b;
+}
+
+fn real_sub(a: real, b: real) -> real {
+    return a - b;
+}
+
+fn real_mul(a: real, b: real) -> real {
+    return a * b;
+}
+
+fn real_div(a: real, b: real) -> real {
+    return a / b;
+}
+
+fn real_min(a: real, b: real) -> real {
+    return min(a, b);
+}
+
+fn real_max(a: real, b: real) -> real {
+    return max(a, b);
+}
+
+fn real_is_zero(a: real) -> bool {
+    return a == real(0.0);
+}
@@ -26,10 +26,20 @@ fn vs_main(
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
+// 0 for active cells, 0.5 for insulated walls and 1 for walls held at zero
+@group(0) @binding(2)
+var t_mask: texture_2d<f32>;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let value = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let label = textureSample(t_mask, s_diffuse, in.tex_coords).r;
+    if label > 0.75 {
+        return vec4<f32>(vec3<f32>(0.2), 1.0);
+    }
+    if label > 0.25 {
+        return vec4<f32>(vec3<f32>(0.6), 1.0);
+    }
     let color = vec4<f32>(TurboColormap(value.r), 1.0);
     return color;
 }
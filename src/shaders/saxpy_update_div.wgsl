@@ -1,12 +1,26 @@
-@group(0) @binding(0) var<storage, read_write> input_vec_a: array<f32>;
-@group(0) @binding(1) var<storage, read> input_vec_b: array<f32>;
-@group(0) @binding(2) var<storage, read> alpha1: f32;
-@group(0) @binding(3) var<storage, read> alpha2: f32;
+@group(0) @binding(0) var<storage, read_write> input_vec_a: array<real>;
+@group(0) @binding(1) var<storage, read> input_vec_b: array<real>;
+@group(0) @binding(2) var<storage, read> alpha1: real;
+@group(0) @binding(3) var<storage, read> alpha2: real;
 
 @compute @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
+    let len = arrayLength(&input_vec_a);
+    real_init(min(len, 1u));
+    if (index >= len) {
+        return;
+    }
+    let a = input_vec_a[index];
+    let b = input_vec_b[index];
+    // a zero denominator means the residual vanished: CG has converged
+    // and the update must be skipped instead of producing NaNs
+    var alpha = real_from_f32(0.0);
+    if (!real_is_zero(alpha2)) {
+        alpha = real_div(alpha1, alpha2);
+    }
 
-    // perform update a = a {OP} alpha * b, where OP can be + or -
-    input_vec_a[index] = input_vec_a[index] {OP} (alpha1 / alpha2) * input_vec_b[index];
-}
\ No newline at end of file
+    // perform update a = a OP alpha * b, where OP can be + or -,
+    // or a = b + alpha * a
+    input_vec_a[index] = {UPDATE};
+}
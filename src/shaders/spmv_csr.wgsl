@@ -0,0 +1,27 @@
+@group(0) @binding(0) var<storage, read> input_vec: array<real>;
+@group(0) @binding(1) var<uniform> params: CSRMatrixParams;
+// the row offsets, followed by the column indices
+@group(0) @binding(2) var<storage, read> indices: array<u32>;
+@group(0) @binding(3) var<storage, read> data: array<real>;
+@group(0) @binding(4) var<storage, read_write> output_vec: array<real>;
+
+// Compressed sparse row representation of a matrix A
+struct CSRMatrixParams {
+    num_cols: u32,
+    num_rows: u32,
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    var row = global_id.x;
+    real_init(min(params.num_rows, 1u));
+    if (row < params.num_rows) {
+        var dot = real_from_f32(0.0);
+        let col_indices = params.num_rows + 1u;
+        for (var k = indices[row]; k < indices[row + 1u]; k++) {
+            dot = real_add(dot, real_mul(data[k], input_vec[indices[col_indices + k]]));
+        }
+        output_vec[row] = dot;
+        spmv_dots(row, params.num_rows, dot);
+    }
+}
@@ -0,0 +1,11 @@
+// Products input_vec .* y and input_vec .* input_vec of a sparse
+// matrix-vector product y = A * input_vec over len rows, stored one after the
+// other in products, for a reduction to sum.
+
+@group(0) @binding({PRODUCTS_BINDING}) var<storage, read_write> products: array<real>;
+
+fn spmv_dots(row: u32, len: u32, y: real) {
+    let x = input_vec[row];
+    products[row] = real_mul(x, y);
+    products[len + row] = real_mul(x, x);
+}
@@ -0,0 +1,31 @@
+@group(0) @binding(0) var<storage, read> input_vec: array<real>;
+@group(0) @binding(1) var<uniform> params: ELLMatrixParams;
+@group(0) @binding(2) var<storage, read> col_indices: array<i32>;
+@group(0) @binding(3) var<storage, read> data: array<real>;
+@group(0) @binding(4) var<storage, read_write> output_vec: array<real>;
+
+// ELLPACK representation of a matrix A
+struct ELLMatrixParams {
+    num_cols: u32,
+    num_rows: u32,
+    width: u32,
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    var row = global_id.x;
+    real_init(min(params.num_rows, 1u));
+    if (row < params.num_rows) {
+        var dot = real_from_f32(0.0);
+        for (var k = 0u; k < params.width; k++) {
+            let col = col_indices[params.num_rows * k + row];
+            // padding entries have column -1
+            if (col >= 0) {
+                let val = data[params.num_rows * k + row];
+                dot = real_add(dot, real_mul(val, input_vec[u32(col)]));
+            }
+        }
+        output_vec[row] = dot;
+        spmv_dots(row, params.num_rows, dot);
+    }
+}
@@ -0,0 +1,63 @@
+@group(0) @binding(0) var<storage, read> input_vec: array<real>;
+@group(0) @binding(1) var<uniform> params: StencilParams;
+@group(0) @binding(2) var<storage, read_write> output_vec: array<real>;
+
+// 3 x 3 stencil over a width x height grid in row-major order, with zero
+// values outside the grid
+struct StencilParams {
+    width: u32,
+    height: u32,
+    south_west: real,
+    south: real,
+    south_east: real,
+    west: real,
+    center: real,
+    east: real,
+    north_west: real,
+    north: real,
+    north_east: real,
+}
+
+// whether the corner weights are used
+const NINE_POINT: bool = {NINE_POINT};
+
+fn value(x: i32, y: i32) -> real {
+    if (x < 0 || y < 0 || x >= i32(params.width) || y >= i32(params.height)) {
+        return real_from_f32(0.0);
+    }
+    return input_vec[u32(y) * params.width + u32(x)];
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let row = global_id.x;
+    let len = params.width * params.height;
+    real_init(min(len, 1u));
+    if (row >= len) {
+        return;
+    }
+
+    let x = i32(row % params.width);
+    let y = i32(row / params.width);
+    // same order as the diagonals of the equivalent DIA matrix
+    var dot = real_from_f32(0.0);
+    if (NINE_POINT) {
+        dot = real_add(dot, real_mul(params.south_west, value(x - 1, y - 1)));
+    }
+    dot = real_add(dot, real_mul(params.south, value(x, y - 1)));
+    if (NINE_POINT) {
+        dot = real_add(dot, real_mul(params.south_east, value(x + 1, y - 1)));
+    }
+    dot = real_add(dot, real_mul(params.west, value(x - 1, y)));
+    dot = real_add(dot, real_mul(params.center, value(x, y)));
+    dot = real_add(dot, real_mul(params.east, value(x + 1, y)));
+    if (NINE_POINT) {
+        dot = real_add(dot, real_mul(params.north_west, value(x - 1, y + 1)));
+    }
+    dot = real_add(dot, real_mul(params.north, value(x, y + 1)));
+    if (NINE_POINT) {
+        dot = real_add(dot, real_mul(params.north_east, value(x + 1, y + 1)));
+    }
+    output_vec[row] = dot;
+    spmv_dots(row, len, dot);
+}
@@ -1,38 +1,67 @@
-@group(0) @binding(0) var<storage, read> input: array<f32>;
-@group(0) @binding(1) var<storage, read_write> output: array<f32>;
+@group(0) @binding(0) var<storage, read> input: array<real>;
+@group(0) @binding(1) var<storage, read_write> output: array<real>;
+{MASK_BINDING}
 
-var<workgroup> sdata: array<f32, {WORKGROUP_SIZE}>;
+var<workgroup> sdata: array<real, {WORKGROUP_SIZE}>;
+
+// identity element and operation of the reduction
+fn identity() -> real {
+    return real_from_f32({IDENTITY});
+}
+
+fn combine(a: real, b: real) -> real {
+    return {COMBINE};
+}
+
+// applied to every input value before it is combined, `i` being its index in
+// its segment
+fn load(v: real, i: u32) -> real {
+    {MASK}
+    return {LOAD};
+}
 
 @compute @workgroup_size({WORKGROUP_SIZE})
-fn main(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>, @builtin(workgroup_id) group_id: vec3<u32>) {
-    // does a parallel sum reduction of the input array
-    // and stores the block results in the output array
+fn main(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) group_id: vec3<u32>,
+    @builtin(num_workgroups) num_groups: vec3<u32>,
+) {
+    // the input holds consecutive segments of the same length, one per row of
+    // workgroups: each workgroup reduces blocks of 2 * WORKGROUP_SIZE
+    // consecutive values of its segment, striding over the row when there
+    // are more blocks than workgroups, and stores its result in the output
+    // array, from index OUTPUT_OFFSET, with the results of each segment in turn
 
     let tid = local_id.x;
-    let i = global_id.x + group_id.x * {WORKGROUP_SIZE}u;
+    let len = arrayLength(&input) / num_groups.y;
+    let segment = group_id.y * len;
+    let block = 2u * {WORKGROUP_SIZE}u;
+    real_init(min(len, 1u));
 
-    sdata[tid] = input[i] + input[i + {WORKGROUP_SIZE}u];
+    var value = identity();
+    for (var start = group_id.x * block; start < len; start += num_groups.x * block) {
+        let i = start + tid;
+        if (i < len) {
+            value = combine(value, load(input[segment + i], i));
+        }
+        let j = i + {WORKGROUP_SIZE}u;
+        if (j < len) {
+            value = combine(value, load(input[segment + j], j));
+        }
+    }
+    sdata[tid] = value;
 
     workgroupBarrier();
 
-    for (var s = {WORKGROUP_SIZE}u / 2u; s > 32u; s >>= 1u) {
+    for (var s = {WORKGROUP_SIZE}u / 2u; s > 0u; s >>= 1u) {
         if (tid < s) {
-            sdata[tid] += sdata[tid + s];
+            sdata[tid] = combine(sdata[tid], sdata[tid + s]);
         }
 
         workgroupBarrier();
     }
 
-    if (tid < 32u) {
-        sdata[tid] += sdata[tid + 32u];
-        sdata[tid] += sdata[tid + 16u];
-        sdata[tid] += sdata[tid + 8u];
-        sdata[tid] += sdata[tid + 4u];
-        sdata[tid] += sdata[tid + 2u];
-        sdata[tid] += sdata[tid + 1u];
-    }
-
     if (tid == 0u) {
-        output[group_id.x] = sdata[0];
+        output[{OUTPUT_OFFSET}u + group_id.y * num_groups.x + group_id.x] = sdata[0];
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,48 @@
+use std::io;
+
+use crate::field::Field;
+
+/// Common interface of the heat equation solvers.
+///
+/// Both the GPU solver (`heat_equation::HeatEquation`) and the CPU reference
+/// (`cpu::heat_equation::HeatEquation`) implement it, so that the same scenario
+/// can be run on either backend. `Context` is whatever the backend needs in
+/// order to do work: a device and queue for the GPU, nothing for the CPU.
+pub trait Solver {
+    type Context<'a>: Copy;
+
+    /// Advances the solution by a single time step.
+    fn compute_step(&mut self, ctx: Self::Context<'_>);
+
+    /// Advances the solution by `steps` time steps.
+    fn compute_steps(&mut self, ctx: Self::Context<'_>, steps: usize) {
+        for _ in 0..steps {
+            self.compute_step(ctx);
+        }
+    }
+
+    /// Number of time steps computed so far.
+    fn iteration(&self) -> usize;
+
+    /// Simulation time of the current solution.
+    fn time(&self) -> f32;
+
+    /// Number of grid points along each axis.
+    fn n(&self) -> usize;
+
+    /// Copies the current solution to the host.
+    fn read_field(&self, ctx: Self::Context<'_>) -> io::Result<Field>;
+
+    /// Replaces the current solution with `data`, in row-major order.
+    fn set_field(&mut self, ctx: Self::Context<'_>, data: &[f32]);
+
+    /// Replaces the rectangular region of the solution starting at grid point
+    /// `origin`, with `width` values per row of `data`.
+    fn set_region(
+        &mut self,
+        ctx: Self::Context<'_>,
+        origin: (usize, usize),
+        width: usize,
+        data: &[f32],
+    );
+}
@@ -0,0 +1,46 @@
+//! Sparse matrices uploaded to the GPU, in any storage format.
+//!
+//! [`DIAMatrixDescriptor`](crate::dia_matrix::DIAMatrixDescriptor) suits the
+//! banded matrices of regular grids, [`CSRMatrixDescriptor`] any sparsity
+//! pattern, and [`ELLMatrixDescriptor`] patterns with about the same number of
+//! entries in every row, such as grids with masked points. The host-side
+//! matrices convert into each other with `From`.
+//!
+//! [`CSRMatrixDescriptor`]: crate::csr_matrix::CSRMatrixDescriptor
+//! [`ELLMatrixDescriptor`]: crate::ell_matrix::ELLMatrixDescriptor
+use wgpu::util::DeviceExt;
+
+use crate::{kernels::kernel::Kernel, precision::Precision};
+
+/// Sparse matrix on the GPU, which the solvers only use through its SpMV
+/// kernel.
+pub trait SparseMatrix {
+    fn num_cols(&self) -> u32;
+    fn num_rows(&self) -> u32;
+    /// Precision of the values, and of the vectors the matrix multiplies.
+    fn precision(&self) -> Precision;
+    /// Kernel computing y = A * x.
+    fn spmv(&self, device: &wgpu::Device, x: &wgpu::Buffer, y: &wgpu::Buffer) -> Box<dyn Kernel>;
+    /// Kernel computing y = A * x along with the products `x .* y` and
+    /// `x .* x` in `products`, which holds
+    /// [`products_len`](crate::kernels::spmv_dots::products_len) values.
+    fn spmv_dots(
+        &self,
+        device: &wgpu::Device,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+        products: &wgpu::Buffer,
+    ) -> Box<dyn Kernel>;
+}
+
+/// Creates a storage buffer holding `contents`, padded with zeros to at least
+/// one value of any precision, since bindings cannot be empty.
+pub(crate) fn storage_buffer(device: &wgpu::Device, label: &str, contents: &[u8]) -> wgpu::Buffer {
+    let mut contents = contents.to_vec();
+    contents.resize(contents.len().max(8), 0);
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: &contents,
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
@@ -0,0 +1,159 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    coo_matrix::COOMatrix,
+    kernels::{kernel::Kernel, stencil::StencilKernel},
+    precision::Precision,
+    sparse_matrix::SparseMatrix,
+};
+
+/// Weights of a 3 x 3 stencil with constant coefficients.
+///
+/// `weights[dy + 1][dx + 1]` multiplies the value of the grid point at
+/// `(x + dx, y + dy)`, rows running in the direction of increasing `y`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stencil {
+    pub weights: [[f64; 3]; 3],
+}
+
+impl Stencil {
+    /// 5-point stencil with the same weight for the four neighbours.
+    pub fn five_point(center: f64, neighbour: f64) -> Self {
+        Self {
+            weights: [
+                [0.0, neighbour, 0.0],
+                [neighbour, center, neighbour],
+                [0.0, neighbour, 0.0],
+            ],
+        }
+    }
+
+    /// 9-point stencil with one weight for the four edge neighbours and one
+    /// for the four corner neighbours.
+    pub fn nine_point(center: f64, edge: f64, corner: f64) -> Self {
+        Self {
+            weights: [
+                [corner, edge, corner],
+                [edge, center, edge],
+                [corner, edge, corner],
+            ],
+        }
+    }
+
+    /// Whether any corner weight is used.
+    pub fn is_nine_point(&self) -> bool {
+        let [[sw, _, se], _, [nw, _, ne]] = self.weights;
+        [sw, se, nw, ne].iter().any(|&w| w != 0.0)
+    }
+
+    /// The stencil applied to a `width x height` grid in row-major order, with
+    /// zero values outside the grid, as an assembled matrix.
+    pub fn to_matrix(&self, width: usize, height: usize) -> COOMatrix {
+        let m = width * height;
+        let mut a = COOMatrix::new(m, m);
+        for y in 0..height {
+            for x in 0..width {
+                for (dy, row) in self.weights.iter().enumerate() {
+                    for (dx, &weight) in row.iter().enumerate() {
+                        let (nx, ny) = ((x + dx).checked_sub(1), (y + dy).checked_sub(1));
+                        if let (Some(nx), Some(ny)) = (nx, ny) {
+                            if nx < width && ny < height && weight != 0.0 {
+                                a.push(y * width + x, ny * width + nx, weight);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        a
+    }
+}
+
+/// Matrix-free sparse matrix: a [`Stencil`] applied to a grid, whose
+/// weights are the only data on the GPU.
+///
+/// Multiplying by it reads the vector alone, instead of the vector and the
+/// `num_diags` diagonals of a [`DIAMatrixDescriptor`](crate::dia_matrix::DIAMatrixDescriptor).
+pub struct StencilOperator {
+    pub width: u32,
+    pub height: u32,
+    pub stencil: Stencil,
+    pub precision: Precision,
+    pub params: wgpu::Buffer, // width, height and the weights
+}
+
+impl StencilOperator {
+    /// Uploads the weights of `stencil`, rounded to `precision`, for a
+    /// `width x height` grid.
+    pub fn new(
+        device: &wgpu::Device,
+        stencil: Stencil,
+        width: u32,
+        height: u32,
+        precision: Precision,
+    ) -> Self {
+        let mut contents = bytemuck::cast_slice(&[width, height]).to_vec();
+        contents.extend(precision.encode(stencil.weights.iter().flatten().copied()));
+        // uniform buffers are bound in multiples of 16 bytes
+        contents.resize(contents.len().next_multiple_of(16), 0);
+        Self {
+            width,
+            height,
+            stencil,
+            precision,
+            params: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Stencil Params Buffer"),
+                contents: &contents,
+                usage: wgpu::BufferUsages::UNIFORM,
+            }),
+        }
+    }
+}
+
+impl SparseMatrix for StencilOperator {
+    fn num_cols(&self) -> u32 {
+        self.width * self.height
+    }
+
+    fn num_rows(&self) -> u32 {
+        self.width * self.height
+    }
+
+    fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    fn spmv(&self, device: &wgpu::Device, x: &wgpu::Buffer, y: &wgpu::Buffer) -> Box<dyn Kernel> {
+        Box::new(StencilKernel::new(device, self, x, y))
+    }
+
+    fn spmv_dots(
+        &self,
+        device: &wgpu::Device,
+        x: &wgpu::Buffer,
+        y: &wgpu::Buffer,
+        products: &wgpu::Buffer,
+    ) -> Box<dyn Kernel> {
+        Box::new(StencilKernel::with_dots(device, self, x, y, Some(products)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_matrix() {
+        let stencil = Stencil::nine_point(4.0, -1.0, -0.5);
+        assert!(stencil.is_nine_point());
+        assert!(!Stencil::five_point(4.0, -1.0).is_nine_point());
+        // 3 x 2 grid
+        let a = stencil.to_matrix(3, 2);
+        assert_eq!(a.get(0, 0), 4.0);
+        assert_eq!(a.get(0, 1), -1.0);
+        assert_eq!(a.get(0, 4), -0.5);
+        assert_eq!(a.get(2, 3), 0.0);
+        assert_eq!(a.get(4, 0), -0.5);
+        assert_eq!(a.entries.len(), 4 * 4 + 2 * 6);
+    }
+}
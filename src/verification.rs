@@ -0,0 +1,219 @@
+//! Verification of the discretization against exact solutions.
+//!
+//! The eigenmodes `u(x, y, 0) = sin(kπx) sin(lπy)` of the unit square decay as
+//! `exp(-α π² (k² + l²) t)`. They are also eigenvectors of the discrete
+//! Laplacian, with a slightly smaller eigenvalue, so running the solver on one
+//! of them and comparing against
+//!
+//! - the exact solution measures the total error, which is dominated by the
+//!   spatial error when `dt` is small;
+//! - the semi-discrete solution, which decays with the discrete eigenvalue,
+//!   measures the time stepping error alone.
+//!
+//! Halving `h` or `dt` should divide both errors by four.
+use std::{f64::consts::PI, io};
+
+use crate::{
+    field::Field, heat_equation::HeatEquation, initial_condition::analytic::Analytic,
+    solver::Solver,
+};
+
+/// The eigenmode `sin(kπx) sin(lπy)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Eigenmode {
+    pub k: u32,
+    pub l: u32,
+}
+
+impl Eigenmode {
+    /// The eigenmode sampled on an `n x n` grid, as an initial condition.
+    pub fn initial_condition(&self, n: usize) -> Vec<f32> {
+        let mut data = vec![0.0; n * n];
+        Analytic::Eigenmode {
+            k: self.k,
+            l: self.l,
+            amplitude: 1.0,
+        }
+        .add_to(n, &mut data);
+        data
+    }
+
+    /// Decay rate of the continuous problem, `α π² (k² + l²)`.
+    pub fn decay_rate(&self, alpha: f64) -> f64 {
+        alpha * PI * PI * (self.k.pow(2) + self.l.pow(2)) as f64
+    }
+
+    /// Decay rate of the eigenvector of the five-point Laplacian on an `n x n` grid,
+    /// `α 4/h² (sin²(kπh/2) + sin²(lπh/2))`.
+    pub fn discrete_decay_rate(&self, alpha: f64, n: usize) -> f64 {
+        let h = HeatEquation::grid_spacing(n);
+        let s = |mode: u32| (mode as f64 * PI * h / 2.0).sin().powi(2);
+        alpha * 4.0 / (h * h) * (s(self.k) + s(self.l))
+    }
+
+    fn sample(&self, n: usize, amplitude: f64) -> Vec<f64> {
+        let mut data = Vec::with_capacity(n * n);
+        for j in 0..n {
+            let y = HeatEquation::grid_coordinate(j, n);
+            for i in 0..n {
+                let x = HeatEquation::grid_coordinate(i, n);
+                data.push(
+                    amplitude * (self.k as f64 * PI * x).sin() * (self.l as f64 * PI * y).sin(),
+                );
+            }
+        }
+        data
+    }
+
+    /// Exact solution of the heat equation at time `t`, sampled on the grid.
+    pub fn exact(&self, n: usize, alpha: f64, t: f64) -> Vec<f64> {
+        self.sample(n, (-self.decay_rate(alpha) * t).exp())
+    }
+
+    /// Exact solution of the spatially discretized problem at time `t`.
+    pub fn semi_discrete(&self, n: usize, alpha: f64, t: f64) -> Vec<f64> {
+        self.sample(n, (-self.discrete_decay_rate(alpha, n) * t).exp())
+    }
+}
+
+/// Discrete norms of the difference between a solution and a reference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorNorms {
+    /// `sqrt(h² Σ e²)`, which approximates the L² norm over the unit square.
+    pub l2: f64,
+    pub linf: f64,
+}
+
+impl ErrorNorms {
+    pub fn new(field: &Field, reference: &[f64]) -> Self {
+        assert_eq!(field.data.len(), reference.len());
+        assert_eq!(field.width, field.height, "the grid must be square");
+        let h = HeatEquation::grid_spacing(field.width);
+        let (mut sum, mut linf) = (0.0, 0.0f64);
+        for (&u, &r) in field.data.iter().zip(reference) {
+            let e = u as f64 - r;
+            sum += e * e;
+            linf = linf.max(e.abs());
+        }
+        Self {
+            l2: (h * h * sum).sqrt(),
+            linf,
+        }
+    }
+}
+
+/// Which solution the error is measured against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    Exact,
+    SemiDiscrete,
+}
+
+/// Runs `solver`, which must have been created with `mode` as its initial
+/// condition, for `steps` time steps and returns its error, or the failure
+/// to read its solution back.
+pub fn eigenmode_error<S: Solver>(
+    solver: &mut S,
+    ctx: S::Context<'_>,
+    mode: Eigenmode,
+    alpha: f32,
+    dt: f32,
+    steps: usize,
+    reference: Reference,
+) -> io::Result<ErrorNorms> {
+    for _ in 0..steps {
+        solver.compute_step(ctx);
+    }
+    let (n, alpha) = (solver.n(), alpha as f64);
+    // computed in double precision, unlike `Solver::time`
+    let t = solver.iteration() as f64 * dt as f64;
+    let reference = match reference {
+        Reference::Exact => mode.exact(n, alpha, t),
+        Reference::SemiDiscrete => mode.semi_discrete(n, alpha, t),
+    };
+    Ok(ErrorNorms::new(&solver.read_field(ctx)?, &reference))
+}
+
+/// Observed orders of convergence `log(e₁/e₂) / log(s₁/s₂)` between successive
+/// pairs of `(step size, error)`.
+pub fn observed_orders(errors: &[(f64, f64)]) -> Vec<f64> {
+    errors
+        .windows(2)
+        .map(|w| (w[0].1 / w[1].1).ln() / (w[0].0 / w[1].0).ln())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::heat_equation::HeatEquation as CpuHeatEquation;
+
+    const ALPHA: f32 = 1.0;
+    const MODE: Eigenmode = Eigenmode { k: 1, l: 2 };
+
+    fn cpu_error(n: usize, dt: f32, end_time: f32, reference: Reference) -> ErrorNorms {
+        let steps = (end_time / dt).round() as usize;
+        let mut solver = CpuHeatEquation::new(ALPHA, n, dt, &MODE.initial_condition(n));
+        eigenmode_error(&mut solver, (), MODE, ALPHA, dt, steps, reference).unwrap()
+    }
+
+    fn assert_second_order(errors: &[(f64, f64)]) {
+        for order in observed_orders(errors) {
+            assert!(
+                (1.8..2.2).contains(&order),
+                "order {order}, errors {errors:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn eigenmode_is_a_discrete_eigenvector() {
+        let n = 12;
+        let mode = MODE.initial_condition(n);
+        let a = HeatEquation::a_matrix(ALPHA, n, 1e-3);
+        let mut am = vec![0.0; n * n];
+        let mode: Vec<f64> = mode.iter().map(|&v| v as f64).collect();
+        crate::cpu::kernels::spmv(&a, &mode, &mut am);
+        let lambda = 1.0 + MODE.discrete_decay_rate(ALPHA as f64, n) * 1e-3 / 2.0;
+        for (am, m) in am.iter().zip(mode.iter()) {
+            assert!((am - lambda * m).abs() < 1e-6, "{am} != {}", lambda * m);
+        }
+    }
+
+    #[test]
+    fn spatial_order() {
+        // dt is small enough for the time stepping error to be negligible
+        let errors: Vec<_> = [7, 15, 31, 63]
+            .into_iter()
+            .map(|n| {
+                let error = cpu_error(n, 1e-4, 0.01, Reference::Exact);
+                (HeatEquation::grid_spacing(n), error)
+            })
+            .collect();
+        let l2: Vec<_> = errors.iter().map(|(h, e)| (*h, e.l2)).collect();
+        let linf: Vec<_> = errors.iter().map(|(h, e)| (*h, e.linf)).collect();
+        assert_second_order(&l2);
+        assert_second_order(&linf);
+    }
+
+    #[test]
+    fn temporal_order() {
+        let errors: Vec<_> = [4e-3, 2e-3, 1e-3, 5e-4]
+            .into_iter()
+            .map(|dt| {
+                let error = cpu_error(15, dt, 0.04, Reference::SemiDiscrete);
+                (dt as f64, error)
+            })
+            .collect();
+        let l2: Vec<_> = errors.iter().map(|(dt, e)| (*dt, e.l2)).collect();
+        let linf: Vec<_> = errors.iter().map(|(dt, e)| (*dt, e.linf)).collect();
+        assert_second_order(&l2);
+        assert_second_order(&linf);
+    }
+
+    #[test]
+    fn orders_of_known_sequences() {
+        let orders = observed_orders(&[(1.0, 1.0), (0.5, 0.25), (0.25, 0.0625)]);
+        assert_eq!(orders, [2.0, 2.0]);
+    }
+}